@@ -0,0 +1,277 @@
+use crate::geometry::{Matrix, Vec3f};
+use crate::model::Model;
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl BoundingBox {
+    pub fn center(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3f {
+        self.max - self.min
+    }
+
+    /// Length of the longest side.
+    pub fn max_extent(&self) -> f32 {
+        let s = self.size();
+        s.x.max(s.y).max(s.z)
+    }
+
+    pub fn contains(&self, p: Vec3f) -> bool {
+        (0..3).all(|i| self.min.get(i) <= p.get(i) && p.get(i) <= self.max.get(i))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3f,
+    pub radius: f32,
+}
+
+impl Model {
+    /// Box around all vertices, `None` for a model without any.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let first = *self.verts.first()?;
+        let mut bbox = BoundingBox {
+            min: first,
+            max: first,
+        };
+        for v in &self.verts {
+            bbox.min = Vec3f::new(
+                bbox.min.x.min(v.x),
+                bbox.min.y.min(v.y),
+                bbox.min.z.min(v.z),
+            );
+            bbox.max = Vec3f::new(
+                bbox.max.x.max(v.x),
+                bbox.max.y.max(v.y),
+                bbox.max.z.max(v.z),
+            );
+        }
+        Some(bbox)
+    }
+
+    /// Sphere around all vertices after Ritter: start from two far apart
+    /// points and grow the sphere over every vertex outside it. Not minimal,
+    /// but within a few percent of it.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        let first = *self.verts.first()?;
+        let farthest = |from: Vec3f| {
+            self.verts
+                .iter()
+                .copied()
+                .max_by(|a, b| (*a - from).norm().total_cmp(&(*b - from).norm()))
+                .unwrap_or(from)
+        };
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut sphere = BoundingSphere {
+            center: (a + b) * 0.5,
+            radius: (b - a).norm() * 0.5,
+        };
+        for &v in &self.verts {
+            let d = (v - sphere.center).norm();
+            if d > sphere.radius {
+                let radius = (sphere.radius + d) * 0.5;
+                sphere.center =
+                    sphere.center + (v - sphere.center) * ((radius - sphere.radius) / d);
+                sphere.radius = radius;
+            }
+        }
+        Some(sphere)
+    }
+
+    /// Area-weighted centroid of the surface, so densely tessellated parts
+    /// do not pull it over. Falls back to the mean vertex for point clouds
+    /// and models without area.
+    pub fn centroid(&self) -> Option<Vec3f> {
+        if self.verts.is_empty() {
+            return None;
+        }
+        let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+        let mut area = 0.0;
+        for face in &self.faces {
+            let p: Vec<Vec3f> = face.iter().map(|c| self.verts[c.x as usize]).collect();
+            let a = ((p[1] - p[0]) ^ (p[2] - p[0])).norm() * 0.5;
+            sum = sum + (p[0] + p[1] + p[2]) * (a / 3.0);
+            area += a;
+        }
+        if area > 0.0 {
+            return Some(sum * (1.0 / area));
+        }
+        let sum = self
+            .verts
+            .iter()
+            .fold(Vec3f::new(0.0, 0.0, 0.0), |acc, &v| acc + v);
+        Some(sum * (1.0 / self.verts.len() as f32))
+    }
+
+    /// Centres the bounding box on the origin and scales the model so its
+    /// longest side spans -1..1. Returns the matrix that was applied.
+    pub fn normalize_to_unit_cube(&mut self) -> Matrix {
+        match self.bounding_box() {
+            Some(bbox) if bbox.max_extent() > 0.0 => {
+                self.fit_uniform(bbox.center(), 2.0 / bbox.max_extent())
+            }
+            Some(bbox) => self.fit_uniform(bbox.center(), 1.0),
+            None => Matrix::identity(4),
+        }
+    }
+
+    /// Centres the bounding sphere on the origin and scales it to radius 1.
+    /// Unlike the unit cube this keeps every point within 1 of the origin
+    /// in any view direction, which is what framing a camera needs.
+    pub fn normalize_to_unit_sphere(&mut self) -> Matrix {
+        match self.bounding_sphere() {
+            Some(s) if s.radius > 0.0 => self.fit_uniform(s.center, 1.0 / s.radius),
+            Some(s) => self.fit_uniform(s.center, 1.0),
+            None => Matrix::identity(4),
+        }
+    }
+
+    /// Maps every vertex v onto (v - center) * scale. A uniform scale keeps
+    /// normals and tangents as they are.
+    fn fit_uniform(&mut self, center: Vec3f, scale: f32) -> Matrix {
+        for v in &mut self.verts {
+            *v = (*v - center) * scale;
+        }
+        let mut m = Matrix::zoom(scale);
+        m[0][3] = -center.x * scale;
+        m[1][3] = -center.y * scale;
+        m[2][3] = -center.z * scale;
+        m
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec3i;
+
+    /// A large triangle and a far smaller one off to the side.
+    fn two_triangles() -> Model {
+        let verts = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(4.0, 0.0, 0.0),
+            Vec3f::new(0.0, 4.0, 2.0),
+            Vec3f::new(10.0, 0.0, 0.0),
+            Vec3f::new(11.0, 0.0, 0.0),
+            Vec3f::new(10.0, 1.0, 0.0),
+        ];
+        let tri = |a, b, c| [a, b, c].map(|v| Vec3i::new(v, -1, -1)).to_vec();
+        Model::from_triangles(
+            verts,
+            Vec::new(),
+            Vec::new(),
+            vec![tri(0, 1, 2), tri(3, 4, 5)],
+        )
+    }
+
+    /// Points scattered over an off-centre, stretched region.
+    fn cloud() -> Model {
+        let verts = (0..200)
+            .map(|i| {
+                let t = i as f32;
+                Vec3f::new(
+                    (t * 1.7).sin() * 3.0 + 2.0,
+                    (t * 2.3).cos() * 5.0 - 1.0,
+                    (t * 0.9).sin() * t.cos() * 7.0 + 4.0,
+                )
+            })
+            .collect();
+        Model::from_triangles(verts, Vec::new(), Vec::new(), Vec::new())
+    }
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn box_and_centroid() {
+        let model = two_triangles();
+        let bbox = model.bounding_box().unwrap();
+        assert_eq!(bbox.min, Vec3f::new(0.0, 0.0, 0.0));
+        assert_eq!(bbox.max, Vec3f::new(11.0, 4.0, 2.0));
+        assert_eq!(bbox.center(), Vec3f::new(5.5, 2.0, 1.0));
+        assert_eq!(bbox.max_extent(), 11.0);
+
+        // weighted by area, the large triangle dominates
+        let big = Vec3f::new(4.0, 4.0, 2.0) * (1.0 / 3.0);
+        let small = Vec3f::new(31.0, 1.0, 0.0) * (1.0 / 3.0);
+        let a = 4.0 * 20f32.sqrt() * 0.5;
+        let expected = (big * a + small * 0.5) * (1.0 / (a + 0.5));
+        assert!(close(model.centroid().unwrap(), expected));
+
+        // without faces every vertex counts the same
+        let points = Model::from_triangles(
+            vec![Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(2.0, 4.0, 6.0)],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        );
+        assert_eq!(points.centroid(), Some(Vec3f::new(1.0, 2.0, 3.0)));
+
+        let empty = Model::from_triangles(Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        assert!(empty.bounding_box().is_none());
+        assert!(empty.bounding_sphere().is_none());
+        assert!(empty.centroid().is_none());
+    }
+
+    #[test]
+    fn sphere_contains_every_vertex() {
+        for model in [two_triangles(), cloud()] {
+            let sphere = model.bounding_sphere().unwrap();
+            for i in 0..model.nverts() {
+                let d = (model.vert(i) - sphere.center).norm();
+                assert!(d <= sphere.radius * (1.0 + 1e-5), "vertex {}", i);
+            }
+            // no larger than the sphere through the box corners
+            let bbox = model.bounding_box().unwrap();
+            assert!(sphere.radius <= bbox.size().norm() * 0.5);
+        }
+    }
+
+    #[test]
+    fn normalizes_into_the_view_volume() {
+        let inside = |model: &Model| {
+            (0..model.nverts()).all(|i| (0..3).all(|k| model.vert(i).get(k).abs() <= 1.0 + 1e-5))
+        };
+
+        for original in [two_triangles(), cloud()] {
+            let mut cube = original.clone();
+            let m = cube.normalize_to_unit_cube();
+            assert!(inside(&cube));
+            let bbox = cube.bounding_box().unwrap();
+            assert!(close(bbox.center(), Vec3f::new(0.0, 0.0, 0.0)));
+            assert!((bbox.max_extent() - 2.0).abs() < 1e-5);
+            // the returned matrix is the one that was applied
+            for i in 0..original.nverts() {
+                assert!(close(m.transform_point(original.vert(i)), cube.vert(i)));
+            }
+
+            let mut sphere = original.clone();
+            sphere.normalize_to_unit_sphere();
+            assert!(inside(&sphere));
+            let max = (0..sphere.nverts())
+                .map(|i| sphere.vert(i).norm())
+                .fold(0.0, f32::max);
+            assert!(max <= 1.0 + 1e-5);
+        }
+
+        // a single point is only moved to the origin
+        let mut point = Model::from_triangles(
+            vec![Vec3f::new(3.0, -2.0, 1.0)],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        );
+        point.normalize_to_unit_cube();
+        assert_eq!(point.vert(0), Vec3f::new(0.0, 0.0, 0.0));
+    }
+}
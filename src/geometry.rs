@@ -236,6 +236,33 @@ impl Mul<f32> for Vec3i {
     }
 }
 
+impl Vec3f {
+    /// Unit vector in the same direction, zero for a zero vector.
+    pub fn normalized(self) -> Vec3f {
+        let n = self.norm();
+        if n > 0.0 {
+            self * (1.0 / n)
+        } else {
+            self
+        }
+    }
+
+    /// Angle between the two vectors in radians, zero if either is zero.
+    pub fn angle(self, other: Vec3f) -> f32 {
+        let (na, nb) = (self.norm(), other.norm());
+        if na == 0.0 || nb == 0.0 {
+            return 0.0;
+        }
+        ((self * other) / (na * nb)).clamp(-1.0, 1.0).acos()
+    }
+
+    /// Normal of the triangle `a`, `b`, `c` following its counter-clockwise
+    /// winding, unnormalised: its length is twice the triangle area.
+    pub fn triangle_normal(a: Vec3f, b: Vec3f, c: Vec3f) -> Vec3f {
+        (b - a) ^ (c - a)
+    }
+}
+
 impl<T> Index<usize> for Vec3<T> {
     type Output = T;
     fn index(&self, i: usize) -> &T {
@@ -268,10 +295,95 @@ where
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec4<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+impl<T> Vec4<T>
+where
+    T: Copy,
+{
+    pub fn new(x: T, y: T, z: T, w: T) -> Self {
+        Vec4 { x, y, z, w }
+    }
+
+    pub fn from_vec3(v: Vec3<T>, w: T) -> Self {
+        Vec4::new(v.x, v.y, v.z, w)
+    }
+
+    pub fn xyz(&self) -> Vec3<T> {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+impl<T> Add for Vec4<T>
+where
+    T: Copy + Add<Output = T>,
+{
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Vec4::new(
+            self.x + other.x,
+            self.y + other.y,
+            self.z + other.z,
+            self.w + other.w,
+        )
+    }
+}
+
+impl<T> Mul<T> for Vec4<T>
+where
+    T: Copy + Mul<Output = T>,
+{
+    type Output = Vec4<T>;
+    fn mul(self, s: T) -> Vec4<T> {
+        Vec4::new(self.x * s, self.y * s, self.z * s, self.w * s)
+    }
+}
+
+impl<T> Index<usize> for Vec4<T> {
+    type Output = T;
+    fn index(&self, i: usize) -> &T {
+        match i {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            3 => &self.w,
+            _ => panic!("dimension out of range"),
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for Vec4<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            3 => &mut self.w,
+            _ => panic!("dimension out of range"),
+        }
+    }
+}
+
+impl<T> fmt::Display for Vec4<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}, {}, {})", self.x, self.y, self.z, self.w)
+    }
+}
+
 pub type Vec2f = Vec2<f32>;
 pub type Vec2i = Vec2<i32>;
 pub type Vec3f = Vec3<f32>;
 pub type Vec3i = Vec3<i32>;
+pub type Vec4f = Vec4<f32>;
 
 impl From<Vec3<f32>> for Vec3<i32> {
     fn from(v: Vec3<f32>) -> Self {
@@ -283,6 +395,7 @@ impl From<Vec3<f32>> for Vec3<i32> {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Matrix {
     m: Vec<Vec<f32>>,
     rows: usize,
@@ -292,24 +405,29 @@ pub struct Matrix {
 impl Matrix {
     pub fn new(rows: usize, cols: usize) -> Self {
         Matrix {
-            cols: cols,
-            rows: rows,
+            cols,
+            rows,
             m: vec![vec![0.0; cols]; rows],
         }
     }
 
     pub fn new_from_viewport(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Matrix::new_from_viewport_depth(x, y, w, h, 255)
+    }
+
+    /// Viewport that maps z from [-1, 1] onto [0, depth].
+    pub fn new_from_viewport_depth(x: usize, y: usize, w: usize, h: usize, depth: u32) -> Self {
         let mut m = Matrix::identity(4);
 
         m[0][3] = x as f32 + w as f32 / 2.0;
         m[1][3] = y as f32 + h as f32 / 2.0;
-        m[2][3] = 255.0 / 2.0;
+        m[2][3] = depth as f32 / 2.0;
 
         m[0][0] = w as f32 / 2.0;
         m[1][1] = h as f32 / 2.0;
-        m[2][2] = 255.0 / 2.0;
+        m[2][2] = depth as f32 / 2.0;
 
-        return m;
+        m
     }
 
     pub fn new_from_vector(v: Vec3f) -> Self {
@@ -318,7 +436,7 @@ impl Matrix {
         m[1][0] = v.y;
         m[2][0] = v.z;
         m[3][0] = 1.0;
-        return m;
+        m
     }
 
     pub fn nrows(&self) -> usize {
@@ -350,11 +468,121 @@ impl Matrix {
     }
 
     pub fn to_vector(&self) -> Vec3f {
-        return Vec3f {
+        Vec3f {
             x: self[0][0] / self[3][0],
             y: self[1][0] / self[3][0],
             z: self[2][0] / self[3][0],
-        };
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut result = Matrix::new(self.cols, self.rows);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                result[j][i] = self[i][j];
+            }
+        }
+        result
+    }
+
+    /// Inverse of a square matrix by Gauss-Jordan elimination, `None` if it
+    /// is singular.
+    pub fn inverse(&self) -> Option<Matrix> {
+        assert_eq!(self.rows, self.cols, "only square matrices have an inverse");
+        let n = self.rows;
+        let mut a = self.clone();
+        let mut result = Matrix::identity(n);
+        // pivots are judged against the largest entry, so scaling a matrix
+        // up or down does not change whether it counts as singular
+        let magnitude = self.m.iter().flatten().fold(0.0f32, |m, v| m.max(v.abs()));
+        let tolerance = magnitude * n as f32 * f32::EPSILON;
+        for col in 0..n {
+            // partial pivoting
+            let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() <= tolerance {
+                return None;
+            }
+            a.m.swap(col, pivot);
+            result.m.swap(col, pivot);
+            let d = a[col][col];
+            for j in 0..n {
+                a[col][j] /= d;
+                result[col][j] /= d;
+            }
+            for i in 0..n {
+                if i == col {
+                    continue;
+                }
+                let f = a[i][col];
+                for j in 0..n {
+                    a[i][j] -= f * a[col][j];
+                    result[i][j] -= f * result[col][j];
+                }
+            }
+        }
+        Some(result)
+    }
+
+    pub fn translation(v: Vec3f) -> Matrix {
+        let mut m = Matrix::identity(4);
+        m[0][3] = v.x;
+        m[1][3] = v.y;
+        m[2][3] = v.z;
+        m
+    }
+
+    /// Scale along each axis, negative factors mirror.
+    pub fn scaling(v: Vec3f) -> Matrix {
+        let mut m = Matrix::identity(4);
+        m[0][0] = v.x;
+        m[1][1] = v.y;
+        m[2][2] = v.z;
+        m
+    }
+
+    /// Rotation by `degrees` around `axis`, counter-clockwise when the axis
+    /// points at the viewer.
+    pub fn rotation(axis: Vec3f, degrees: f32) -> Matrix {
+        let mut a = axis;
+        if a.norm() > 0.0 {
+            a.normalize();
+        }
+        let (s, c) = degrees.to_radians().sin_cos();
+        let t = 1.0 - c;
+        let mut m = Matrix::identity(4);
+        m[0][0] = t * a.x * a.x + c;
+        m[0][1] = t * a.x * a.y - s * a.z;
+        m[0][2] = t * a.x * a.z + s * a.y;
+        m[1][0] = t * a.x * a.y + s * a.z;
+        m[1][1] = t * a.y * a.y + c;
+        m[1][2] = t * a.y * a.z - s * a.x;
+        m[2][0] = t * a.x * a.z - s * a.y;
+        m[2][1] = t * a.y * a.z + s * a.x;
+        m[2][2] = t * a.z * a.z + c;
+        m
+    }
+
+    /// Applies a 4x4 matrix to a point, with the perspective divide.
+    pub fn transform_point(&self, p: Vec3f) -> Vec3f {
+        (self * Matrix::new_from_vector(p)).to_vector()
+    }
+
+    /// Applies the upper 3x3 part, for directions.
+    pub fn transform_vector(&self, v: Vec3f) -> Vec3f {
+        let m = self;
+        Vec3f::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Determinant of the upper 3x3 part, negative for mirroring transforms.
+    pub fn determinant3(&self) -> f32 {
+        let m = self;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
     }
 
     pub fn zoom(factor: f32) -> Matrix {
@@ -362,7 +590,7 @@ impl Matrix {
         z[0][0] = factor;
         z[1][1] = factor;
         z[2][2] = factor;
-        return z;
+        z
     }
 }
 
@@ -429,10 +657,10 @@ impl Display for Matrix {
             }
         }
 
-        for i in 0..self.rows {
+        for row in &formatted {
             write!(f, "|")?;
-            for j in 0..self.cols {
-                write!(f, " {:>width$}", formatted[i][j], width = max_width)?;
+            for cell in row {
+                write!(f, " {:>width$}", cell, width = max_width)?;
             }
             writeln!(f, " |")?;
         }
@@ -440,3 +668,65 @@ impl Display for Matrix {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_rows(rows: &[&[f32]]) -> Matrix {
+        let mut m = Matrix::new(rows.len(), rows[0].len());
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &v) in row.iter().enumerate() {
+                m[i][j] = v;
+            }
+        }
+        m
+    }
+
+    fn assert_identity(m: &Matrix, tolerance: f32) {
+        for i in 0..m.nrows() {
+            for j in 0..m.ncols() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((m[i][j] - expected).abs() < tolerance, "{}", m);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = Matrix::translation(Vec3f::new(1.0, -2.0, 3.0))
+            * Matrix::rotation(Vec3f::new(1.0, 1.0, 0.0), 30.0)
+            * Matrix::scaling(Vec3f::new(2.0, 0.5, -3.0));
+        let inv = m.inverse().unwrap();
+        assert_identity(&(&m * &inv), 1e-5);
+        assert_identity(&(&inv * &m), 1e-5);
+
+        // needs a row swap, the first pivot is zero
+        let swap = from_rows(&[&[0.0, 1.0], &[2.0, 0.0]]);
+        assert_identity(&(&swap * swap.inverse().unwrap()), 1e-6);
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(Matrix::new(3, 3).inverse().is_none());
+        assert!(Matrix::scaling(Vec3f::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+        let dependent = from_rows(&[&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0], &[0.0, 1.0, 1.0]]);
+        assert!(dependent.inverse().is_none());
+        // singular up to rounding at a large scale
+        let nearly = from_rows(&[&[1e6, 2e6], &[1e6, 2e6 + 0.1]]);
+        assert!(nearly.inverse().is_none());
+    }
+
+    #[test]
+    fn tiny_matrices_are_invertible() {
+        let mut small = Matrix::identity(3);
+        for i in 0..3 {
+            small[i][i] = 1e-8;
+        }
+        let inv = small.inverse().unwrap();
+        assert!((inv[0][0] - 1e8).abs() < 1.0);
+        assert_identity(&(&small * &inv), 1e-5);
+    }
+}
@@ -0,0 +1,1096 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::geometry::{Matrix, Vec2f, Vec3f, Vec3i, Vec4f};
+use crate::json::Json;
+use crate::material::{Material, TextureMap};
+use crate::model::{FaceAttributes, FaceInfo, Model, ModelError, ParseMode, Submesh};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+const MODE_TRIANGLES: usize = 4;
+const MODE_TRIANGLE_STRIP: usize = 5;
+const MODE_TRIANGLE_FAN: usize = 6;
+
+// most elements an accessor without a buffer view may have, its zeros
+// are allocated before any sparse values are read
+const MAX_ZEROED: usize = 1 << 24;
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// URIs are percent-encoded, e.g. spaces in file names become `%20`.
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Local transform of a node, from `matrix` or from translation, rotation
+/// and scale.
+fn node_matrix(node: &Json) -> Matrix {
+    let mut m = Matrix::identity(4);
+    if let Some(a) = node.get("matrix").as_f32s().filter(|a| a.len() == 16) {
+        // column major
+        for r in 0..4 {
+            for c in 0..4 {
+                m[r][c] = a[c * 4 + r];
+            }
+        }
+        return m;
+    }
+    let t = node
+        .get("translation")
+        .as_f32s()
+        .filter(|a| a.len() == 3)
+        .unwrap_or(vec![0.0; 3]);
+    let q = node
+        .get("rotation")
+        .as_f32s()
+        .filter(|a| a.len() == 4)
+        .unwrap_or(vec![0.0, 0.0, 0.0, 1.0]);
+    let s = node
+        .get("scale")
+        .as_f32s()
+        .filter(|a| a.len() == 3)
+        .unwrap_or(vec![1.0; 3]);
+    let (x, y, z, w) = (q[0], q[1], q[2], q[3]);
+    let rotation = [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - z * w),
+            2.0 * (x * z + y * w),
+        ],
+        [
+            2.0 * (x * y + z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - x * w),
+        ],
+        [
+            2.0 * (x * z - y * w),
+            2.0 * (y * z + x * w),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ];
+    // T * R * S
+    for r in 0..3 {
+        for c in 0..3 {
+            m[r][c] = rotation[r][c] * s[c];
+        }
+        m[r][3] = t[r];
+    }
+    m
+}
+
+/// Value of one accessor component, normalized integers mapped onto 0..=1
+/// or -1..=1.
+fn component(b: &[u8], component_type: usize, normalized: bool) -> f32 {
+    match component_type {
+        5120 if normalized => (b[0] as i8 as f32 / 127.0).max(-1.0),
+        5120 => b[0] as i8 as f32,
+        5121 if normalized => b[0] as f32 / 255.0,
+        5121 => b[0] as f32,
+        5122 if normalized => (i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.0).max(-1.0),
+        5122 => i16::from_le_bytes([b[0], b[1]]) as f32,
+        5123 if normalized => u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0,
+        5123 => u16::from_le_bytes([b[0], b[1]]) as f32,
+        5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+        _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+    }
+}
+
+fn component_size(component_type: usize) -> Option<usize> {
+    match component_type {
+        5120 | 5121 => Some(1),
+        5122 | 5123 => Some(2),
+        5125 | 5126 => Some(4),
+        _ => None,
+    }
+}
+
+/// A parsed glTF file with all of its buffers loaded.
+struct Document {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+    dir: PathBuf,
+    file: String,
+    mode: ParseMode,
+}
+
+impl Document {
+    /// Fails in strict mode, warns and lets the caller skip the offending
+    /// part otherwise.
+    fn problem(&self, reason: String) -> Result<(), String> {
+        match self.mode {
+            ParseMode::Strict => Err(reason),
+            ParseMode::Lenient => {
+                eprintln!("warning: {}: {}", self.file, reason);
+                Ok(())
+            }
+        }
+    }
+
+    fn parse(filename: &str, data: &[u8], mode: ParseMode) -> Result<Self, String> {
+        let dir = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+        let (text, bin) = if data.starts_with(GLB_MAGIC) {
+            split_glb(data)?
+        } else {
+            (String::from_utf8_lossy(data).into_owned(), None)
+        };
+        let json = Json::parse(&text).map_err(|(line, e)| format!("line {}: {}", line, e))?;
+
+        let mut buffers = Vec::new();
+        for (i, buffer) in json.get("buffers").items().iter().enumerate() {
+            let bytes = match buffer.get("uri").as_str() {
+                None if i == 0 => bin
+                    .clone()
+                    .ok_or_else(|| String::from("buffer 0 has no uri and there is no GLB chunk"))?,
+                None => return Err(format!("buffer {} has no uri", i)),
+                Some(uri) if uri.starts_with("data:") => {
+                    let payload = uri.split_once(";base64,").map(|(_, p)| p);
+                    payload
+                        .and_then(decode_base64)
+                        .ok_or_else(|| format!("buffer {} has an invalid data uri", i))?
+                }
+                Some(uri) => {
+                    let path = dir.join(decode_uri(uri));
+                    fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?
+                }
+            };
+            let length = buffer.get("byteLength").as_usize().unwrap_or(bytes.len());
+            if bytes.len() < length {
+                return Err(format!(
+                    "buffer {} has {} bytes, byteLength is {}",
+                    i,
+                    bytes.len(),
+                    length
+                ));
+            }
+            buffers.push(bytes);
+        }
+        Ok(Document {
+            json,
+            buffers,
+            dir,
+            file: filename.to_string(),
+            mode,
+        })
+    }
+
+    /// Reads accessor `index` as floats, `ncomp` per element, with its sparse
+    /// values applied. Normalized integers are mapped onto 0..=1 or -1..=1.
+    fn accessor(&self, index: usize, ncomp: usize) -> Result<Vec<f32>, String> {
+        let acc = self.json.get("accessors").at(index);
+        let count = acc
+            .get("count")
+            .as_usize()
+            .ok_or_else(|| format!("accessor {} has no count", index))?;
+        let actual = match acc.get("type").as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            other => {
+                return Err(format!(
+                    "accessor {} has unsupported type {:?}",
+                    index, other
+                ))
+            }
+        };
+        if actual != ncomp {
+            return Err(format!(
+                "accessor {} has {} components, expected {}",
+                index, actual, ncomp
+            ));
+        }
+        let component_type = acc.get("componentType").as_usize().unwrap_or(0);
+        let size = component_size(component_type)
+            .ok_or_else(|| format!("accessor {} has component type {}", index, component_type))?;
+        let normalized = acc.get("normalized").as_bool().unwrap_or(false);
+        let too_large = || format!("accessor {} has too many elements", index);
+        let total = count.checked_mul(ncomp).ok_or_else(too_large)?;
+
+        let mut out = match acc.get("bufferView").as_usize() {
+            Some(view) => {
+                let stride = self
+                    .json
+                    .get("bufferViews")
+                    .at(view)
+                    .get("byteStride")
+                    .as_usize()
+                    .unwrap_or(ncomp * size);
+                let offset = acc.get("byteOffset").as_usize().unwrap_or(0);
+                let bytes = self.view_bytes(view, offset, stride, count, ncomp * size)?;
+                let mut out = Vec::with_capacity(total);
+                for i in 0..count {
+                    for c in 0..ncomp {
+                        let at = i * stride + c * size;
+                        out.push(component(&bytes[at..at + size], component_type, normalized));
+                    }
+                }
+                out
+            }
+            // no buffer view means all zeros, and nothing in the file
+            // bounds the count
+            None if count > MAX_ZEROED => return Err(too_large()),
+            None => vec![0.0; total],
+        };
+
+        let sparse = acc.get("sparse");
+        if !sparse.is_null() {
+            let n = sparse.get("count").as_usize().unwrap_or(0);
+            let bad = || format!("accessor {} has invalid sparse values", index);
+            let (indices, values) = (sparse.get("indices"), sparse.get("values"));
+            let index_type = indices.get("componentType").as_usize().unwrap_or(0);
+            let index_size = component_size(index_type)
+                .filter(|_| [5121, 5123, 5125].contains(&index_type))
+                .ok_or_else(bad)?;
+            let at = |part: &Json, stride: usize| {
+                let view = part.get("bufferView").as_usize().ok_or_else(bad)?;
+                let offset = part.get("byteOffset").as_usize().unwrap_or(0);
+                self.view_bytes(view, offset, stride, n, stride)
+            };
+            let index_bytes = at(indices, index_size)?;
+            let value_bytes = at(values, ncomp * size)?;
+            for i in 0..n {
+                let b = &index_bytes[i * index_size..(i + 1) * index_size];
+                let target = component(b, index_type, false) as usize;
+                if target >= count {
+                    return Err(bad());
+                }
+                for c in 0..ncomp {
+                    let v = &value_bytes[(i * ncomp + c) * size..][..size];
+                    out[target * ncomp + c] = component(v, component_type, normalized);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Bytes of `count` elements of `len` bytes, `stride` apart, starting
+    /// `offset` bytes into buffer view `index`. The returned slice starts at
+    /// the first element.
+    fn view_bytes(
+        &self,
+        index: usize,
+        offset: usize,
+        stride: usize,
+        count: usize,
+        len: usize,
+    ) -> Result<&[u8], String> {
+        let view = self.json.get("bufferViews").at(index);
+        let buffer = view
+            .get("buffer")
+            .as_usize()
+            .and_then(|b| self.buffers.get(b))
+            .ok_or_else(|| format!("buffer view {} has no buffer", index))?;
+        let past_end = || format!("buffer view {} is read past the end of its buffer", index);
+        let start = view
+            .get("byteOffset")
+            .as_usize()
+            .unwrap_or(0)
+            .checked_add(offset)
+            .ok_or_else(past_end)?;
+        let span = match count {
+            0 => Some(0),
+            _ => stride
+                .checked_mul(count - 1)
+                .and_then(|s| s.checked_add(len)),
+        };
+        let end = span
+            .and_then(|span| start.checked_add(span))
+            .ok_or_else(past_end)?;
+        buffer.get(start..end).ok_or_else(past_end)
+    }
+
+    /// Vertex indices of a primitive. Read as integers so large meshes do
+    /// not lose precision.
+    fn indices(&self, index: usize) -> Result<Vec<usize>, String> {
+        let acc = self.json.get("accessors").at(index);
+        match acc.get("componentType").as_usize() {
+            Some(5125) if acc.get("sparse").is_null() => {
+                // u32 does not fit into f32 exactly, read it directly
+                let view = self
+                    .json
+                    .get("bufferViews")
+                    .at(acc.get("bufferView").as_usize().unwrap_or(usize::MAX));
+                let buffer = view
+                    .get("buffer")
+                    .as_usize()
+                    .and_then(|b| self.buffers.get(b))
+                    .ok_or_else(|| format!("accessor {} has no buffer", index))?;
+                let past_end = || format!("accessor {} reads past the end of its buffer", index);
+                let count = acc.get("count").as_usize().unwrap_or(0);
+                let range = view
+                    .get("byteOffset")
+                    .as_usize()
+                    .unwrap_or(0)
+                    .checked_add(acc.get("byteOffset").as_usize().unwrap_or(0))
+                    .and_then(|offset| Some(offset..offset.checked_add(count.checked_mul(4)?)?))
+                    .ok_or_else(past_end)?;
+                let bytes = buffer.get(range).ok_or_else(past_end)?;
+                Ok(bytes
+                    .chunks_exact(4)
+                    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                    .collect())
+            }
+            _ => Ok(self
+                .accessor(index, 1)?
+                .into_iter()
+                .map(|v| v as usize)
+                .collect()),
+        }
+    }
+
+    /// Loads the image of texture `info`, the `slot` of material `material`.
+    /// Only external TGA files can be read, other images are reported and
+    /// left without pixels.
+    fn texture(
+        &self,
+        info: &Json,
+        material: &str,
+        slot: &str,
+    ) -> Result<Option<TextureMap>, String> {
+        let Some(texture) = info.get("index").as_usize() else {
+            return Ok(None);
+        };
+        let Some(source) = self
+            .json
+            .get("textures")
+            .at(texture)
+            .get("source")
+            .as_usize()
+        else {
+            return Ok(None);
+        };
+        let image = self.json.get("images").at(source);
+        let uri = image.get("uri").as_str();
+        if let Some(uri) = uri.filter(|u| !u.starts_with("data:")) {
+            if uri.to_ascii_lowercase().ends_with(".tga") {
+                return Ok(Some(TextureMap::load(&self.dir, &decode_uri(uri))));
+            }
+        }
+        // data uris are named by their index, the payload is no use in a message
+        let name = match (image.get("name").as_str(), uri) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(uri)) if !uri.starts_with("data:") => uri.to_string(),
+            _ => format!("image {}", source),
+        };
+        let kind = image
+            .get("mimeType")
+            .as_str()
+            .or_else(|| uri.and_then(|u| u.strip_prefix("data:")?.split(';').next()))
+            .unwrap_or("not TGA");
+        self.problem(format!(
+            "{} of material {} uses {} ({}), only TGA files are loaded, skipped",
+            slot, material, name, kind
+        ))?;
+        Ok(Some(TextureMap { name, image: None }))
+    }
+
+    fn materials(&self) -> Result<Vec<Material>, String> {
+        let mut materials = Vec::new();
+        for (i, m) in self.json.get("materials").items().iter().enumerate() {
+            let name = m
+                .get("name")
+                .as_str()
+                .map_or_else(|| format!("material{}", i), str::to_string);
+            let mut material = Material::new(&name);
+            let pbr = m.get("pbrMetallicRoughness");
+            if let Some(c) = pbr
+                .get("baseColorFactor")
+                .as_f32s()
+                .filter(|c| c.len() == 4)
+            {
+                material.diffuse = Vec3f::new(c[0], c[1], c[2]);
+                material.dissolve = c[3];
+            }
+            // the glTF defaults, unlike MTL files
+            material.metallic = pbr.get("metallicFactor").as_f32().unwrap_or(1.0);
+            material.roughness = pbr.get("roughnessFactor").as_f32().unwrap_or(1.0);
+            if let Some(e) = m.get("emissiveFactor").as_f32s().filter(|e| e.len() == 3) {
+                material.emissive = Vec3f::new(e[0], e[1], e[2]);
+            }
+            let texture = |info: &Json, slot: &str| self.texture(info, &name, slot);
+            material.diffuse_map = texture(pbr.get("baseColorTexture"), "baseColorTexture")?;
+            material.metallic_roughness_map = texture(
+                pbr.get("metallicRoughnessTexture"),
+                "metallicRoughnessTexture",
+            )?;
+            material.normal_map = texture(m.get("normalTexture"), "normalTexture")?;
+            material.occlusion_map = texture(m.get("occlusionTexture"), "occlusionTexture")?;
+            material.emissive_map = texture(m.get("emissiveTexture"), "emissiveTexture")?;
+            materials.push(material);
+        }
+        Ok(materials)
+    }
+
+    /// Every node of the default scene that has a mesh, with its world
+    /// transform. Nodes are visited once: a node reached again through a
+    /// second parent or a cycle, both invalid glTF, is skipped.
+    fn mesh_instances(&self) -> Result<Vec<(usize, Matrix)>, String> {
+        let nodes = self.json.get("nodes").items();
+        let scene = self.json.get("scene").as_usize().unwrap_or(0);
+        let roots: Vec<usize> = match self.json.get("scenes").at(scene) {
+            Json::Null => {
+                // no scene, every node that is nobody's child is a root
+                let children: Vec<usize> = nodes
+                    .iter()
+                    .flat_map(|n| n.get("children").items())
+                    .filter_map(Json::as_usize)
+                    .collect();
+                (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+            }
+            scene => scene
+                .get("nodes")
+                .items()
+                .iter()
+                .filter_map(Json::as_usize)
+                .collect(),
+        };
+
+        let mut out = Vec::new();
+        let mut visited = vec![false; nodes.len()];
+        let mut stack: Vec<(usize, Matrix)> = roots
+            .into_iter()
+            .rev()
+            .map(|r| (r, Matrix::identity(4)))
+            .collect();
+        while let Some((index, parent)) = stack.pop() {
+            match visited.get_mut(index) {
+                Some(seen) if !*seen => *seen = true,
+                Some(_) => {
+                    self.problem(format!("node {} has more than one parent, skipped", index))?;
+                    continue;
+                }
+                None => {
+                    self.problem(format!("node {} does not exist", index))?;
+                    continue;
+                }
+            }
+            let node = self.json.get("nodes").at(index);
+            let world = &parent * node_matrix(node);
+            for child in node.get("children").items().iter().rev() {
+                if let Some(child) = child.as_usize() {
+                    stack.push((child, world.clone()));
+                }
+            }
+            if node.get("mesh").as_usize().is_some() {
+                out.push((index, world));
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn split_glb(data: &[u8]) -> Result<(String, Option<Vec<u8>>), String> {
+    let u32_at = |at: usize| {
+        data.get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    if u32_at(4) != Some(2) {
+        return Err(String::from("only GLB version 2 is supported"));
+    }
+    let mut json = None;
+    let mut bin = None;
+    let mut at = 12;
+    while let (Some(length), Some(kind)) = (u32_at(at), u32_at(at + 4)) {
+        let start = at + 8;
+        let chunk = data
+            .get(start..start + length as usize)
+            .ok_or_else(|| String::from("GLB chunk runs past the end of the file"))?;
+        match kind {
+            CHUNK_JSON => json = Some(String::from_utf8_lossy(chunk).into_owned()),
+            CHUNK_BIN if bin.is_none() => bin = Some(chunk.to_vec()),
+            _ => {}
+        }
+        at = start + length as usize;
+    }
+    let json = json.ok_or_else(|| String::from("GLB file has no JSON chunk"))?;
+    Ok((json, bin))
+}
+
+/// Collects transformed primitives into the arrays of one `Model`.
+#[derive(Default)]
+struct Builder {
+    verts: Vec<Vec3f>,
+    norms: Vec<Vec3f>,
+    uv: Vec<Vec2f>,
+    faces: Vec<Vec<Vec3i>>,
+    face_info: Vec<FaceInfo>,
+    tangents: Vec<[Vec4f; 3]>,
+    missing_tangents: bool,
+    submeshes: Vec<Submesh>,
+}
+
+impl Builder {
+    fn add_mesh(
+        &mut self,
+        doc: &Document,
+        node_index: usize,
+        world: &Matrix,
+    ) -> Result<(), String> {
+        let node = doc.json.get("nodes").at(node_index);
+        let mesh_index = node.get("mesh").as_usize().unwrap_or(0);
+        let mesh = doc.json.get("meshes").at(mesh_index);
+        let object = node
+            .get("name")
+            .as_str()
+            .map_or_else(|| format!("node{}", node_index), str::to_string);
+        let name = mesh
+            .get("name")
+            .as_str()
+            .map_or_else(|| format!("mesh{}", mesh_index), str::to_string);
+        let normal_matrix = world.inverse().map(|m| m.transpose());
+        // mirroring transforms flip the winding and the tangent handedness
+        let mirrored = world.determinant3() < 0.0;
+
+        for primitive in mesh.get("primitives").items() {
+            let mode = primitive.get("mode").as_usize().unwrap_or(MODE_TRIANGLES);
+            if ![MODE_TRIANGLES, MODE_TRIANGLE_STRIP, MODE_TRIANGLE_FAN].contains(&mode) {
+                doc.problem(format!(
+                    "{}: skipping primitive with mode {}, only triangles are drawn",
+                    name, mode
+                ))?;
+                continue;
+            }
+            let attributes = primitive.get("attributes");
+            let position = attributes
+                .get("POSITION")
+                .as_usize()
+                .ok_or_else(|| format!("primitive of {} has no POSITION", name))?;
+            let positions = doc.accessor(position, 3)?;
+            let count = positions.len() / 3;
+            let read = |key: &str, ncomp: usize| -> Result<Option<Vec<f32>>, String> {
+                match attributes.get(key).as_usize() {
+                    Some(i) => {
+                        let values = doc.accessor(i, ncomp)?;
+                        if values.len() / ncomp != count {
+                            return Err(format!(
+                                "{} of {} has a different count than POSITION",
+                                key, name
+                            ));
+                        }
+                        Ok(Some(values))
+                    }
+                    None => Ok(None),
+                }
+            };
+            let normals = read("NORMAL", 3)?;
+            let texcoords = read("TEXCOORD_0", 2)?;
+            let tangents = read("TANGENT", 4)?;
+
+            let vbase = self.verts.len();
+            let nbase = self.norms.len();
+            let tbase = self.uv.len();
+            for p in positions.chunks_exact(3) {
+                self.verts
+                    .push(world.transform_point(Vec3f::new(p[0], p[1], p[2])));
+            }
+            if let Some(normals) = &normals {
+                for n in normals.chunks_exact(3) {
+                    let n = Vec3f::new(n[0], n[1], n[2]);
+                    let n = match &normal_matrix {
+                        Some(m) => m.transform_vector(n),
+                        None => n,
+                    };
+                    self.norms.push(n.normalized());
+                }
+            }
+            if let Some(texcoords) = &texcoords {
+                // glTF puts v = 0 at the top of the image, the renderer at the bottom
+                for t in texcoords.chunks_exact(2) {
+                    self.uv.push(Vec2f::new(t[0], 1.0 - t[1]));
+                }
+            }
+            let vertex_tangent = |i: usize| {
+                tangents.as_ref().map(|t| {
+                    let d = world
+                        .transform_vector(Vec3f::new(t[i * 4], t[i * 4 + 1], t[i * 4 + 2]))
+                        .normalized();
+                    let w = if mirrored {
+                        -t[i * 4 + 3]
+                    } else {
+                        t[i * 4 + 3]
+                    };
+                    Vec4f::from_vec3(d, w)
+                })
+            };
+
+            let indices = match primitive.get("indices").as_usize() {
+                Some(i) => doc.indices(i)?,
+                None => (0..count).collect(),
+            };
+            if let Some(&bad) = indices.iter().find(|&&i| i >= count) {
+                return Err(format!(
+                    "index {} of {} out of range, {} vertices",
+                    bad, name, count
+                ));
+            }
+            let triangles: Vec<[usize; 3]> = match mode {
+                MODE_TRIANGLE_STRIP => (2..indices.len())
+                    .map(|i| {
+                        // every other triangle of a strip is wound the other way
+                        if i % 2 == 0 {
+                            [indices[i - 2], indices[i - 1], indices[i]]
+                        } else {
+                            [indices[i - 1], indices[i - 2], indices[i]]
+                        }
+                    })
+                    .collect(),
+                MODE_TRIANGLE_FAN => (2..indices.len())
+                    .map(|i| [indices[0], indices[i - 1], indices[i]])
+                    .collect(),
+                _ => indices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect(),
+            };
+
+            let mut material = primitive.get("material").as_usize();
+            if let Some(m) = material.filter(|&m| m >= doc.json.get("materials").items().len()) {
+                doc.problem(format!(
+                    "primitive of {} uses material {} which does not exist, drawn without one",
+                    name, m
+                ))?;
+                material = None;
+            }
+            let first = self.faces.len();
+            for mut t in triangles {
+                if mirrored {
+                    t.swap(1, 2);
+                }
+                let corner = |i: usize| {
+                    Vec3i::new(
+                        (vbase + i) as i32,
+                        if texcoords.is_some() {
+                            (tbase + i) as i32
+                        } else {
+                            -1
+                        },
+                        if normals.is_some() {
+                            (nbase + i) as i32
+                        } else {
+                            -1
+                        },
+                    )
+                };
+                self.faces.push(t.iter().map(|&i| corner(i)).collect());
+                self.face_info.push(FaceInfo {
+                    source: self.face_info.len(),
+                    attributes: FaceAttributes {
+                        uv: texcoords.is_some(),
+                        normal: normals.is_some(),
+                    },
+                    material,
+                    smoothing: 0,
+                });
+                match (
+                    vertex_tangent(t[0]),
+                    vertex_tangent(t[1]),
+                    vertex_tangent(t[2]),
+                ) {
+                    (Some(a), Some(b), Some(c)) => self.tangents.push([a, b, c]),
+                    _ => self.missing_tangents = true,
+                }
+            }
+            if self.faces.len() > first {
+                self.submeshes.push(Submesh {
+                    name: name.clone(),
+                    object: object.clone(),
+                    faces: first..self.faces.len(),
+                    material,
+                    visible: true,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self, materials: Vec<Material>) -> Model {
+        let mut model = Model::from_triangles(self.verts, self.norms, self.uv, self.faces);
+        model.face_info = self.face_info;
+        model.submeshes = self.submeshes;
+        model.materials = materials;
+        // tangents are all or nothing, `compute_tangents` fills them otherwise
+        if !self.missing_tangents {
+            model.tangents = self.tangents;
+        }
+        model.print_summary();
+        model
+    }
+}
+
+fn open_document(filename: &str, mode: ParseMode) -> Result<Document, ModelError> {
+    let data = fs::read(filename).map_err(|source| ModelError::Io {
+        file: filename.to_string(),
+        source,
+    })?;
+    Document::parse(filename, &data, mode).map_err(|reason| ModelError::Parse {
+        file: filename.to_string(),
+        line: 0,
+        reason,
+    })
+}
+
+impl Model {
+    /// Loads a `.gltf` or `.glb` file with one model per mesh node of the
+    /// default scene. Node transforms are applied to the vertices, every
+    /// primitive becomes a submesh. Parts that can't be used, such as
+    /// point primitives or non-TGA textures, are skipped with a warning, or
+    /// fail the load in strict mode.
+    pub fn load_gltf(filename: &str, mode: ParseMode) -> Result<Vec<Model>, ModelError> {
+        let fail = |reason| ModelError::Parse {
+            file: filename.to_string(),
+            line: 0,
+            reason,
+        };
+        let doc = open_document(filename, mode)?;
+        let materials = doc.materials().map_err(fail)?;
+        let mut models = Vec::new();
+        for (node, world) in doc.mesh_instances().map_err(fail)? {
+            let mut builder = Builder::default();
+            builder.add_mesh(&doc, node, &world).map_err(fail)?;
+            models.push(builder.finish(materials.clone()));
+        }
+        Ok(models)
+    }
+
+    /// Like `load_gltf`, but the whole scene goes into a single model with
+    /// the node names as submesh objects.
+    pub fn load_gltf_scene(filename: &str, mode: ParseMode) -> Result<Model, ModelError> {
+        let fail = |reason| ModelError::Parse {
+            file: filename.to_string(),
+            line: 0,
+            reason,
+        };
+        let doc = open_document(filename, mode)?;
+        let materials = doc.materials().map_err(fail)?;
+        let mut builder = Builder::default();
+        for (node, world) in doc.mesh_instances().map_err(fail)? {
+            builder.add_mesh(&doc, node, &world).map_err(fail)?;
+        }
+        Ok(builder.finish(materials))
+    }
+
+    /// `load_gltf_scene` in lenient mode.
+    pub fn from_gltf(filename: &str) -> Result<Model, ModelError> {
+        Model::load_gltf_scene(filename, ParseMode::Lenient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let b = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A document whose only buffer holds `bin` as a data uri. `rest` holds
+    /// the other top level members.
+    fn document(bin: &[u8], rest: &str, mode: ParseMode) -> Result<Document, String> {
+        let json = format!(
+            r#"{{ "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}], {} }}"#,
+            bin.len(),
+            encode_base64(bin),
+            rest
+        );
+        Document::parse("test.gltf", json.as_bytes(), mode)
+    }
+
+    /// Loads a single mesh node over `bin`, `rest` holding the accessors,
+    /// buffer views, meshes and materials.
+    fn load(bin: &[u8], rest: &str, mode: ParseMode) -> Result<Model, String> {
+        let rest = format!(r#""nodes": [{{ "mesh": 0 }}], {}"#, rest);
+        let doc = document(bin, &rest, mode)?;
+        let mut builder = Builder::default();
+        builder.add_mesh(&doc, 0, &Matrix::identity(4))?;
+        Ok(builder.finish(doc.materials()?))
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(decode_base64("aGVs\nbG8h").unwrap(), b"hello!");
+        // url safe alphabet
+        assert_eq!(decode_base64("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode_base64("+/8=").unwrap(), [0xfb, 0xff]);
+        assert!(decode_base64("aGV*bG8=").is_none());
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode_base64(&encode_base64(&bytes)).unwrap(), bytes);
+        let doc = document(&bytes, r#""asset": {}"#, ParseMode::Strict).unwrap();
+        assert_eq!(doc.buffers, [bytes]);
+
+        let json = r#"{ "buffers": [{ "byteLength": 3, "uri": "data:application/octet-stream;base64,AA" }] }"#;
+        assert!(Document::parse("short.gltf", json.as_bytes(), ParseMode::Strict).is_err());
+        let json = r#"{ "buffers": [{ "uri": "data:text/plain,hello" }] }"#;
+        assert!(Document::parse("plain.gltf", json.as_bytes(), ParseMode::Strict).is_err());
+    }
+
+    #[test]
+    fn glb_chunks() {
+        let glb = |version: u32, chunks: &[(u32, &[u8])]| {
+            let mut data = b"glTF".to_vec();
+            data.extend(version.to_le_bytes());
+            data.extend(0u32.to_le_bytes());
+            for (kind, chunk) in chunks {
+                data.extend((chunk.len() as u32).to_le_bytes());
+                data.extend(kind.to_le_bytes());
+                data.extend_from_slice(chunk);
+            }
+            let length = data.len() as u32;
+            data[8..12].copy_from_slice(&length.to_le_bytes());
+            data
+        };
+        let json: &[u8] = br#"{ "buffers": [{ "byteLength": 4 }] }  "#;
+        let bin: &[u8] = &[1, 2, 3, 4];
+        // unknown chunks are skipped, the first BIN chunk is buffer 0
+        let data = glb(
+            2,
+            &[(CHUNK_JSON, json), (0x1234, b"????"), (CHUNK_BIN, bin)],
+        );
+        let doc = Document::parse("test.glb", &data, ParseMode::Strict).unwrap();
+        assert_eq!(doc.buffers, [bin]);
+        assert_eq!(
+            doc.json.get("buffers").at(0).get("byteLength").as_usize(),
+            Some(4)
+        );
+
+        let (text, none) = split_glb(&glb(2, &[(CHUNK_JSON, b"{}")])).unwrap();
+        assert_eq!((text.as_str(), none), ("{}", None));
+        assert!(split_glb(&glb(1, &[(CHUNK_JSON, b"{}")])).is_err());
+        assert!(split_glb(&glb(2, &[(CHUNK_BIN, bin)])).is_err());
+        let mut truncated = glb(2, &[(CHUNK_JSON, json), (CHUNK_BIN, bin)]);
+        truncated.pop();
+        assert!(split_glb(&truncated).is_err());
+        // buffer 0 has no uri and there is nothing to read it from
+        let data = glb(2, &[(CHUNK_JSON, json)]);
+        assert!(Document::parse("test.glb", &data, ParseMode::Strict).is_err());
+    }
+
+    #[test]
+    fn strided_and_normalized_accessors() {
+        // interleaved position and rgba8 colour, 16 bytes per vertex
+        let mut bin = Vec::new();
+        for (p, c) in [
+            ([1.0, 2.0, 3.0], [0, 255, 51, 255]),
+            ([4.0, 5.0, 6.0], [255, 0, 102, 0]),
+        ] {
+            bin.extend(floats(&p));
+            bin.extend(c);
+        }
+        bin.extend(i16::MIN.to_le_bytes());
+        bin.extend(i16::MAX.to_le_bytes());
+        bin.extend(0i16.to_le_bytes());
+        let rest = r#"
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 32, "byteStride": 16 },
+                { "buffer": 0, "byteOffset": 32, "byteLength": 6 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" },
+                { "bufferView": 0, "byteOffset": 12, "componentType": 5121, "normalized": true, "count": 2, "type": "VEC4" },
+                { "bufferView": 0, "byteOffset": 12, "componentType": 5121, "count": 2, "type": "VEC4" },
+                { "bufferView": 1, "componentType": 5122, "normalized": true, "count": 3, "type": "SCALAR" },
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }
+            ]"#;
+        let doc = document(&bin, rest, ParseMode::Strict).unwrap();
+        assert_eq!(doc.accessor(0, 3).unwrap(), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(
+            doc.accessor(1, 4).unwrap(),
+            [0.0, 1.0, 0.2, 1.0, 1.0, 0.0, 0.4, 0.0]
+        );
+        assert_eq!(
+            doc.accessor(2, 4).unwrap(),
+            [0.0, 255.0, 51.0, 255.0, 255.0, 0.0, 102.0, 0.0]
+        );
+        assert_eq!(doc.accessor(3, 1).unwrap(), [-1.0, 1.0, 0.0]);
+        // wrong type, and a third element past the end of the view
+        assert!(doc.accessor(0, 2).is_err());
+        assert!(doc.accessor(4, 3).is_err());
+    }
+
+    #[test]
+    fn sparse_accessors() {
+        let mut bin = Vec::new();
+        bin.extend(floats(&[1.0, 1.0, 2.0, 2.0, 3.0, 3.0]));
+        bin.extend([2u16, 0].iter().flat_map(|i| i.to_le_bytes()));
+        bin.extend(floats(&[9.0, 8.0, 7.0, 6.0]));
+        bin.extend([5u16].iter().flat_map(|i| i.to_le_bytes()));
+        let rest = r#"
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 24 },
+                { "buffer": 0, "byteOffset": 24, "byteLength": 4 },
+                { "buffer": 0, "byteOffset": 28, "byteLength": 16 },
+                { "buffer": 0, "byteOffset": 44, "byteLength": 2 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC2",
+                  "sparse": { "count": 2, "indices": { "bufferView": 1, "componentType": 5123 },
+                              "values": { "bufferView": 2 } } },
+                { "componentType": 5126, "count": 3, "type": "VEC2",
+                  "sparse": { "count": 2, "indices": { "bufferView": 1, "componentType": 5123 },
+                              "values": { "bufferView": 2 } } },
+                { "componentType": 5126, "count": 3, "type": "VEC2",
+                  "sparse": { "count": 1, "indices": { "bufferView": 3, "componentType": 5123 },
+                              "values": { "bufferView": 2 } } }
+            ]"#;
+        let doc = document(&bin, rest, ParseMode::Strict).unwrap();
+        assert_eq!(doc.accessor(0, 2).unwrap(), [7.0, 6.0, 2.0, 2.0, 9.0, 8.0]);
+        // without a buffer view the values start out as zeros
+        assert_eq!(doc.accessor(1, 2).unwrap(), [7.0, 6.0, 0.0, 0.0, 9.0, 8.0]);
+        // index 5 is past the three elements
+        assert!(doc.accessor(2, 2).is_err());
+    }
+
+    #[test]
+    fn huge_counts() {
+        let bin = floats(&[0.0; 6]);
+        let rest = r#"
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 24 },
+                { "buffer": 0, "byteLength": 24, "byteStride": 4611686018427387904 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 4611686018427387904, "type": "VEC3" },
+                { "componentType": 5126, "count": 4611686018427387904, "type": "SCALAR" },
+                { "componentType": 5126, "count": 100000000, "type": "SCALAR" },
+                { "bufferView": 0, "componentType": 5125, "count": 4611686018427387904, "type": "SCALAR" },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "SCALAR" }
+            ]"#;
+        let doc = document(&bin, rest, ParseMode::Strict).unwrap();
+        assert!(doc.accessor(0, 3).is_err());
+        assert!(doc.accessor(1, 1).is_err());
+        // no overflow, but too many zeros to allocate on the file's word
+        assert!(doc.accessor(2, 1).is_err());
+        assert!(doc.indices(3).is_err());
+        assert!(doc.accessor(4, 1).is_err());
+    }
+
+    /// Five vertices along a zigzag and `mode`, without indices.
+    fn zigzag(mode: usize) -> Model {
+        let bin = floats(&[
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 2.0, 0.0,
+        ]);
+        let rest = format!(
+            r#""bufferViews": [{{ "buffer": 0, "byteLength": 60 }}],
+            "accessors": [{{ "bufferView": 0, "componentType": 5126, "count": 5, "type": "VEC3" }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "mode": {} }}] }}]"#,
+            mode
+        );
+        load(&bin, &rest, ParseMode::Strict).unwrap()
+    }
+
+    fn triangles(model: &Model) -> Vec<[i32; 3]> {
+        (0..model.nfaces())
+            .map(|f| {
+                let face = model.face(f);
+                [face[0].x, face[1].x, face[2].x]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strip_and_fan_winding() {
+        let strip = zigzag(MODE_TRIANGLE_STRIP);
+        assert_eq!(triangles(&strip), [[0, 1, 2], [2, 1, 3], [2, 3, 4]]);
+        let fan = zigzag(MODE_TRIANGLE_FAN);
+        assert_eq!(triangles(&fan), [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        let list = zigzag(MODE_TRIANGLES);
+        assert_eq!(triangles(&list), [[0, 1, 2]]);
+        // every strip triangle faces the same way as the first
+        for f in 0..strip.nfaces() {
+            let v = |k: usize| strip.vert(strip.face(f)[k].x as usize);
+            assert!(Vec3f::triangle_normal(v(0), v(1), v(2)).z > 0.0);
+        }
+    }
+
+    #[test]
+    fn material_out_of_range() {
+        let bin = floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let rest = |material: usize| {
+            format!(
+                r#""bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+                "accessors": [{{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }}],
+                "materials": [{{ "name": "only" }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "material": {} }}] }}]"#,
+                material
+            )
+        };
+        let model = load(&bin, &rest(0), ParseMode::Strict).unwrap();
+        assert_eq!(model.material(0).map(|m| m.name.as_str()), Some("only"));
+
+        let model = load(&bin, &rest(3), ParseMode::Lenient).unwrap();
+        assert_eq!(model.face_material(0), None);
+        assert!(model.material(0).is_none());
+        assert_eq!(model.submeshes()[0].material, None);
+        assert!(load(&bin, &rest(3), ParseMode::Strict).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::model::Model;
+
+/// One directed side of an edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalfEdge {
+    /// Vertex the half-edge points to.
+    pub vertex: usize,
+    /// Face on its left, `None` on a boundary.
+    pub face: Option<usize>,
+    pub next: usize,
+    pub prev: usize,
+    /// The half-edge running the other way along the same edge.
+    pub twin: usize,
+}
+
+/// Half-edge adjacency of a model's faces. Vertices are the model's
+/// positions and faces its triangles; the half-edges of face `f` are
+/// `3f`, `3f + 1` and `3f + 2`, running from corner `k` to corner `k + 1`.
+/// Edges with only one face get a boundary half-edge, and boundary
+/// half-edges link up into the loops around holes.
+///
+/// Edges shared by more than two faces, or by two faces of opposite
+/// winding, keep their first pair of half-edges as twins and give every
+/// other face a boundary of its own; they are listed by
+/// `non_manifold_edges`.
+#[derive(Debug, Clone)]
+pub struct HalfEdgeMesh {
+    half_edges: Vec<HalfEdge>,
+    // an outgoing half-edge of every vertex, a boundary one if there is any
+    vertex_edge: Vec<Option<usize>>,
+    // half-edge of every (from, to), the first face's where there are several
+    lookup: HashMap<(usize, usize), usize>,
+    nfaces: usize,
+    non_manifold: Vec<(usize, usize)>,
+}
+
+impl HalfEdgeMesh {
+    pub fn half_edges(&self) -> &[HalfEdge] {
+        &self.half_edges
+    }
+
+    pub fn half_edge(&self, h: usize) -> &HalfEdge {
+        &self.half_edges[h]
+    }
+
+    pub fn nverts(&self) -> usize {
+        self.vertex_edge.len()
+    }
+
+    pub fn nfaces(&self) -> usize {
+        self.nfaces
+    }
+
+    /// Vertex the half-edge starts from.
+    pub fn origin(&self, h: usize) -> usize {
+        self.half_edges[self.half_edges[h].twin].vertex
+    }
+
+    pub fn is_boundary(&self, h: usize) -> bool {
+        self.half_edges[h].face.is_none()
+    }
+
+    /// Half-edge from `from` to `to`, whether or not a face lies on it.
+    pub fn find(&self, from: usize, to: usize) -> Option<usize> {
+        self.lookup.get(&(from, to)).copied()
+    }
+
+    /// Half-edges leaving `v`, turning around it from the boundary if it is
+    /// on one. Only the first fan of a vertex where several meet is visited.
+    pub fn outgoing(&self, v: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = self.vertex_edge[v];
+        let mut at = start;
+        let mut steps = 0;
+        std::iter::from_fn(move || {
+            let h = at?;
+            steps += 1;
+            let next = self.half_edges[self.half_edges[h].prev].twin;
+            at = (Some(next) != start && steps < self.half_edges.len()).then_some(next);
+            Some(h)
+        })
+    }
+
+    /// Vertices sharing an edge with `v`, in order around it.
+    pub fn one_ring(&self, v: usize) -> impl Iterator<Item = usize> + '_ {
+        self.outgoing(v).map(|h| self.half_edges[h].vertex)
+    }
+
+    /// Faces around `v`, in order around it.
+    pub fn vertex_faces(&self, v: usize) -> impl Iterator<Item = usize> + '_ {
+        self.outgoing(v).filter_map(|h| self.half_edges[h].face)
+    }
+
+    /// Number of edges at `v`.
+    pub fn valence(&self, v: usize) -> usize {
+        self.outgoing(v).count()
+    }
+
+    pub fn is_boundary_vertex(&self, v: usize) -> bool {
+        self.vertex_edge[v].is_some_and(|h| self.is_boundary(h))
+    }
+
+    /// Faces on either side of the edge between `a` and `b`: the one where
+    /// it runs from `a` to `b` and the one where it runs back.
+    pub fn edge_faces(&self, a: usize, b: usize) -> [Option<usize>; 2] {
+        let h = self.find(a, b);
+        let t = self.find(b, a);
+        [
+            h.and_then(|h| self.half_edges[h].face),
+            t.and_then(|t| self.half_edges[t].face),
+        ]
+    }
+
+    /// Faces across the three edges of `f`, from corner k to corner k + 1.
+    pub fn face_neighbours(&self, f: usize) -> [Option<usize>; 3] {
+        [0, 1, 2].map(|k| self.half_edges[self.half_edges[3 * f + k].twin].face)
+    }
+
+    /// Every hole as its vertices in order along the boundary half-edges,
+    /// which run against the winding of the faces next to them.
+    pub fn boundary_loops(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.half_edges.len()];
+        let mut loops = Vec::new();
+        for start in 0..self.half_edges.len() {
+            if seen[start] || !self.is_boundary(start) {
+                continue;
+            }
+            let mut vertices = Vec::new();
+            let mut h = start;
+            while !seen[h] {
+                seen[h] = true;
+                vertices.push(self.origin(h));
+                h = self.half_edges[h].next;
+            }
+            loops.push(vertices);
+        }
+        loops
+    }
+
+    /// Edges with more than two faces or with two faces of opposite
+    /// winding, as pairs of vertices.
+    pub fn non_manifold_edges(&self) -> &[(usize, usize)] {
+        &self.non_manifold
+    }
+}
+
+impl Model {
+    /// Builds the half-edge adjacency of the faces.
+    pub fn to_half_edges(&self) -> HalfEdgeMesh {
+        let nfaces = self.faces.len();
+        let mut half_edges = Vec::with_capacity(nfaces * 3);
+        let mut lookup = HashMap::new();
+        let mut non_manifold = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for k in 0..3 {
+                let h = 3 * f + k;
+                let (from, to) = (face[k].x as usize, face[(k + 1) % 3].x as usize);
+                half_edges.push(HalfEdge {
+                    vertex: to,
+                    face: Some(f),
+                    next: 3 * f + (k + 1) % 3,
+                    prev: 3 * f + (k + 2) % 3,
+                    twin: usize::MAX,
+                });
+                match lookup.entry((from, to)) {
+                    Entry::Vacant(e) => {
+                        e.insert(h);
+                    }
+                    Entry::Occupied(_) => {
+                        let edge = (from.min(to), from.max(to));
+                        if !non_manifold.contains(&edge) {
+                            non_manifold.push(edge);
+                        }
+                    }
+                }
+            }
+        }
+
+        // pair up the first face on each side of every edge, anything else
+        // gets a boundary half-edge
+        for h in 0..nfaces * 3 {
+            let (from, to) = (half_edges[half_edges[h].prev].vertex, half_edges[h].vertex);
+            let first = lookup[&(from, to)] == h;
+            match lookup.get(&(to, from)) {
+                Some(&t) if first && t != h && t < nfaces * 3 => half_edges[h].twin = t,
+                _ => {
+                    let b = half_edges.len();
+                    half_edges[h].twin = b;
+                    half_edges.push(HalfEdge {
+                        vertex: from,
+                        face: None,
+                        next: usize::MAX,
+                        prev: usize::MAX,
+                        twin: h,
+                    });
+                    if first {
+                        lookup.insert((to, from), b);
+                    }
+                }
+            }
+        }
+
+        // link each boundary half-edge to the next one, found by turning
+        // around its end vertex through the faces
+        for b in nfaces * 3..half_edges.len() {
+            let mut g = half_edges[b].twin;
+            for _ in 0..half_edges.len() {
+                g = half_edges[half_edges[g].prev].twin;
+                if half_edges[g].face.is_none() {
+                    break;
+                }
+            }
+            if half_edges[g].face.is_none() && half_edges[g].prev == usize::MAX {
+                half_edges[b].next = g;
+                half_edges[g].prev = b;
+            }
+        }
+        // whatever could not be linked, at non-manifold vertices, closes on itself
+        for (b, he) in half_edges.iter_mut().enumerate().skip(nfaces * 3) {
+            if he.next == usize::MAX {
+                he.next = b;
+            }
+            if he.prev == usize::MAX {
+                he.prev = b;
+            }
+        }
+
+        let mut vertex_edge = vec![None; self.verts.len()];
+        for (h, he) in half_edges.iter().enumerate() {
+            let from = half_edges[he.twin].vertex;
+            if vertex_edge[from].is_none() || he.face.is_none() {
+                vertex_edge[from] = Some(h);
+            }
+        }
+        HalfEdgeMesh {
+            half_edges,
+            vertex_edge,
+            lookup,
+            nfaces,
+            non_manifold,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::geometry::{Vec3f, Vec3i};
+
+    fn mesh(verts: usize, faces: &[[i32; 3]]) -> HalfEdgeMesh {
+        let verts = vec![Vec3f::new(0.0, 0.0, 0.0); verts];
+        let faces = faces
+            .iter()
+            .map(|f| f.iter().map(|&v| Vec3i::new(v, -1, -1)).collect())
+            .collect();
+        Model::from_triangles(verts, Vec::new(), Vec::new(), faces).to_half_edges()
+    }
+
+    /// Neighbours of `v` as read off the faces.
+    fn ring(faces: &[[i32; 3]], v: i32) -> BTreeSet<usize> {
+        faces
+            .iter()
+            .filter(|f| f.contains(&v))
+            .flat_map(|f| f.iter().filter(|&&w| w != v).map(|&w| w as usize))
+            .collect()
+    }
+
+    fn check_links(mesh: &HalfEdgeMesh) {
+        for (h, he) in mesh.half_edges().iter().enumerate() {
+            assert_eq!(mesh.half_edge(he.twin).twin, h);
+            assert_eq!(mesh.half_edge(he.next).prev, h);
+            assert_eq!(mesh.origin(he.next), he.vertex);
+        }
+    }
+
+    #[test]
+    fn closed_cube() {
+        let faces = [
+            [0, 2, 3],
+            [0, 3, 1],
+            [4, 5, 7],
+            [4, 7, 6],
+            [0, 1, 5],
+            [0, 5, 4],
+            [2, 6, 7],
+            [2, 7, 3],
+            [0, 4, 6],
+            [0, 6, 2],
+            [1, 3, 7],
+            [1, 7, 5],
+        ];
+        let mesh = mesh(8, &faces);
+        check_links(&mesh);
+        assert_eq!(mesh.half_edges().len(), 36);
+        assert!(mesh.boundary_loops().is_empty());
+        assert!(mesh.non_manifold_edges().is_empty());
+        for v in 0..8 {
+            assert!(!mesh.is_boundary_vertex(v));
+            let around: BTreeSet<usize> = mesh.one_ring(v).collect();
+            assert_eq!(around, ring(&faces, v as i32));
+            assert_eq!(mesh.valence(v), around.len());
+            assert_eq!(mesh.vertex_faces(v).count(), mesh.valence(v));
+        }
+        assert_eq!(mesh.edge_faces(0, 3), [Some(1), Some(0)]);
+        assert_eq!(mesh.face_neighbours(0), [Some(9), Some(7), Some(1)]);
+    }
+
+    #[test]
+    fn open_grid() {
+        // 3 x 3 vertices, numbered row by row
+        let mut faces = Vec::new();
+        for j in 0..2 {
+            for i in 0..2 {
+                let v = j * 3 + i;
+                faces.push([v, v + 1, v + 4]);
+                faces.push([v, v + 4, v + 3]);
+            }
+        }
+        let mesh = mesh(9, &faces);
+        check_links(&mesh);
+        assert!(mesh.non_manifold_edges().is_empty());
+        let loops = mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        // against the winding of the faces, which run counter-clockwise
+        let mut hole = loops[0].clone();
+        let start = hole.iter().position(|&v| v == 0).unwrap();
+        hole.rotate_left(start);
+        assert_eq!(hole, [0, 3, 6, 7, 8, 5, 2, 1]);
+
+        assert!(!mesh.is_boundary_vertex(4));
+        assert_eq!(mesh.valence(4), 6);
+        assert_eq!(mesh.vertex_faces(4).count(), 6);
+        // a boundary ring starts on a boundary edge, crosses the hole and has
+        // one face fewer than edges
+        assert!(mesh.is_boundary_vertex(0));
+        let around: Vec<usize> = mesh.one_ring(0).collect();
+        assert_eq!(around, [3, 1, 4]);
+        assert_eq!(mesh.vertex_faces(0).count(), 2);
+        assert_eq!(mesh.edge_faces(0, 1), [Some(0), None]);
+    }
+
+    #[test]
+    fn fin() {
+        // three faces on the edge 0-1
+        let mesh = mesh(5, &[[0, 1, 2], [1, 0, 3], [0, 1, 4]]);
+        check_links(&mesh);
+        assert_eq!(mesh.non_manifold_edges(), [(0, 1)]);
+        // the first two faces pair up, the third is cut loose along the edge
+        assert_eq!(mesh.edge_faces(0, 1), [Some(0), Some(1)]);
+        assert_eq!(mesh.face_neighbours(2)[0], None);
+        assert_eq!(mesh.face_neighbours(0)[0], Some(1));
+    }
+}
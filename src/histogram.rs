@@ -0,0 +1,323 @@
+use crate::tga::{AlphaMode, TGAColor, TGAImage};
+
+/// Luma below this is treated as black when judging exposure.
+pub const DARK_LEVEL: u8 = 16;
+/// Luma at or above this is treated as clipped highlight.
+pub const CLIP_LEVEL: u8 = 250;
+
+/// Per-channel 256 bin histogram. Channels are kept in the image storage
+/// order (b, g, r, a for colour images), plus a luma histogram.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    channels: Vec<[u64; 256]>,
+    luma: [u64; 256],
+    count: u64,
+}
+
+impl Histogram {
+    pub fn nchannels(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn channel(&self, idx: usize) -> &[u64; 256] {
+        &self.channels[idx]
+    }
+
+    pub fn luma(&self) -> &[u64; 256] {
+        &self.luma
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Smallest value such that at least `p` (0..=1) of the samples are at or below it.
+    pub fn percentile(&self, bins: &[u64; 256], p: f32) -> u8 {
+        let target = (p.clamp(0.0, 1.0) as f64 * self.count as f64).ceil() as u64;
+        let mut acc = 0;
+        for (value, &n) in bins.iter().enumerate() {
+            acc += n;
+            if acc >= target.max(1) {
+                return value as u8;
+            }
+        }
+        255
+    }
+
+    pub fn mean(&self, bins: &[u64; 256]) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let sum: u64 = bins.iter().enumerate().map(|(v, &n)| v as u64 * n).sum();
+        (sum as f64 / self.count as f64) as f32
+    }
+
+    /// Fraction of samples within `lo..=hi`.
+    pub fn fraction(&self, bins: &[u64; 256], lo: u8, hi: u8) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let n: u64 = bins[lo as usize..=hi as usize].iter().sum();
+        (n as f64 / self.count as f64) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    Normal,
+    /// Nearly black, e.g. negative intensity or a light pointing away.
+    Underexposed,
+    /// Most lit pixels are clipped to white.
+    Overexposed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExposureStats {
+    pub mean: f32,
+    pub p1: u8,
+    pub p50: u8,
+    pub p99: u8,
+    pub dark_fraction: f32,
+    pub clipped_fraction: f32,
+    /// Share of clipped pixels among those brighter than `DARK_LEVEL`, so
+    /// a black background does not hide a blown out model.
+    pub clipped_lit_fraction: f32,
+}
+
+impl ExposureStats {
+    pub fn classify(&self) -> Exposure {
+        if self.p99 < DARK_LEVEL {
+            Exposure::Underexposed
+        } else if self.clipped_lit_fraction > 0.5 {
+            Exposure::Overexposed
+        } else {
+            Exposure::Normal
+        }
+    }
+}
+
+fn luma(image: &TGAImage, c: &TGAColor) -> u8 {
+    if image.bpp < 3 {
+        return c[0];
+    }
+    (0.299 * c[2] as f32 + 0.587 * c[1] as f32 + 0.114 * c[0] as f32).round() as u8
+}
+
+/// Lookup table that maps `lo..=hi` linearly onto `0..=255`.
+fn stretch_table(lo: u8, hi: u8) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    for (v, out) in table.iter_mut().enumerate() {
+        *out = if hi <= lo {
+            v as u8
+        } else {
+            ((v as f32 - lo as f32) * 255.0 / (hi - lo) as f32)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+    }
+    table
+}
+
+impl TGAImage {
+    /// Histogram of every channel. 16-bit images are counted as 24-bit RGB,
+    /// their packed 5-bit channels can't be binned directly.
+    pub fn histogram(&self) -> Histogram {
+        if self.bpp == 2 {
+            return self.to_truecolor().histogram();
+        }
+        let bpp = self.bpp as usize;
+        let mut hist = Histogram {
+            channels: vec![[0; 256]; bpp],
+            luma: [0; 256],
+            count: 0,
+        };
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if let Some(c) = self.get(x, y) {
+                    for (i, bins) in hist.channels.iter_mut().enumerate() {
+                        bins[c[i] as usize] += 1;
+                    }
+                    hist.luma[luma(self, &c) as usize] += 1;
+                    hist.count += 1;
+                }
+            }
+        }
+        hist
+    }
+
+    pub fn exposure(&self) -> ExposureStats {
+        let hist = self.histogram();
+        let luma = hist.luma();
+        let lit = 1.0 - hist.fraction(luma, 0, DARK_LEVEL - 1);
+        let clipped = hist.fraction(luma, CLIP_LEVEL, 255);
+        ExposureStats {
+            mean: hist.mean(luma),
+            p1: hist.percentile(luma, 0.01),
+            p50: hist.percentile(luma, 0.5),
+            p99: hist.percentile(luma, 0.99),
+            dark_fraction: 1.0 - lit,
+            clipped_fraction: clipped,
+            clipped_lit_fraction: if lit > 0.0 { clipped / lit } else { 0.0 },
+        }
+    }
+
+    /// Stretches every colour channel on its own so that the `clip` darkest
+    /// and brightest fractions saturate. Alpha is left untouched, 16-bit
+    /// images come out as 24-bit RGB.
+    pub fn auto_levels(&mut self, clip: f32) {
+        self.stretch(|hist, ncolor| {
+            (0..ncolor)
+                .map(|i| {
+                    let bins = hist.channel(i);
+                    stretch_table(
+                        hist.percentile(bins, clip),
+                        hist.percentile(bins, 1.0 - clip),
+                    )
+                })
+                .collect()
+        });
+    }
+
+    /// Like `auto_levels` but with a single mapping derived from luma, so
+    /// the colour balance is kept.
+    pub fn auto_contrast(&mut self, clip: f32) {
+        self.stretch(|hist, ncolor| {
+            let table = stretch_table(
+                hist.percentile(hist.luma(), clip),
+                hist.percentile(hist.luma(), 1.0 - clip),
+            );
+            vec![table; ncolor]
+        });
+    }
+
+    /// Applies the per colour channel tables built from the histogram.
+    /// Premultiplied images are stretched as straight colours, otherwise
+    /// translucent pixels would count as darker than they are, and then
+    /// premultiplied again.
+    fn stretch(&mut self, tables: impl FnOnce(&Histogram, usize) -> Vec<[u8; 256]>) {
+        if self.bpp == 2 {
+            *self = self.to_truecolor();
+        }
+        let premultiplied = self.bpp == 4 && self.alpha_mode() == AlphaMode::Premultiplied;
+        if premultiplied {
+            self.unpremultiply();
+        }
+        let ncolor = if self.bpp == 4 { 3 } else { self.bpp as usize };
+        let tables = tables(&self.histogram(), ncolor);
+        self.apply_tables(&tables);
+        if premultiplied {
+            self.premultiply();
+        }
+    }
+
+    fn apply_tables(&mut self, tables: &[[u8; 256]]) {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if let Some(c) = self.get(x, y) {
+                    let mut bgra = [c[0], c[1], c[2], c[3]];
+                    for (i, table) in tables.iter().enumerate() {
+                        bgra[i] = table[bgra[i] as usize];
+                    }
+                    let _ = self.set(
+                        x as usize,
+                        y as usize,
+                        TGAColor::from_rgba(bgra[2], bgra[1], bgra[0], bgra[3]),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tga::Format;
+
+    fn gray_ramp(values: &[u8]) -> TGAImage {
+        let mut image = TGAImage::new(values.len() as i32, 1, Format::Grayscale);
+        for (x, &v) in values.iter().enumerate() {
+            image.set(x, 0, TGAColor::from_rgb(v, v, v)).unwrap();
+        }
+        image
+    }
+
+    fn grays(image: &TGAImage) -> Vec<u8> {
+        (0..image.width())
+            .map(|x| image.get(x, 0).unwrap()[0])
+            .collect()
+    }
+
+    #[test]
+    fn counts_channels_and_luma() {
+        let mut image = TGAImage::new(2, 2, Format::RGB);
+        image.set(0, 0, TGAColor::from_rgb(255, 0, 0)).unwrap();
+        image.set(1, 0, TGAColor::from_rgb(0, 255, 0)).unwrap();
+        image.set(0, 1, TGAColor::from_rgb(0, 0, 255)).unwrap();
+        image.set(1, 1, TGAColor::from_rgb(255, 255, 255)).unwrap();
+        let hist = image.histogram();
+        assert_eq!(hist.nchannels(), 3);
+        assert_eq!(hist.count(), 4);
+        // storage order, blue first
+        assert_eq!(hist.channel(0)[255], 2);
+        assert_eq!(hist.channel(0)[0], 2);
+        assert_eq!(hist.channel(2)[255], 2);
+        for luma in [76, 150, 29, 255] {
+            assert_eq!(hist.luma()[luma], 1, "luma {}", luma);
+        }
+        assert_eq!(hist.mean(hist.luma()), (76 + 150 + 29 + 255) as f32 / 4.0);
+        assert_eq!(hist.fraction(hist.luma(), 0, 100), 0.5);
+    }
+
+    #[test]
+    fn percentiles() {
+        let values: Vec<u8> = (0..100).collect();
+        let hist = gray_ramp(&values).histogram();
+        let luma = hist.luma();
+        assert_eq!(hist.percentile(luma, 0.0), 0);
+        assert_eq!(hist.percentile(luma, 0.01), 0);
+        assert_eq!(hist.percentile(luma, 0.02), 1);
+        assert_eq!(hist.percentile(luma, 0.5), 49);
+        assert_eq!(hist.percentile(luma, 0.75), 74);
+        assert_eq!(hist.percentile(luma, 1.0), 99);
+    }
+
+    #[test]
+    fn stretches_levels() {
+        let mut image = gray_ramp(&[50, 100, 150]);
+        image.auto_levels(0.0);
+        assert_eq!(grays(&image), [0, 128, 255]);
+
+        // the clipped outliers saturate, the rest spreads over the range
+        let mut values = vec![0u8, 0, 0, 0, 255, 255, 255, 255];
+        values.extend((0..92).map(|i| 100 + i / 2));
+        let mut image = gray_ramp(&values);
+        image.auto_contrast(0.05);
+        let out = grays(&image);
+        assert_eq!(out[..8], [0, 0, 0, 0, 255, 255, 255, 255]);
+        assert_eq!((out[8], out[53], out[99]), (0, 125, 255));
+
+        // a flat image is left alone
+        let mut flat = gray_ramp(&[80, 80]);
+        flat.auto_levels(0.0);
+        assert_eq!(grays(&flat), [80, 80]);
+    }
+
+    #[test]
+    fn stretches_premultiplied_images_as_straight_colour() {
+        // both store 100, but the translucent one is twice as bright
+        let mut image = TGAImage::new(2, 1, Format::RGBA);
+        image
+            .set(0, 0, TGAColor::from_rgba(100, 100, 100, 255))
+            .unwrap();
+        image
+            .set(1, 0, TGAColor::from_rgba(200, 200, 200, 128))
+            .unwrap();
+        image.premultiply();
+        image.auto_levels(0.0);
+        assert_eq!(image.alpha_mode(), AlphaMode::Premultiplied);
+        let (dark, bright) = (image.get(0, 0).unwrap(), image.get(1, 0).unwrap());
+        assert_eq!([dark[2], dark[3]], [0, 255]);
+        assert_eq!([bright[2], bright[3]], [128, 128]);
+    }
+}
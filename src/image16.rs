@@ -0,0 +1,423 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    ops::Index,
+};
+
+use crate::tga::{Format, TGAColor, TGAImage};
+
+/// Widens an 8-bit channel so that 255 maps onto 65535.
+pub fn widen(v: u8) -> u16 {
+    v as u16 * 257
+}
+
+/// Narrows a 16-bit channel to 8 bits with rounding.
+pub fn narrow(v: u16) -> u8 {
+    ((v as u32 * 255 + 32767) / 65535) as u8
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color16 {
+    bgra: [u16; 4],
+}
+
+impl Color16 {
+    pub fn from_rgba(r: u16, g: u16, b: u16, a: u16) -> Self {
+        Self { bgra: [b, g, r, a] }
+    }
+
+    pub fn from_rgb(r: u16, g: u16, b: u16) -> Self {
+        Self::from_rgba(r, g, b, u16::MAX)
+    }
+
+    pub fn gray(v: u16) -> Self {
+        Self::from_rgba(v, v, v, u16::MAX)
+    }
+
+    pub fn from_color(c: TGAColor) -> Self {
+        Self {
+            bgra: [widen(c[0]), widen(c[1]), widen(c[2]), widen(c[3])],
+        }
+    }
+
+    pub fn to_color(self) -> TGAColor {
+        TGAColor::from_rgba(
+            narrow(self.bgra[2]),
+            narrow(self.bgra[1]),
+            narrow(self.bgra[0]),
+            narrow(self.bgra[3]),
+        )
+    }
+}
+
+impl Index<usize> for Color16 {
+    type Output = u16;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.bgra[index]
+    }
+}
+
+/// Image with 16 bits per channel. Channels are stored in the same b, g, r, a
+/// order as `TGAImage`, `channels` plays the role of `TGAImage::bpp`.
+#[derive(Debug, Clone)]
+pub struct Image16 {
+    w: i32,
+    h: i32,
+    pub channels: u8,
+    data: Vec<u16>,
+}
+
+impl Image16 {
+    pub fn new(width: i32, height: i32, format: Format) -> Self {
+        let channels = format as u8;
+        Image16 {
+            w: width,
+            h: height,
+            channels,
+            data: vec![0; (width * height) as usize * channels as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.w
+    }
+
+    pub fn height(&self) -> i32 {
+        self.h
+    }
+
+    pub fn data(&self) -> &[u16] {
+        &self.data
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color16) -> Result<(), String> {
+        if x >= self.w as usize || y >= self.h as usize {
+            return Err("Coordinates out of bounds".to_string());
+        }
+        let n = self.channels as usize;
+        let index = (x + y * self.w as usize) * n;
+        self.data[index..index + n].copy_from_slice(&color.bgra[..n]);
+        Ok(())
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<Color16> {
+        if x < 0 || y < 0 || x >= self.w || y >= self.h {
+            return None;
+        }
+        let n = self.channels as usize;
+        let index = (x + y * self.w) as usize * n;
+        let mut color = Color16 { bgra: [0; 4] };
+        color.bgra[..n].copy_from_slice(&self.data[index..index + n]);
+        Some(color)
+    }
+
+    pub fn from_image(image: &TGAImage) -> Self {
+        if image.bpp == 2 {
+            // 16-bit TGAs have no 16-bit channel layout of their own
+            return Image16::from_image(&image.to_truecolor());
+        }
+        let format = Format::from_bpp(image.bpp).expect("unsupported bytes per pixel");
+        let mut out = Image16::new(image.width(), image.height(), format);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                if let Some(c) = image.get(x, y) {
+                    let _ = out.set(x as usize, y as usize, Color16::from_color(c));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_image(&self) -> TGAImage {
+        let format = Format::from_bpp(self.channels).expect("unsupported channel count");
+        let mut out = TGAImage::new(self.w, self.h, format);
+        for y in 0..self.h {
+            for x in 0..self.w {
+                if let Some(c) = self.get(x, y) {
+                    let _ = out.set(x as usize, y as usize, c.to_color());
+                }
+            }
+        }
+        out
+    }
+
+    /// Grayscale image from a depth buffer, mapping `near..=far` onto the
+    /// full 16-bit range. Values outside are clamped.
+    pub fn from_depth(width: i32, height: i32, depth: &[f32], near: f32, far: f32) -> Self {
+        let mut out = Image16::new(width, height, Format::Grayscale);
+        let range = if far != near { far - near } else { 1.0 };
+        for (dst, &z) in out.data.iter_mut().zip(depth) {
+            let t = ((z - near) / range).clamp(0.0, 1.0);
+            *dst = (t * u16::MAX as f32).round() as u16;
+        }
+        out
+    }
+
+    /// Writes a binary PGM (gray), PPM (RGB) or PAM (RGBA) file with 16-bit
+    /// samples. With `vflip` the bottom row is written first, as in `write_tga_file`.
+    pub fn write_pnm_file(&self, filename: &str, vflip: bool) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(filename)?);
+        match self.channels {
+            1 => write!(out, "P5\n{} {}\n65535\n", self.w, self.h)?,
+            3 => write!(out, "P6\n{} {}\n65535\n", self.w, self.h)?,
+            _ => write!(
+                out,
+                "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 65535\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+                self.w, self.h
+            )?,
+        }
+
+        let n = self.channels as usize;
+        let row_len = self.w as usize * n;
+        for row in 0..self.h as usize {
+            let y = if vflip {
+                self.h as usize - 1 - row
+            } else {
+                row
+            };
+            for px in self.data[y * row_len..(y + 1) * row_len].chunks_exact(n) {
+                // PNM stores samples as r, g, b, a
+                let mut rgba = [px[0]; 4];
+                if n > 1 {
+                    rgba[..3].copy_from_slice(&[px[2], px[1], px[0]]);
+                }
+                if n == 4 {
+                    rgba[3] = px[3];
+                }
+                for v in &rgba[..n] {
+                    out.write_all(&v.to_be_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+
+    /// Reads a binary PGM, PPM or PAM file with 8 or 16-bit samples. PAM
+    /// files must hold gray, RGB or RGB_ALPHA tuples. With `vflip` the first
+    /// row in the file becomes the bottom row of the image.
+    pub fn from_pnm_file(filename: &str, vflip: bool) -> io::Result<Self> {
+        Image16::read_pnm(&mut BufReader::new(File::open(filename)?), vflip)
+    }
+
+    fn read_pnm(reader: &mut impl BufRead, vflip: bool) -> io::Result<Self> {
+        let parse = |s: &str| s.parse::<u32>().map_err(|_| invalid("bad PNM header"));
+        let magic = header_field(reader)?;
+        let (format, w, h, maxval) = match magic.as_str() {
+            "P5" | "P6" => {
+                let format = if magic == "P5" {
+                    Format::Grayscale
+                } else {
+                    Format::RGB
+                };
+                let w = parse(&header_field(reader)?)?;
+                let h = parse(&header_field(reader)?)?;
+                // the pixels start right after the whitespace byte that ends maxval
+                let maxval = parse(&header_field(reader)?)?;
+                (format, w, h, maxval)
+            }
+            "P7" => {
+                let (mut w, mut h, mut depth, mut maxval) = (None, None, None, None);
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line)? == 0 {
+                        return Err(invalid("truncated PNM header"));
+                    }
+                    let line = line.split('#').next().unwrap_or("");
+                    let mut parts = line.split_whitespace();
+                    match (parts.next(), parts.next()) {
+                        (Some("ENDHDR"), _) => break,
+                        (Some("WIDTH"), Some(v)) => w = Some(parse(v)?),
+                        (Some("HEIGHT"), Some(v)) => h = Some(parse(v)?),
+                        (Some("DEPTH"), Some(v)) => depth = Some(parse(v)?),
+                        (Some("MAXVAL"), Some(v)) => maxval = Some(parse(v)?),
+                        // the tuple type follows from the depth
+                        _ => {}
+                    }
+                }
+                let format = match depth {
+                    Some(1) => Format::Grayscale,
+                    Some(3) => Format::RGB,
+                    Some(4) => Format::RGBA,
+                    _ => return Err(invalid("only PAM files with depth 1, 3 or 4 are supported")),
+                };
+                match (w, h, maxval) {
+                    (Some(w), Some(h), Some(maxval)) => (format, w, h, maxval),
+                    _ => return Err(invalid("incomplete PAM header")),
+                }
+            }
+            _ => return Err(invalid("only binary PGM, PPM and PAM files are supported")),
+        };
+        if maxval == 0 || maxval > 65535 {
+            return Err(invalid("bad PNM maxval"));
+        }
+
+        let too_large = || invalid("PNM image too large");
+        let (iw, ih) = (
+            i32::try_from(w).map_err(|_| too_large())?,
+            i32::try_from(h).map_err(|_| too_large())?,
+        );
+        iw.checked_mul(ih).ok_or_else(too_large)?;
+        let n = format as usize;
+        let sample_size = if maxval > 255 { 2 } else { 1 };
+        let len = (w as usize)
+            .checked_mul(h as usize)
+            .and_then(|v| v.checked_mul(n * sample_size))
+            .ok_or_else(too_large)?;
+        // read before allocating the image, so a header promising more
+        // pixels than the file holds fails without reserving them all
+        let mut raw = Vec::new();
+        reader.take(len as u64).read_to_end(&mut raw)?;
+        if raw.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "PNM pixel data is truncated",
+            ));
+        }
+        let mut image = Image16::new(iw, ih, format);
+
+        let row_len = w as usize * n;
+        for row in 0..h as usize {
+            let y = if vflip { h as usize - 1 - row } else { row };
+            for x in 0..w as usize {
+                let mut rgba = [0u16, 0, 0, u16::MAX];
+                for (c, v) in rgba.iter_mut().enumerate().take(n) {
+                    let i = (row * row_len + x * n + c) * sample_size;
+                    let sample = if sample_size == 2 {
+                        u16::from_be_bytes([raw[i], raw[i + 1]]) as u32
+                    } else {
+                        raw[i] as u32
+                    };
+                    *v = (sample * 65535 / maxval) as u16;
+                }
+                let color = if n == 1 {
+                    Color16::gray(rgba[0])
+                } else {
+                    Color16::from_rgba(rgba[0], rgba[1], rgba[2], rgba[3])
+                };
+                let _ = image.set(x, y, color);
+            }
+        }
+        Ok(image)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Next whitespace separated field of a PGM or PPM header, skipping `#`
+/// comments. Exactly one whitespace byte after the field is consumed, as
+/// the format allows only one between the maxval and the pixels.
+fn header_field(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut next = || -> io::Result<u8> {
+        let mut byte = [0];
+        match reader.read_exact(&mut byte) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(invalid("truncated PNM header"))
+            }
+            result => result.map(|_| byte[0]),
+        }
+    };
+    let mut field = Vec::new();
+    loop {
+        match next()? {
+            b'#' => {
+                // a comment runs to the end of the line and ends a field
+                while next()? != b'\n' {}
+                if !field.is_empty() {
+                    break;
+                }
+            }
+            b if b.is_ascii_whitespace() => {
+                if !field.is_empty() {
+                    break;
+                }
+            }
+            b => field.push(b),
+        }
+    }
+    Ok(String::from_utf8_lossy(&field).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(header: &[u8], pixels: &[u8]) -> io::Result<Image16> {
+        let data = [header, pixels].concat();
+        Image16::read_pnm(&mut &data[..], false)
+    }
+
+    #[test]
+    fn single_whitespace_after_maxval() {
+        // a tab ends the maxval and the first sample is a newline byte
+        let image = read(b"P5 2 2 255\t", &[0x0a, 0xff, 0x00, 0x0a]).unwrap();
+        assert_eq!(image.data(), [widen(0x0a), 65535, 0, widen(0x0a)]);
+        // 16-bit samples that start with a space
+        let image = read(b"P5\n1 1\n65535 ", &[0x20, 0x01]).unwrap();
+        assert_eq!(image.data(), [0x2001]);
+    }
+
+    #[test]
+    fn header_comments() {
+        let image = read(
+            b"P6\n# written by hand\n1 #width\n1\n# maxval next\n255\n",
+            &[10, 20, 30],
+        )
+        .unwrap();
+        // stored b, g, r
+        assert_eq!(image.data(), [widen(30), widen(20), widen(10)]);
+    }
+
+    #[test]
+    fn pam_header() {
+        let header = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+        let image = read(header, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            image.get(0, 0),
+            Some(Color16::from_rgba(widen(1), widen(2), widen(3), widen(4)))
+        );
+    }
+
+    #[test]
+    fn bad_headers() {
+        let kind = |header: &[u8]| read(header, &[0; 16]).unwrap_err().kind();
+        assert_eq!(kind(b"P5 2 2"), io::ErrorKind::InvalidData);
+        assert_eq!(kind(b"P5 2 x 255 "), io::ErrorKind::InvalidData);
+        assert_eq!(kind(b"P5 2 2 0 "), io::ErrorKind::InvalidData);
+        assert_eq!(kind(b"P2 2 2 255 "), io::ErrorKind::InvalidData);
+        // the pixels are short
+        assert!(read(b"P6 4 4 255 ", &[0; 16]).is_err());
+    }
+
+    #[test]
+    fn oversized_headers() {
+        let kind = |header: &[u8]| read(header, &[0; 16]).unwrap_err().kind();
+        assert_eq!(kind(b"P5 100000 100000 255 "), io::ErrorKind::InvalidData);
+        assert_eq!(kind(b"P6 4294967295 1 255 "), io::ErrorKind::InvalidData);
+        assert_eq!(
+            kind(b"P7\nWIDTH 4000000000\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nENDHDR\n"),
+            io::ErrorKind::InvalidData
+        );
+        // fits, but the file holds far fewer pixels than promised
+        assert_eq!(kind(b"P5 40000 40000 255 "), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn from_sixteen_bit_tga() {
+        let mut tga = TGAImage::new(1, 1, Format::RGB);
+        tga.set(0, 0, TGAColor::from_rgba(255, 0, 0, 255)).unwrap();
+        // 1x1 uncompressed 16-bit TGA holding pure red
+        let mut file = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 16, 0];
+        file.extend_from_slice(&[0x00, 0x7c]);
+        let path = std::env::temp_dir().join(format!("image16_tga_{}.tga", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+        let packed = TGAImage::from_tga_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(packed.bpp, 2);
+        let image = Image16::from_image(&packed);
+        assert_eq!(image.channels, 3);
+        assert_eq!(image.data(), Image16::from_image(&tga).data());
+    }
+}
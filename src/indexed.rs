@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use crate::geometry::{Vec2f, Vec3f};
+use crate::model::Model;
+
+/// One entry of an indexed vertex buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: Vec3f,
+    /// (0, 0) for faces without texture coordinates.
+    pub uv: Vec2f,
+    /// The face normal for faces without `vn` indices.
+    pub normal: Vec3f,
+}
+
+impl Vertex {
+    fn key(&self) -> [u32; 8] {
+        // adding zero turns -0.0 into 0.0 so both compare equal
+        let b = |v: f32| (v + 0.0).to_bits();
+        [
+            b(self.position.x),
+            b(self.position.y),
+            b(self.position.z),
+            b(self.uv.x),
+            b(self.uv.y),
+            b(self.normal.x),
+            b(self.normal.y),
+            b(self.normal.z),
+        ]
+    }
+}
+
+/// A mesh with a single index per corner, as a GPU would draw it. Triangle
+/// `i` is face `i` of the model it came from, so materials and submeshes
+/// still apply.
+#[derive(Debug, Clone)]
+pub struct IndexedMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl Model {
+    /// Builds a unified vertex buffer, sharing one vertex between all
+    /// corners with identical position, UV and normal.
+    pub fn to_indexed(&self) -> IndexedMesh {
+        let mut vertices = Vec::new();
+        let mut lookup: HashMap<[u32; 8], u32> = HashMap::new();
+        let mut indices = Vec::with_capacity(self.faces.len());
+        for iface in 0..self.faces.len() {
+            let mut tri = [0u32; 3];
+            for (k, index) in tri.iter_mut().enumerate() {
+                let vertex = Vertex {
+                    position: self.vert(self.faces[iface][k].x as usize),
+                    uv: self.uv(iface, k),
+                    normal: self.norm(iface, k),
+                };
+                *index = *lookup.entry(vertex.key()).or_insert_with(|| {
+                    vertices.push(vertex);
+                    vertices.len() as u32 - 1
+                });
+            }
+            indices.push(tri);
+        }
+        IndexedMesh { vertices, indices }
+    }
+
+    /// Merges positions closer than `epsilon`, then strips the ones no face
+    /// uses any more. Faces whose corners merge stay as degenerate triangles
+    /// so face indices keep matching submeshes and tangents. Returns the
+    /// number of positions removed.
+    pub fn weld(&mut self, epsilon: f32) -> usize {
+        let n = self.verts.len();
+        let mut remap: Vec<usize> = (0..n).collect();
+        if epsilon > 0.0 {
+            // grid of epsilon sized cells, neighbours can only be in the 27 around
+            let cell = |v: Vec3f| {
+                (
+                    (v.x / epsilon).floor() as i64,
+                    (v.y / epsilon).floor() as i64,
+                    (v.z / epsilon).floor() as i64,
+                )
+            };
+            let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+            for (i, &v) in self.verts.iter().enumerate() {
+                let (cx, cy, cz) = cell(v);
+                let mut found = None;
+                'search: for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        for dz in -1..=1 {
+                            for &j in grid.get(&(cx + dx, cy + dy, cz + dz)).into_iter().flatten() {
+                                if (self.verts[j] - v).norm() <= epsilon {
+                                    found = Some(j);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+                match found {
+                    Some(j) => remap[i] = j,
+                    None => grid.entry((cx, cy, cz)).or_default().push(i),
+                }
+            }
+        } else {
+            let mut lookup: HashMap<[u32; 3], usize> = HashMap::new();
+            for (i, v) in self.verts.iter().enumerate() {
+                let key = [
+                    (v.x + 0.0).to_bits(),
+                    (v.y + 0.0).to_bits(),
+                    (v.z + 0.0).to_bits(),
+                ];
+                remap[i] = *lookup.entry(key).or_insert(i);
+            }
+        }
+        for face in &mut self.faces {
+            for c in face.iter_mut() {
+                c.x = remap[c.x as usize] as i32;
+            }
+        }
+        let before = self.verts.len();
+        self.remove_unused();
+        before - self.verts.len()
+    }
+
+    /// Drops positions, texture coordinates and normals that no face
+    /// references and renumbers the rest. Vertex colours and attributes
+    /// follow their positions. Models without faces, such as point clouds,
+    /// are left alone.
+    pub fn remove_unused(&mut self) {
+        if self.faces.is_empty() {
+            return;
+        }
+        let mut used = [
+            vec![false; self.verts.len()],
+            vec![false; self.uv.len()],
+            vec![false; self.norms.len()],
+        ];
+        for c in self.faces.iter().flatten() {
+            for (slot, index) in [c.x, c.y, c.z].into_iter().enumerate() {
+                if index >= 0 {
+                    used[slot][index as usize] = true;
+                }
+            }
+        }
+        // new index of every kept entry
+        let renumber = |used: &[bool]| {
+            let mut next = 0;
+            used.iter()
+                .map(|&u| {
+                    let i = next;
+                    next += u as i32;
+                    i
+                })
+                .collect::<Vec<i32>>()
+        };
+        let remaps = [renumber(&used[0]), renumber(&used[1]), renumber(&used[2])];
+        for face in &mut self.faces {
+            for c in face.iter_mut() {
+                if c.x >= 0 {
+                    c.x = remaps[0][c.x as usize];
+                }
+                if c.y >= 0 {
+                    c.y = remaps[1][c.y as usize];
+                }
+                if c.z >= 0 {
+                    c.z = remaps[2][c.z as usize];
+                }
+            }
+        }
+
+        fn keep<T: Copy>(values: &mut Vec<T>, used: &[bool]) {
+            if values.len() == used.len() {
+                let mut flags = used.iter();
+                values.retain(|_| *flags.next().unwrap_or(&false));
+            }
+        }
+        keep(&mut self.verts, &used[0]);
+        keep(&mut self.uv, &used[1]);
+        keep(&mut self.norms, &used[2]);
+        keep(&mut self.colors, &used[0]);
+        for values in self.vertex_attributes.values_mut() {
+            keep(values, &used[0]);
+        }
+        for lists in self.vertex_lists.values_mut() {
+            if lists.len() == used[0].len() {
+                lists.retain_rows(&used[0]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Vec3i, Vec4f};
+
+    /// Unit cube with four positions of its own per side, moved by less
+    /// than `jitter` along each axis, and a normal per side when `hard`.
+    fn split_cube(jitter: f32, hard: bool) -> Model {
+        let corner = |i: i32| Vec3f::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32);
+        let quads = [
+            ([0, 2, 3, 1], Vec3f::new(0.0, 0.0, -1.0)),
+            ([4, 5, 7, 6], Vec3f::new(0.0, 0.0, 1.0)),
+            ([0, 1, 5, 4], Vec3f::new(0.0, -1.0, 0.0)),
+            ([2, 6, 7, 3], Vec3f::new(0.0, 1.0, 0.0)),
+            ([0, 4, 6, 2], Vec3f::new(-1.0, 0.0, 0.0)),
+            ([1, 3, 7, 5], Vec3f::new(1.0, 0.0, 0.0)),
+        ];
+        let (mut verts, mut norms, mut faces) = (Vec::new(), Vec::new(), Vec::new());
+        for (q, (quad, normal)) in quads.iter().enumerate() {
+            let first = verts.len() as i32;
+            // every side moves its copies of the corners by its own amount
+            let nudge = jitter * q as f32 / 6.0;
+            for &i in quad {
+                verts.push(corner(i) + Vec3f::new(nudge, -nudge, nudge));
+            }
+            let n = if hard { q as i32 } else { -1 };
+            let c = |k: i32| Vec3i::new(first + k, -1, n);
+            faces.push(vec![c(0), c(1), c(2)]);
+            faces.push(vec![c(0), c(2), c(3)]);
+            norms.push(*normal);
+        }
+        if !hard {
+            norms.clear();
+        }
+        Model::from_triangles(verts, norms, Vec::new(), faces)
+    }
+
+    #[test]
+    fn welds_within_tolerance() {
+        let mut model = split_cube(1e-4, false);
+        assert_eq!(model.nverts(), 24);
+        assert_eq!(model.weld(1e-3), 16);
+        assert_eq!(model.nverts(), 8);
+        assert_eq!(model.nfaces(), 12);
+        // every face still has three distinct corners
+        for f in 0..model.nfaces() {
+            let face = model.face(f);
+            assert!(face[0].x != face[1].x && face[1].x != face[2].x && face[0].x != face[2].x);
+        }
+
+        // a tolerance below the jitter only merges what is exactly equal
+        let mut model = split_cube(1e-2, false);
+        assert_eq!(model.weld(1e-4), 0);
+        let mut model = split_cube(0.0, false);
+        assert_eq!(model.weld(0.0), 16);
+    }
+
+    #[test]
+    fn seams_stay_split() {
+        // hard normals: one vertex per corner and side
+        let mut model = split_cube(0.0, true);
+        model.weld(1e-3);
+        assert_eq!(model.nverts(), 8);
+        let mesh = model.to_indexed();
+        assert_eq!(mesh.vertices.len(), 24);
+        assert_eq!(mesh.indices.len(), 12);
+
+        // a square whose two halves meet at a UV seam
+        let verts = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(1.0, 1.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        ];
+        let uv = vec![
+            Vec2f::new(0.0, 0.0),
+            Vec2f::new(0.5, 0.0),
+            Vec2f::new(0.5, 0.5),
+            Vec2f::new(0.6, 0.0),
+            Vec2f::new(0.6, 0.5),
+            Vec2f::new(0.0, 0.5),
+        ];
+        let c = |v, t| Vec3i::new(v, t, -1);
+        let faces = vec![
+            vec![c(0, 0), c(1, 1), c(2, 2)],
+            vec![c(0, 3), c(2, 4), c(3, 5)],
+        ];
+        let mut square = Model::from_triangles(verts, Vec::new(), uv, faces);
+        assert_eq!(square.weld(1e-3), 0);
+        let mesh = square.to_indexed();
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, [[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(mesh.vertices[3].position, mesh.vertices[0].position);
+    }
+
+    #[test]
+    fn remove_unused_renumbers_faces() {
+        let verts = (0..5).map(|i| Vec3f::new(i as f32, 0.0, 0.0)).collect();
+        let uv = (0..4).map(|i| Vec2f::new(i as f32, 0.0)).collect();
+        let norms = vec![Vec3f::new(1.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0)];
+        let faces = vec![vec![
+            Vec3i::new(1, 3, 1),
+            Vec3i::new(3, 1, 1),
+            Vec3i::new(4, 3, 1),
+        ]];
+        let mut model = Model::from_triangles(verts, norms, uv, faces);
+        model.colors = (0..5)
+            .map(|i| Vec4f::new(i as f32, 0.0, 0.0, 1.0))
+            .collect();
+        model.remove_unused();
+
+        assert_eq!(
+            model.face(0),
+            &[
+                Vec3i::new(0, 1, 0),
+                Vec3i::new(1, 0, 0),
+                Vec3i::new(2, 1, 0)
+            ]
+        );
+        let xs: Vec<f32> = (0..model.nverts()).map(|i| model.vert(i).x).collect();
+        assert_eq!(xs, [1.0, 3.0, 4.0]);
+        assert_eq!(model.uv(0, 0), Vec2f::new(3.0, 0.0));
+        assert_eq!(model.norms, [Vec3f::new(0.0, 0.0, 1.0)]);
+        let reds: Vec<f32> = model.colors.iter().map(|c| c.x).collect();
+        assert_eq!(reds, [1.0, 3.0, 4.0]);
+    }
+}
@@ -1,3 +1,24 @@
+pub mod bounds;
 pub mod geometry;
+pub mod gltf;
+pub mod halfedge;
+pub mod histogram;
+pub mod image16;
+pub mod indexed;
+mod json;
+pub mod maps;
+pub mod material;
 pub mod model;
+pub mod normals;
+pub mod obj_writer;
+pub mod ply;
+pub mod sampler;
+pub mod simplify;
+pub mod stl;
+pub mod subdivision;
+pub mod tangents;
+pub mod terminal;
 pub mod tga;
+pub mod tga_stream;
+pub mod transform;
+pub mod validate;
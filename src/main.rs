@@ -1,15 +1,21 @@
-use std::{env, mem::swap};
+use std::{env, io, path::Path, process};
 
-use rand::Rng;
 use tinyrenderer_rust::{
-    geometry::{Matrix, Vec2f, Vec2i, Vec3, Vec3f, Vec3i},
-    model::Model,
+    geometry::{Matrix, Vec2f, Vec3, Vec3f, Vec3i},
+    histogram::Exposure,
+    image16::Image16,
+    model::{Model, ParseMode},
+    sampler::{Filter, Sampler, Wrap},
+    simplify::SimplifyOptions,
+    subdivision::Subdivision,
+    terminal::{self, PreviewMode},
     tga::{Format, TGAColor, TGAImage},
+    tga_stream::TGAStreamWriter,
 };
 
 const IMAGE_WIDTH: i32 = 2000;
 const IMAGE_HEIGHT: i32 = 2000;
-const DEPTH: i32 = 255;
+const DEPTH: i32 = 65535;
 
 pub fn lookat(eye: Vec3f, center: Vec3f, up: Vec3f) -> Matrix {
     let z = (eye - center).normalize();
@@ -25,89 +31,456 @@ pub fn lookat(eye: Vec3f, center: Vec3f, up: Vec3f) -> Matrix {
     res
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let model_path = if args.len() == 2 {
-        &args[1]
+/// Screen region a pass of the rasterizer draws into.
+struct Tile {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+/// A front facing triangle after the vertex transforms.
+struct ScreenFace<'a> {
+    pts: [Vec3i; 3],
+    uv: [Vec2f; 3],
+    intensity: f32,
+    texture: Option<&'a TGAImage>,
+    sampler: Sampler,
+    // used when there is no texture
+    color: TGAColor,
+}
+
+/// Flat colour of an untextured face: the average vertex colour, else the
+/// material's Kd, else white.
+fn face_color(model: &Model, iface: usize) -> TGAColor {
+    let rgb = if model.has_vertex_colors() {
+        (0..3)
+            .filter_map(|k| model.vertex_color(iface, k))
+            .fold(Vec3f::new(0.0, 0.0, 0.0), |acc, c| {
+                acc + c.xyz() * (1.0 / 3.0)
+            })
     } else {
-        "obj/dude.obj"
+        match model.material(iface) {
+            Some(m) => m.diffuse,
+            None => Vec3f::new(1.0, 1.0, 1.0),
+        }
+    };
+    TGAColor::from_rgb(
+        (rgb.x.clamp(0.0, 1.0) * 255.0) as u8,
+        (rgb.y.clamp(0.0, 1.0) * 255.0) as u8,
+        (rgb.z.clamp(0.0, 1.0) * 255.0) as u8,
+    )
+}
+
+fn parse_size(s: &str) -> Option<(i32, i32)> {
+    let (w, h) = s.split_once('x')?;
+    let (w, h) = (w.parse().ok()?, h.parse().ok()?);
+    // TGA stores each side in 16 bits
+    let max = u16::MAX as i32;
+    if (1..=max).contains(&w) && (1..=max).contains(&h) {
+        Some((w, h))
+    } else {
+        None
+    }
+}
+
+/// The `info` and `check` subcommands, returns the exit code. `check`
+/// fails if the model has problems, or still has them after `--fix`.
+fn inspect(command: &str, mut args: impl Iterator<Item = String>) -> i32 {
+    let mut path = None;
+    let mut parse_mode = ParseMode::Lenient;
+    let mut fix = None;
+    while let Some(arg) = args.next() {
+        if arg == "--strict" {
+            parse_mode = ParseMode::Strict;
+        } else if arg == "--fix" && command == "check" {
+            fix = args.next();
+            if fix.is_none() {
+                eprintln!("--fix expects an output file name");
+                return 1;
+            }
+        } else {
+            path = Some(arg);
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{} expects a model file", command);
+        return 1;
+    };
+    let mut model = match Model::open(&path, parse_mode) {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("failed to load model: {}", err);
+            return 1;
+        }
+    };
+    let report = model.validate();
+    if command == "info" {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        println!("vertices: {}", model.nverts());
+        println!("triangles: {}", model.nfaces());
+        println!("normals: {}", yes_no(model.has_normals()));
+        println!(
+            "texture coordinates: {}",
+            yes_no((0..model.nfaces()).all(|f| model.face_attributes(f).uv))
+        );
+        println!("vertex colours: {}", yes_no(model.has_vertex_colors()));
+        let names: Vec<&str> = model.submeshes().iter().map(|m| m.name.as_str()).collect();
+        println!("submeshes: {}", names.join(", "));
+        let names: Vec<&str> = model.materials().iter().map(|m| m.name.as_str()).collect();
+        println!("materials: {}", names.join(", "));
+        if let Some(bbox) = model.bounding_box() {
+            println!("bounds: {} to {}, size {}", bbox.min, bbox.max, bbox.size());
+        }
+        println!("{}", report);
+        return 0;
+    }
+
+    println!("{}", report);
+    let Some(out) = fix else {
+        return if report.is_clean() { 0 } else { 1 };
+    };
+    println!("{}", model.repair());
+    if let Err(err) = model.write_obj(&out, true) {
+        eprintln!("failed to write {}: {}", out, err);
+        return 1;
+    }
+    let report = model.validate();
+    println!("after repair, written to {}:\n{}", out, report);
+    if report.is_clean() {
+        0
+    } else {
+        1
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    if let Some(command) = args.next_if(|a| a == "info" || a == "check") {
+        process::exit(inspect(&command, args));
+    }
+    let mut model_path = String::from("obj/dude.obj");
+    let mut preview = None;
+    let mut zbuffer_path = None;
+    let (mut width, mut height) = (IMAGE_WIDTH, IMAGE_HEIGHT);
+    let mut tile_size = None;
+    let mut hidden = Vec::new();
+    let mut only = None;
+    let mut parse_mode = ParseMode::Lenient;
+    let mut frame = true;
+    let mut simplify = None;
+    let mut subdivide = 0;
+    let mut scheme = None;
+    let mut sampler = Sampler::default();
+    let mut cell_size = terminal::CELL_SIZE;
+    while let Some(arg) = args.next() {
+        if arg == "--size" {
+            match args.next().as_deref().and_then(parse_size) {
+                Some(size) => (width, height) = size,
+                None => {
+                    eprintln!("--size expects WIDTHxHEIGHT, at most 65535 per side");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--tile" {
+            match args.next().and_then(|v| v.parse::<i32>().ok()) {
+                Some(n) if n > 0 => tile_size = Some(n),
+                _ => {
+                    eprintln!("--tile expects a tile size in pixels");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--simplify" {
+            match args.next().and_then(|v| v.parse::<f32>().ok()) {
+                Some(ratio) if ratio > 0.0 && ratio <= 1.0 => simplify = Some(ratio),
+                _ => {
+                    eprintln!("--simplify expects the fraction of triangles to keep, e.g. 0.1");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--subdivide" {
+            match args.next().and_then(|v| v.parse::<usize>().ok()) {
+                Some(levels) => subdivide = levels,
+                None => {
+                    eprintln!("--subdivide expects a number of levels");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--subdivide-scheme" {
+            match args.next().as_deref().and_then(Subdivision::from_name) {
+                Some(s) => scheme = Some(s),
+                None => {
+                    eprintln!("--subdivide-scheme expects loop or catmull-clark");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--filter" {
+            match args.next().as_deref().and_then(Filter::from_name) {
+                Some(filter) => sampler.filter = filter,
+                None => {
+                    eprintln!("--filter expects nearest or bilinear");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--wrap" {
+            match args.next().as_deref().and_then(Wrap::from_name) {
+                Some(wrap) => (sampler.wrap_u, sampler.wrap_v) = (wrap, wrap),
+                None => {
+                    eprintln!("--wrap expects repeat, clamp or mirror");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--no-frame" {
+            frame = false;
+        } else if arg == "--strict" {
+            parse_mode = ParseMode::Strict;
+        } else if arg == "--hide" || arg == "--only" {
+            let Some(name) = args.next() else {
+                eprintln!("{} expects a submesh or object name", arg);
+                process::exit(1);
+            };
+            if arg == "--hide" {
+                hidden.push(name);
+            } else {
+                only = Some(name);
+            }
+        } else if arg == "--zbuffer" {
+            zbuffer_path = args.next();
+            if zbuffer_path.is_none() {
+                eprintln!("--zbuffer expects an output file name");
+                process::exit(1);
+            }
+        } else if arg == "--cell-size" {
+            match args.next().as_deref().and_then(parse_size) {
+                Some((w, h)) => cell_size = (w as usize, h as usize),
+                None => {
+                    eprintln!("--cell-size expects the pixel size of a terminal cell, e.g. 10x20");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--preview" {
+            preview = Some(PreviewMode::Ansi);
+        } else if let Some(mode) = arg.strip_prefix("--preview=") {
+            match PreviewMode::from_name(mode) {
+                Some(mode) => preview = Some(mode),
+                None => {
+                    eprintln!("unknown preview mode {}, expected ansi or sixel", mode);
+                    process::exit(1);
+                }
+            }
+        } else {
+            model_path = arg;
+        }
+    }
+    if tile_size.is_some() && (preview.is_some() || zbuffer_path.is_some()) {
+        eprintln!("--preview and --zbuffer need the whole frame and can't be used with --tile");
+        process::exit(1);
+    }
+    let mut model = match Model::open(&model_path, parse_mode) {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("failed to load model: {}", err);
+            process::exit(1);
+        }
     };
-    let mut model = Model::new(model_path).expect("Failed to load model");
-    model.load_texture("obj/dude_diffuse.tga");
-
-    // colors
-    let red = TGAColor::from_rgb(255, 0, 0);
-    let green = TGAColor::from_rgb(0, 255, 0);
-    let white = TGAColor::from_rgb(255, 255, 255);
-    let yellow = TGAColor::from_rgb(255, 255, 0);
-
-    // image and camera setup
-    let mut image = TGAImage::new(IMAGE_WIDTH, IMAGE_HEIGHT, Format::RGB);
-    let mut zbuffer = vec![i32::MIN; IMAGE_WIDTH as usize * IMAGE_HEIGHT as usize];
+    model.sampler = sampler;
+    if let Some(ratio) = simplify {
+        let before = model.nfaces();
+        let target = (before as f32 * ratio) as usize;
+        let error = model.simplify(&SimplifyOptions::triangles(target));
+        eprintln!(
+            "simplified {} to {} triangles, max error {}",
+            before,
+            model.nfaces(),
+            error
+        );
+    }
+    if subdivide > 0 {
+        let scheme = scheme.unwrap_or_else(|| Subdivision::for_model(&model));
+        model.subdivide(scheme, subdivide);
+        eprintln!("{:?} subdivision to {} triangles", scheme, model.nfaces());
+    }
+    if frame {
+        // fit the bounding sphere into the unit sphere the camera is set up for
+        model.normalize_to_unit_sphere();
+    }
+    if let Some(name) = &only {
+        if !model.show_only(name) {
+            eprintln!("no submesh or object named {}", name);
+        }
+    }
+    for name in &hidden {
+        if !model.set_visible(name, false) {
+            eprintln!("no submesh or object named {}", name);
+        }
+    }
+    if model.materials().is_empty() {
+        // no MTL file, fall back to the tinyrenderer convention of <name>_diffuse.tga,
+        // <name>_nm_tangent.tga or <name>_nm.tga and so on
+        model.discover_maps(Path::new(&model_path));
+    }
+
+    // camera setup
     let light_dir = Vec3f::new(0.0, 0.0, -1.0);
-    let camera = Vec3f::new(0.0, 0.0, 3.0);
     let eye = Vec3::new(1.0, 1.0, 3.0);
     let center = Vec3::new(0.0, 0.0, 0.0);
     let model_view = lookat(eye, center, Vec3::new(0.0, 1.0, 0.0));
     let mut projection = Matrix::identity(4);
-    let viewport = Matrix::new_from_viewport(
-        (IMAGE_WIDTH / 8) as usize,
-        (IMAGE_HEIGHT / 8) as usize,
-        (IMAGE_WIDTH * 3 / 4) as usize,
-        (IMAGE_HEIGHT * 3 / 4) as usize,
+    let viewport = Matrix::new_from_viewport_depth(
+        (width / 8) as usize,
+        (height / 8) as usize,
+        (width * 3 / 4) as usize,
+        (height * 3 / 4) as usize,
+        DEPTH as u32,
     );
     projection[3][2] = -1.0 / (eye - center).norm();
 
-    // draw the model
-    for i in 0..model.nfaces() {
-        let face = model.face(i);
-        let mut screen_coords: Vec<Vec3i> = Vec::with_capacity(3);
-        let mut world_coords: Vec<Vec3f> = Vec::with_capacity(3);
-        let mut intensity = vec![f32::MIN; 3];
-        for j in 0..3 {
-            let idx = face[j].x as usize;
-            let v = model.vert(idx);
-
-            screen_coords.push(
-                (&viewport * &projection * &model_view * Matrix::new_from_vector(v))
-                    .to_vector()
-                    .into(),
-            );
-            world_coords.push(v);
-            intensity.push(model.norm(i, j) * light_dir);
-        }
-        let mut n = (world_coords[2] - world_coords[0]) ^ (world_coords[1] - world_coords[0]);
+    // transform every vertex once, every tile only rasterizes
+    let mesh = model.to_indexed();
+    let transform = &viewport * &projection * &model_view;
+    let screen: Vec<Vec3i> = mesh
+        .vertices
+        .iter()
+        .map(|v| transform.transform_point(v.position).into())
+        .collect();
+    let mut faces = Vec::new();
+    for i in model.visible_faces() {
+        let [a, b, c] = mesh.indices[i].map(|v| v as usize);
+        let world = [a, b, c].map(|v| mesh.vertices[v].position);
+        let mut n = (world[2] - world[0]) ^ (world[1] - world[0]);
         n.normalize();
         let intensity = n * light_dir;
         if intensity > 0.0 {
-            let mut uv: Vec<Vec2i> = Vec::with_capacity(3);
-            for k in 0..3 {
-                uv.push(model.uv(i, k));
-            }
-            triangle_scanline(
-                screen_coords[0],
-                screen_coords[1],
-                screen_coords[2],
-                uv[0],
-                uv[1],
-                uv[2],
-                &mut zbuffer,
-                &mut image,
-                &model,
+            faces.push(ScreenFace {
+                pts: [screen[a], screen[b], screen[c]],
+                uv: [a, b, c].map(|v| mesh.vertices[v].uv),
                 intensity,
-            );
+                texture: model.texture(i).filter(|_| model.face_attributes(i).uv),
+                sampler: model.sampler,
+                color: face_color(&model, i),
+            });
         }
     }
 
+    if let Some(tile_size) = tile_size {
+        render_tiled(&faces, width, height, tile_size, "output.tga").unwrap();
+        return;
+    }
+
+    let mut image = TGAImage::new(width, height, Format::RGB);
+    let mut zbuffer = vec![i32::MIN; width as usize * height as usize];
+    let frame = Tile {
+        x: 0,
+        y: 0,
+        w: width,
+        h: height,
+    };
+    draw_faces(&faces, &frame, &mut zbuffer, &mut image);
+
     image.write_tga_file("output.tga", true, true).unwrap();
+
+    if let Some(path) = zbuffer_path {
+        let depth: Vec<f32> = zbuffer.iter().map(|&z| z.max(0) as f32).collect();
+        Image16::from_depth(width, height, &depth, 0.0, DEPTH as f32)
+            .write_pnm_file(&path, true)
+            .unwrap();
+    }
+
+    let stats = image.exposure();
+    match stats.classify() {
+        Exposure::Underexposed => eprintln!(
+            "warning: output.tga is nearly black (99th percentile luma {}), check the light direction",
+            stats.p99
+        ),
+        Exposure::Overexposed => eprintln!(
+            "warning: output.tga is blown out ({:.0}% of lit pixels clipped)",
+            stats.clipped_lit_fraction * 100.0
+        ),
+        Exposure::Normal => {}
+    }
+
+    if let Some(mode) = preview {
+        // keep one line free for the shell prompt
+        let (cols, rows) = terminal::terminal_size();
+        let rows = rows.saturating_sub(1).max(1);
+        let mut out = io::stdout().lock();
+        match mode {
+            PreviewMode::Ansi => terminal::write_ansi(&image, cols, rows, true, &mut out),
+            PreviewMode::Sixel => {
+                let (w, h) = cell_size;
+                terminal::write_sixel(&image, cols * w, rows * h, true, &mut out)
+            }
+        }
+        .unwrap();
+    }
+}
+
+fn draw_faces(faces: &[ScreenFace], tile: &Tile, zbuffer: &mut [i32], image: &mut TGAImage) {
+    for face in faces {
+        let [t0, t1, t2] = face.pts;
+        let min_x = t0.x.min(t1.x).min(t2.x);
+        let max_x = t0.x.max(t1.x).max(t2.x);
+        let min_y = t0.y.min(t1.y).min(t2.y);
+        let max_y = t0.y.max(t1.y).max(t2.y);
+        if max_x < tile.x || min_x >= tile.x + tile.w || max_y < tile.y || min_y >= tile.y + tile.h
+        {
+            continue;
+        }
+        triangle_scanline(
+            t0,
+            t1,
+            t2,
+            face.uv[0],
+            face.uv[1],
+            face.uv[2],
+            tile,
+            zbuffer,
+            image,
+            face.intensity,
+            face.texture,
+            face.sampler,
+            face.color,
+        );
+    }
 }
 
+/// Renders `tile_size` x `tile_size` tiles bottom to top and streams them to
+/// `filename`, so only one band of tiles is held in memory.
+fn render_tiled(
+    faces: &[ScreenFace],
+    width: i32,
+    height: i32,
+    tile_size: i32,
+    filename: &str,
+) -> io::Result<()> {
+    let mut writer = TGAStreamWriter::create(filename, width, height, Format::RGB, true, true)?;
+    let mut zbuffer = vec![i32::MIN; tile_size as usize * tile_size as usize];
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            let tile = Tile {
+                x,
+                y,
+                w: tile_size.min(width - x),
+                h: tile_size.min(height - y),
+            };
+            let mut image = TGAImage::new(tile.w, tile.h, Format::RGB);
+            zbuffer.fill(i32::MIN);
+            draw_faces(faces, &tile, &mut zbuffer, &mut image);
+            writer.write_tile(x, &image)?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[allow(dead_code)]
 fn world_to_screen(v: Vec3f, width: usize, height: usize) -> Vec3f {
-    let x = ((v.x + 1.0) * (width as f32) / 2.0 + 0.5).floor() as f32;
-    let y = ((v.y + 1.0) * (height as f32) / 2.0 + 0.5).floor() as f32;
+    let x = ((v.x + 1.0) * (width as f32) / 2.0 + 0.5).floor();
+    let y = ((v.y + 1.0) * (height as f32) / 2.0 + 0.5).floor();
     Vec3f::new(x, y, v.z)
 }
 
+#[allow(dead_code)]
 fn barycentric(a: Vec3f, b: Vec3f, c: Vec3f, p: Vec3f) -> Vec3f {
     let mut s = [Vec3f::new(0.0, 0.0, 0.0); 2];
     for i in (0..2).rev() {
@@ -126,17 +499,18 @@ fn barycentric(a: Vec3f, b: Vec3f, c: Vec3f, p: Vec3f) -> Vec3f {
     Vec3f::new(-1.0, 1.0, 1.0)
 }
 
+#[allow(dead_code)]
 fn triangle_raster(pts: Vec<Vec3f>, zbuffer: &mut [f32], image: &mut TGAImage, color: TGAColor) {
     let mut bbox_min = Vec2f::new(f32::MAX, f32::MAX);
     let mut bbox_max = Vec2f::new(f32::MIN, f32::MIN);
 
     let clamp = Vec2f::new((IMAGE_WIDTH - 1) as f32, (IMAGE_HEIGHT - 1) as f32);
 
-    for i in 0..3 {
+    for pt in pts.iter().take(3) {
         for j in 0..2 {
             let val = match j {
-                0 => pts[i].x,
-                1 => pts[i].y,
+                0 => pt.x,
+                1 => pt.y,
                 _ => unreachable!(),
             };
             let min_val = bbox_min.get(j).min(val).max(0.0);
@@ -163,17 +537,17 @@ fn triangle_raster(pts: Vec<Vec3f>, zbuffer: &mut [f32], image: &mut TGAImage, c
             }
 
             p.z = 0.0;
-            for i in 0..3 {
+            for (i, pt) in pts.iter().enumerate().take(3) {
                 let weight = match i {
                     0 => bc_screen.x,
                     1 => bc_screen.y,
                     2 => bc_screen.z,
                     _ => unreachable!(),
                 };
-                p.z += pts[i].z * weight;
+                p.z += pt.z * weight;
             }
 
-            let idx = (x + y * IMAGE_WIDTH as i32) as usize;
+            let idx = (x + y * IMAGE_WIDTH) as usize;
             if idx < zbuffer.len() && p.z > zbuffer[idx] {
                 zbuffer[idx] = p.z;
                 let _ = image.set(x as usize, y as usize, color);
@@ -182,17 +556,21 @@ fn triangle_raster(pts: Vec<Vec3f>, zbuffer: &mut [f32], image: &mut TGAImage, c
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn triangle_scanline(
     mut t0: Vec3i,
     mut t1: Vec3i,
     mut t2: Vec3i,
-    mut uv0: Vec2i,
-    mut uv1: Vec2i,
-    mut uv2: Vec2i,
+    mut uv0: Vec2f,
+    mut uv1: Vec2f,
+    mut uv2: Vec2f,
+    tile: &Tile,
     zbuffer: &mut [i32],
     image: &mut TGAImage,
-    model: &Model,
     intensity: f32,
+    texture: Option<&TGAImage>,
+    sampler: Sampler,
+    color: TGAColor,
 ) {
     if t0.y == t1.y && t0.y == t2.y {
         return;
@@ -210,8 +588,12 @@ fn triangle_scanline(
         std::mem::swap(&mut t1, &mut t2);
         std::mem::swap(&mut uv1, &mut uv2);
     }
-    let total_height = (t2.y - t0.y) as i32;
+    let total_height = t2.y - t0.y;
     for i in 0..total_height {
+        let y = t0.y + i;
+        if y < tile.y || y >= tile.y + tile.h {
+            continue;
+        }
         let second_half = i > (t1.y - t0.y) || t1.y == t0.y;
         let segment_height = if second_half {
             t2.y - t1.y
@@ -243,21 +625,24 @@ fn triangle_scanline(
             std::mem::swap(&mut uv_a, &mut uv_b);
         }
 
-        for j in (a.x as usize)..=(b.x as usize) {
+        for j in a.x.max(tile.x)..=b.x.min(tile.x + tile.w - 1) {
             let phi = if b.x == a.x {
                 1.0
             } else {
-                ((j as i32 - a.x) as f32 / (b.x - a.x) as f32) as f32
+                (j - a.x) as f32 / (b.x - a.x) as f32
             };
             let mut p = a + (b - a) * phi;
             let uv_p = uv_a + (uv_b - uv_a) * phi;
-            p.x = j as i32;
-            p.y = t0.y + i;
+            p.x = j - tile.x;
+            p.y = y - tile.y;
 
-            let idx = (p.x + p.y * IMAGE_WIDTH) as usize;
-            if idx < zbuffer.len() && zbuffer[idx] < p.z as i32 {
-                zbuffer[idx] = p.z as i32;
-                let color = model.diffuse(uv_p);
+            let idx = (p.x + p.y * tile.w) as usize;
+            if zbuffer[idx] < p.z {
+                zbuffer[idx] = p.z;
+                let color = match texture {
+                    Some(t) => sampler.sample(t, uv_p),
+                    None => color,
+                };
                 let _ = image.set(
                     p.x as usize,
                     p.y as usize,
@@ -272,6 +657,7 @@ fn triangle_scanline(
     }
 }
 
+#[allow(dead_code)]
 fn line(p0: Vec3i, p1: Vec3i, image: &mut TGAImage, color: TGAColor) {
     let mut x0 = p0.x;
     let mut y0 = p0.y;
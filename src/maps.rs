@@ -0,0 +1,282 @@
+use std::path::Path;
+
+use crate::geometry::{Vec2f, Vec3f};
+use crate::material::Material;
+use crate::model::Model;
+use crate::tga::{TGAColor, TGAImage};
+
+/// The textures a `Model` can carry besides its materials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureSlot {
+    Diffuse,
+    /// Tangent or object space normals, xyz stored as rgb.
+    Normal,
+    Specular,
+    /// Emission, added on top of the lit colour.
+    Glow,
+    Roughness,
+    Metallic,
+}
+
+impl TextureSlot {
+    pub const ALL: [TextureSlot; 6] = [
+        TextureSlot::Diffuse,
+        TextureSlot::Normal,
+        TextureSlot::Specular,
+        TextureSlot::Glow,
+        TextureSlot::Roughness,
+        TextureSlot::Metallic,
+    ];
+
+    /// File name suffixes of the tinyrenderer asset convention, e.g.
+    /// `african_head_nm.tga` next to `african_head.obj`, most preferred
+    /// first. Tangent space normal maps (`_nm_tangent`) win over object
+    /// space ones. Roughness and metallic maps have no agreed suffix and
+    /// must be loaded explicitly.
+    pub fn suffixes(self) -> &'static [&'static str] {
+        match self {
+            TextureSlot::Diffuse => &["_diffuse"],
+            TextureSlot::Normal => &["_nm_tangent", "_nm"],
+            TextureSlot::Specular => &["_spec"],
+            TextureSlot::Glow => &["_glow"],
+            TextureSlot::Roughness | TextureSlot::Metallic => &[],
+        }
+    }
+}
+
+impl Model {
+    pub fn map(&self, slot: TextureSlot) -> Option<&TGAImage> {
+        match slot {
+            TextureSlot::Diffuse => self.diffusemap.as_ref(),
+            TextureSlot::Normal => self.normalmap.as_ref(),
+            TextureSlot::Specular => self.specularmap.as_ref(),
+            TextureSlot::Glow => self.glowmap.as_ref(),
+            TextureSlot::Roughness => self.roughnessmap.as_ref(),
+            TextureSlot::Metallic => self.metallicmap.as_ref(),
+        }
+    }
+
+    pub fn set_map(&mut self, slot: TextureSlot, image: Option<TGAImage>) {
+        let field = match slot {
+            TextureSlot::Diffuse => &mut self.diffusemap,
+            TextureSlot::Normal => &mut self.normalmap,
+            TextureSlot::Specular => &mut self.specularmap,
+            TextureSlot::Glow => &mut self.glowmap,
+            TextureSlot::Roughness => &mut self.roughnessmap,
+            TextureSlot::Metallic => &mut self.metallicmap,
+        };
+        *field = image;
+    }
+
+    /// Loads a TGA file into `slot`. A failed load leaves the slot unchanged.
+    pub fn load_map(&mut self, slot: TextureSlot, filename: &str) -> bool {
+        match TGAImage::from_tga_file(filename) {
+            Some(img) => {
+                self.set_map(slot, Some(img));
+                eprintln!("texture file {} loading ok", filename);
+                true
+            }
+            None => {
+                eprintln!("texture file {} loading failed", filename);
+                false
+            }
+        }
+    }
+
+    /// Loads the first `<name><suffix>.tga` of every slot that exists next
+    /// to the OBJ file `obj_path`. Returns the slots that were filled.
+    pub fn discover_maps(&mut self, obj_path: &Path) -> Vec<TextureSlot> {
+        let stem = obj_path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let mut found = Vec::new();
+        for slot in TextureSlot::ALL {
+            for suffix in slot.suffixes() {
+                let file = obj_path.with_file_name(format!("{}{}.tga", stem, suffix));
+                if file.is_file() && self.load_map(slot, file.to_str().unwrap_or_default()) {
+                    found.push(slot);
+                    break;
+                }
+            }
+        }
+        found
+    }
+
+    /// Unit normal from the normal map, rgb mapped from 0..=255 onto -1..=1.
+    /// Without a normal map this is (0, 0, 1), the unperturbed tangent space normal.
+    pub fn normal(&self, uv: Vec2f) -> Vec3f {
+        let Some(map) = &self.normalmap else {
+            return Vec3f::new(0.0, 0.0, 1.0);
+        };
+        let c = self.sampler.sample(map, uv);
+        Vec3f::new(
+            c[2] as f32 / 255.0 * 2.0 - 1.0,
+            c[1] as f32 / 255.0 * 2.0 - 1.0,
+            c[0] as f32 / 255.0 * 2.0 - 1.0,
+        )
+        .normalized()
+    }
+
+    /// Specular exponent as stored in the map, 0..=255, like the original
+    /// tinyrenderer `_spec.tga` files. 0 without a specular map.
+    pub fn specular(&self, uv: Vec2f) -> f32 {
+        self.specularmap
+            .as_ref()
+            .map_or(0.0, |map| self.sampler.sample(map, uv)[0] as f32)
+    }
+
+    /// Emitted colour, black without a glow map.
+    pub fn glow(&self, uv: Vec2f) -> TGAColor {
+        match &self.glowmap {
+            Some(map) => self.sampler.sample(map, uv),
+            None => TGAColor::from_rgb(0, 0, 0),
+        }
+    }
+
+    /// Roughness in 0..=1 at a point of face `iface`. Colour maps are read
+    /// the glTF way, roughness in green, so a packed metallicRoughness
+    /// texture can fill both slots; gray maps hold it in their only channel.
+    /// Without a roughness map the face's material decides.
+    pub fn roughness(&self, iface: usize, uv: Vec2f) -> f32 {
+        match &self.roughnessmap {
+            Some(map) => self.channel(map, uv, 1),
+            None => self.material_factor(iface, uv, 1, |m| m.roughness, 1.0),
+        }
+    }
+
+    /// Metalness in 0..=1 at a point of face `iface`. Read from blue in
+    /// colour maps, as glTF packs it, or the only channel of gray maps.
+    /// Without a metallic map the face's material decides.
+    pub fn metallic(&self, iface: usize, uv: Vec2f) -> f32 {
+        match &self.metallicmap {
+            Some(map) => self.channel(map, uv, 0),
+            None => self.material_factor(iface, uv, 0, |m| m.metallic, 0.0),
+        }
+    }
+
+    /// The scalar of the face's material, times channel `index` of its
+    /// metallicRoughness texture if it has one. Faces without a material
+    /// get `default`, the value of `Material::new`.
+    fn material_factor(
+        &self,
+        iface: usize,
+        uv: Vec2f,
+        index: usize,
+        scalar: fn(&Material) -> f32,
+        default: f32,
+    ) -> f32 {
+        let Some(material) = self.material(iface) else {
+            return default;
+        };
+        let texel = material
+            .metallic_roughness_map
+            .as_ref()
+            .and_then(|t| t.image.as_ref())
+            .map_or(1.0, |map| self.channel(map, uv, index));
+        scalar(material) * texel
+    }
+
+    /// Channel `index` of the b, g, r colour scaled to 0..=1, the value of
+    /// gray maps whatever the index.
+    fn channel(&self, map: &TGAImage, uv: Vec2f, index: usize) -> f32 {
+        let c = self.sampler.sample(map, uv);
+        let index = if map.bpp == 1 { 0 } else { index };
+        c[index] as f32 / 255.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec3i;
+    use crate::material::TextureMap;
+    use crate::tga::Format;
+
+    fn triangle() -> Model {
+        let verts = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        ];
+        let faces = vec![(0..3).map(|v| Vec3i::new(v, -1, -1)).collect()];
+        Model::from_triangles(verts, Vec::new(), Vec::new(), faces)
+    }
+
+    fn pixel(format: Format, color: TGAColor) -> TGAImage {
+        let mut image = TGAImage::new(1, 1, format);
+        image.set(0, 0, color).unwrap();
+        image
+    }
+
+    #[test]
+    fn discovers_maps_by_suffix() {
+        let dir = std::env::temp_dir().join(format!("maps_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, width: i32| {
+            let path = dir.join(name);
+            TGAImage::new(width, 1, Format::RGB)
+                .write_tga_file(path.to_str().unwrap(), true, false)
+                .unwrap();
+        };
+        write("head_diffuse.tga", 1);
+        // tangent space wins over object space normals
+        write("head_nm.tga", 2);
+        write("head_nm_tangent.tga", 3);
+        write("head_glow.tga", 4);
+        // belongs to another model
+        write("body_spec.tga", 5);
+
+        let mut model = triangle();
+        let found = model.discover_maps(&dir.join("head.obj"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            found,
+            [TextureSlot::Diffuse, TextureSlot::Normal, TextureSlot::Glow]
+        );
+        let width = |slot| model.map(slot).map(|m| m.width());
+        assert_eq!(width(TextureSlot::Normal), Some(3));
+        assert_eq!(width(TextureSlot::Glow), Some(4));
+        assert_eq!(width(TextureSlot::Specular), None);
+    }
+
+    #[test]
+    fn reads_roughness_from_green_and_metalness_from_blue() {
+        let uv = Vec2f::new(0.5, 0.5);
+        let mut model = triangle();
+        let packed = pixel(Format::RGB, TGAColor::from_rgb(0, 51, 255));
+        model.set_map(TextureSlot::Roughness, Some(packed.clone()));
+        model.set_map(TextureSlot::Metallic, Some(packed));
+        assert_eq!(model.roughness(0, uv), 0.2);
+        assert_eq!(model.metallic(0, uv), 1.0);
+
+        let gray = pixel(Format::Grayscale, TGAColor::from_rgb(102, 102, 102));
+        model.set_map(TextureSlot::Roughness, Some(gray.clone()));
+        model.set_map(TextureSlot::Metallic, Some(gray));
+        assert_eq!(model.roughness(0, uv), 0.4);
+        assert_eq!(model.metallic(0, uv), 0.4);
+    }
+
+    #[test]
+    fn falls_back_to_the_material() {
+        let uv = Vec2f::new(0.5, 0.5);
+        let mut model = triangle();
+        // no material: fully rough, not metallic
+        assert_eq!(model.roughness(0, uv), 1.0);
+        assert_eq!(model.metallic(0, uv), 0.0);
+
+        let mut material = Material::new("metal");
+        material.roughness = 0.5;
+        material.metallic = 0.8;
+        model.materials.push(material);
+        model.face_info[0].material = Some(0);
+        assert_eq!(model.roughness(0, uv), 0.5);
+        assert_eq!(model.metallic(0, uv), 0.8);
+
+        // the factors scale the packed texture
+        model.materials[0].metallic_roughness_map = Some(TextureMap {
+            name: String::from("packed.tga"),
+            image: Some(pixel(Format::RGB, TGAColor::from_rgb(0, 102, 51))),
+        });
+        assert_eq!(model.roughness(0, uv), 0.5 * 0.4);
+        assert_eq!(model.metallic(0, uv), 0.8 * 0.2);
+    }
+}
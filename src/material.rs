@@ -0,0 +1,343 @@
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::geometry::Vec3f;
+use crate::tga::TGAImage;
+
+/// A texture referenced from a material. `name` is the path as written in
+/// the MTL file, `image` is `None` when it could not be loaded.
+#[derive(Debug, Clone)]
+pub struct TextureMap {
+    pub name: String,
+    pub image: Option<TGAImage>,
+}
+
+impl TextureMap {
+    /// Loads `name` relative to `dir`. Only TGA images are supported.
+    pub fn load(dir: &Path, name: &str) -> Self {
+        let path = dir.join(name);
+        let image = TGAImage::from_tga_file(path.to_str().unwrap_or(name));
+        match image {
+            Some(_) => eprintln!("texture file {} loading ok", path.display()),
+            None => eprintln!("texture file {} loading failed", path.display()),
+        }
+        TextureMap {
+            name: name.to_string(),
+            image,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: String,
+    /// Ka
+    pub ambient: Vec3f,
+    /// Kd
+    pub diffuse: Vec3f,
+    /// Ks
+    pub specular: Vec3f,
+    /// Ns
+    pub shininess: f32,
+    /// d, or 1 - Tr
+    pub dissolve: f32,
+    pub illum: i32,
+    /// map_Kd
+    pub diffuse_map: Option<TextureMap>,
+    /// map_Ks
+    pub specular_map: Option<TextureMap>,
+    /// map_Bump, bump or norm
+    pub normal_map: Option<TextureMap>,
+    /// map_d
+    pub alpha_map: Option<TextureMap>,
+    /// Ke
+    pub emissive: Vec3f,
+    /// map_Ke
+    pub emissive_map: Option<TextureMap>,
+    /// Pm
+    pub metallic: f32,
+    /// Pr
+    pub roughness: f32,
+    /// glTF metallicRoughnessTexture, roughness in green and metalness in blue
+    pub metallic_roughness_map: Option<TextureMap>,
+    /// glTF occlusionTexture
+    pub occlusion_map: Option<TextureMap>,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            ambient: Vec3f::new(0.0, 0.0, 0.0),
+            diffuse: Vec3f::new(1.0, 1.0, 1.0),
+            specular: Vec3f::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            dissolve: 1.0,
+            illum: 2,
+            diffuse_map: None,
+            specular_map: None,
+            normal_map: None,
+            alpha_map: None,
+            emissive: Vec3f::new(0.0, 0.0, 0.0),
+            emissive_map: None,
+            metallic: 0.0,
+            roughness: 1.0,
+            metallic_roughness_map: None,
+            occlusion_map: None,
+        }
+    }
+}
+
+fn parse_color<'a>(parts: impl Iterator<Item = &'a str>) -> Option<Vec3f> {
+    let c: Vec<f32> = parts.take(3).filter_map(|x| x.parse().ok()).collect();
+    match c.len() {
+        // a single value means gray
+        1 => Some(Vec3f::new(c[0], c[0], c[0])),
+        3 => Some(Vec3f::new(c[0], c[1], c[2])),
+        _ => None,
+    }
+}
+
+/// File name of a map statement, everything after the options, so names
+/// with spaces survive: `-s 1 1 1 -clamp on my texture.tga`.
+fn map_file(args: &str) -> Option<&str> {
+    fn token(s: &str) -> (&str, &str) {
+        let s = s.trim_start();
+        let end = s.find(char::is_whitespace).unwrap_or(s.len());
+        (&s[..end], &s[end..])
+    }
+    let mut rest = args.trim_start();
+    while let Some(option) = rest.strip_prefix('-') {
+        let (name, after) = token(option);
+        // -o, -s and -t take one to three numbers, -mm two, the rest one value
+        let (min, max) = match name {
+            "o" | "s" | "t" => (1, 3),
+            "mm" => (2, 2),
+            _ => (1, 1),
+        };
+        rest = after;
+        for i in 0..max {
+            let (value, after) = token(rest);
+            if value.is_empty() || (i >= min && value.parse::<f32>().is_err()) {
+                break;
+            }
+            rest = after;
+        }
+        rest = rest.trim_start();
+    }
+    let name = rest.trim_end();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Reads every material of an MTL file. Texture maps are loaded relative to
+/// `texture_dir`, normally the directory of the OBJ file.
+pub fn load_mtl(filename: &Path, texture_dir: &Path) -> io::Result<Vec<Material>> {
+    let dir = texture_dir;
+    let reader = io::BufReader::new(File::open(filename)?);
+    let mut materials: Vec<Material> = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let mut parts = line.split_whitespace();
+        let Some(first) = parts.next() else {
+            continue;
+        };
+        if first == "newmtl" {
+            let name = parts.collect::<Vec<_>>().join(" ");
+            materials.push(Material::new(&name));
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+        let args = &line.trim_start()[first.len()..];
+        let map = || map_file(args).map(|name| TextureMap::load(dir, name));
+        match first {
+            "Ka" => material.ambient = parse_color(parts).unwrap_or(material.ambient),
+            "Kd" => material.diffuse = parse_color(parts).unwrap_or(material.diffuse),
+            "Ks" => material.specular = parse_color(parts).unwrap_or(material.specular),
+            "Ke" => material.emissive = parse_color(parts).unwrap_or(material.emissive),
+            "Ns" => {
+                if let Some(ns) = parts.next().and_then(|x| x.parse().ok()) {
+                    material.shininess = ns;
+                }
+            }
+            "d" => {
+                if let Some(d) = parts.next().and_then(|x| x.parse().ok()) {
+                    material.dissolve = d;
+                }
+            }
+            "Tr" => {
+                if let Some(tr) = parts.next().and_then(|x| x.parse::<f32>().ok()) {
+                    material.dissolve = 1.0 - tr;
+                }
+            }
+            "Pm" => {
+                if let Some(pm) = parts.next().and_then(|x| x.parse().ok()) {
+                    material.metallic = pm;
+                }
+            }
+            "Pr" => {
+                if let Some(pr) = parts.next().and_then(|x| x.parse().ok()) {
+                    material.roughness = pr;
+                }
+            }
+            "illum" => {
+                if let Some(illum) = parts.next().and_then(|x| x.parse().ok()) {
+                    material.illum = illum;
+                }
+            }
+            "map_Kd" => material.diffuse_map = map(),
+            "map_Ks" => material.specular_map = map(),
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_map = map(),
+            "map_d" => material.alpha_map = map(),
+            "map_Ke" => material.emissive_map = map(),
+            _ => {}
+        }
+    }
+
+    eprintln!(
+        "material library {} has {} materials",
+        filename.display(),
+        materials.len()
+    );
+    Ok(materials)
+}
+
+/// Writes materials in MTL format. Texture maps keep the names they were
+/// loaded with, so they resolve when the file stays next to the textures.
+pub fn write_mtl(filename: &Path, materials: &[Material]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(File::create(filename)?);
+    let color = |v: Vec3f| format!("{} {} {}", v.x, v.y, v.z);
+    for (i, m) in materials.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "newmtl {}", m.name)?;
+        writeln!(out, "Ka {}", color(m.ambient))?;
+        writeln!(out, "Kd {}", color(m.diffuse))?;
+        writeln!(out, "Ks {}", color(m.specular))?;
+        writeln!(out, "Ke {}", color(m.emissive))?;
+        writeln!(out, "Ns {}", m.shininess)?;
+        writeln!(out, "d {}", m.dissolve)?;
+        writeln!(out, "Pm {}", m.metallic)?;
+        writeln!(out, "Pr {}", m.roughness)?;
+        writeln!(out, "illum {}", m.illum)?;
+        let maps = [
+            ("map_Kd", &m.diffuse_map),
+            ("map_Ks", &m.specular_map),
+            ("map_Bump", &m.normal_map),
+            ("map_d", &m.alpha_map),
+            ("map_Ke", &m.emissive_map),
+        ];
+        for (keyword, map) in maps {
+            if let Some(map) = map {
+                writeln!(out, "{} {}", keyword, map.name)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Model, ParseMode};
+
+    /// Writes `files` into a fresh temporary directory and returns it.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            std::fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn map_file_names_follow_the_options() {
+        assert_eq!(map_file(" diffuse.tga"), Some("diffuse.tga"));
+        assert_eq!(map_file(" my texture.tga "), Some("my texture.tga"));
+        assert_eq!(map_file(" -s 1 1 1 my texture.tga"), Some("my texture.tga"));
+        assert_eq!(map_file(" -o 0.5 -bm 2 bump.tga"), Some("bump.tga"));
+        assert_eq!(
+            map_file(" -clamp on -mm 0 1 -t 0.1 0.2 tex/a b.tga"),
+            Some("tex/a b.tga")
+        );
+        // a number of options but no file
+        assert_eq!(map_file(" -s 2 2"), None);
+        assert_eq!(map_file(""), None);
+    }
+
+    #[test]
+    fn reads_materials() {
+        let mtl = "# two materials
+newmtl red
+Kd 1 0 0
+Ks 0.5
+Ns 32
+Tr 0.25
+map_Kd -s 1 1 1 red paint.tga
+newmtl shiny metal
+d 0.5
+Pm 1
+Pr 0.2
+illum 3
+norm -bm 0.5 metal_nm.tga
+";
+        let dir = write_files("mtl_parse", &[("lib.mtl", mtl)]);
+        let materials = load_mtl(&dir.join("lib.mtl"), &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(materials.len(), 2);
+        let red = &materials[0];
+        assert_eq!(red.name, "red");
+        assert_eq!(red.diffuse, Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(red.specular, Vec3f::new(0.5, 0.5, 0.5));
+        assert_eq!(red.shininess, 32.0);
+        assert_eq!(red.dissolve, 0.75);
+        let map = red.diffuse_map.as_ref().unwrap();
+        assert_eq!(map.name, "red paint.tga");
+        assert!(map.image.is_none());
+
+        let metal = &materials[1];
+        assert_eq!(metal.name, "shiny metal");
+        assert_eq!(metal.dissolve, 0.5);
+        assert_eq!(
+            (metal.metallic, metal.roughness, metal.illum),
+            (1.0, 0.2, 3)
+        );
+        assert_eq!(metal.normal_map.as_ref().unwrap().name, "metal_nm.tga");
+        assert_eq!(metal.diffuse, Vec3f::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn resolves_usemtl_before_mtllib_and_unknown_names() {
+        let obj = "v 0 0 0
+v 1 0 0
+v 0 1 0
+usemtl red
+f 1 2 3
+mtllib lib.mtl
+usemtl missing
+f 1 2 3
+usemtl
+f 1 2 3
+";
+        let dir = write_files(
+            "mtl_usemtl",
+            &[("lib.mtl", "newmtl red\nKd 1 0 0\n"), ("a.obj", obj)],
+        );
+        let model = Model::open(dir.join("a.obj").to_str().unwrap(), ParseMode::Strict);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // materials resolve once the whole file is read, and an unknown
+        // name is only a warning, even in strict mode
+        let model = model.unwrap();
+        assert_eq!(model.nfaces(), 3);
+        assert_eq!(model.material(0).map(|m| m.name.as_str()), Some("red"));
+        assert!(model.material(1).is_none());
+        assert!(model.material(2).is_none());
+    }
+}
@@ -1,129 +1,518 @@
+use std::collections::BTreeMap;
+use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::Range;
 use std::path::Path;
 
-use crate::geometry::{Vec2f, Vec2i, Vec3f, Vec3i};
+use crate::geometry::{Vec2f, Vec3f, Vec3i, Vec4f};
+use crate::maps::TextureSlot;
+use crate::material::{load_mtl, Material};
+use crate::ply::ListAttribute;
+use crate::sampler::Sampler;
 use crate::tga::{TGAColor, TGAImage};
 
 #[derive(Debug)]
+pub enum ModelError {
+    Io {
+        file: String,
+        source: io::Error,
+    },
+    Parse {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Io { file, source } => write!(f, "{}: {}", file, source),
+            ModelError::Parse { file, line, reason } => write!(f, "{}:{}: {}", file, line, reason),
+        }
+    }
+}
+
+impl Error for ModelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModelError::Io { source, .. } => Some(source),
+            ModelError::Parse { .. } => None,
+        }
+    }
+}
+
+/// What to do with malformed lines and out of range indices.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParseMode {
+    /// Fail on the first problem.
+    Strict,
+    /// Warn, skip the offending line or face and keep going.
+    #[default]
+    Lenient,
+}
+
+pub(crate) struct Diagnostics<'a> {
+    pub(crate) file: &'a str,
+    pub(crate) mode: ParseMode,
+}
+
+impl Diagnostics<'_> {
+    pub(crate) fn report(&self, line: usize, reason: String) -> Result<(), ModelError> {
+        match self.mode {
+            ParseMode::Strict => Err(ModelError::Parse {
+                file: self.file.to_string(),
+                line,
+                reason,
+            }),
+            ParseMode::Lenient => {
+                self.warn(line, reason);
+                Ok(())
+            }
+        }
+    }
+
+    /// Warns about a problem that is not fatal even in strict mode.
+    pub(crate) fn warn(&self, line: usize, reason: String) {
+        eprintln!("warning: {}:{}: {}", self.file, line, reason);
+    }
+}
+
+/// Which optional attributes every corner of a face references.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FaceAttributes {
+    pub uv: bool,
+    pub normal: bool,
+}
+
+/// A run of faces that share an OBJ object (`o`), group (`g`) and material.
+#[derive(Debug, Clone)]
+pub struct Submesh {
+    /// Group name, or the object name for faces outside any group.
+    pub name: String,
+    pub object: String,
+    pub faces: Range<usize>,
+    pub material: Option<usize>,
+    pub visible: bool,
+}
+
+/// Per triangle data besides the corner indices.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct FaceInfo {
+    // polygon of the OBJ file the triangle was cut from
+    pub(crate) source: usize,
+    pub(crate) attributes: FaceAttributes,
+    pub(crate) material: Option<usize>,
+    // OBJ smoothing group, 0 when off
+    pub(crate) smoothing: u32,
+}
+
+#[derive(Debug, Clone)]
 pub struct Model {
-    verts: Vec<Vec3f>,
-    faces: Vec<Vec<Vec3i>>,
-    norms: Vec<Vec3f>,
-    uv: Vec<Vec2f>,
+    pub(crate) verts: Vec<Vec3f>,
+    pub(crate) faces: Vec<Vec<Vec3i>>,
+    pub(crate) norms: Vec<Vec3f>,
+    pub(crate) uv: Vec<Vec2f>,
+    pub(crate) face_info: Vec<FaceInfo>,
+    pub(crate) materials: Vec<Material>,
+    pub(crate) submeshes: Vec<Submesh>,
+    // whether the OBJ file had any `s` statements
+    pub(crate) has_smoothing_groups: bool,
+    // per corner tangent frames, empty until `compute_tangents`
+    pub(crate) tangents: Vec<[Vec4f; 3]>,
+    // per position rgba from PLY files, empty otherwise
+    pub(crate) colors: Vec<Vec4f>,
+    pub(crate) vertex_attributes: BTreeMap<String, Vec<f32>>,
+    pub(crate) polygon_attributes: BTreeMap<String, Vec<f32>>,
+    pub(crate) vertex_lists: BTreeMap<String, ListAttribute>,
+    pub(crate) polygon_lists: BTreeMap<String, ListAttribute>,
     pub diffusemap: Option<TGAImage>,
+    pub normalmap: Option<TGAImage>,
+    pub specularmap: Option<TGAImage>,
+    pub glowmap: Option<TGAImage>,
+    pub roughnessmap: Option<TGAImage>,
+    pub metallicmap: Option<TGAImage>,
+    /// Used by `diffuse` and the other map lookups.
+    pub sampler: Sampler,
 }
 
 impl Model {
-    pub fn new(filename: &str) -> io::Result<Self> {
+    /// Loads an OBJ file, skipping malformed lines with a warning.
+    pub fn new(filename: &str) -> Result<Self, ModelError> {
+        Model::load(filename, ParseMode::Lenient)
+    }
+
+    /// Loads any supported format, picked by the file extension. Unknown
+    /// extensions are read as OBJ.
+    pub fn open(filename: &str, mode: ParseMode) -> Result<Self, ModelError> {
+        let ext = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match ext.as_str() {
+            "stl" => Model::load_stl(filename, mode),
+            "ply" => Model::load_ply(filename, mode),
+            "gltf" | "glb" => Model::load_gltf_scene(filename, mode),
+            _ => Model::load(filename, mode),
+        }
+    }
+
+    pub fn load(filename: &str, mode: ParseMode) -> Result<Self, ModelError> {
+        let diag = Diagnostics {
+            file: filename,
+            mode,
+        };
+        let io_error = |source| ModelError::Io {
+            file: filename.to_string(),
+            source,
+        };
         let mut verts = Vec::new();
-        let mut faces = Vec::new();
+        let mut polygons = Vec::new();
         let mut norms = Vec::new();
         let mut uv = Vec::new();
 
-        let file = File::open(filename)?;
+        let mut materials: Vec<Material> = Vec::new();
+        // names given to `usemtl` with the line they first appear on, looked
+        // up once the whole file is read so `mtllib` may come later
+        let mut material_names: Vec<(String, usize)> = Vec::new();
+        let mut polygon_material = Vec::new();
+        let mut current_material = None;
+        let mut object = String::from("default");
+        let mut group: Option<String> = None;
+        // submesh of every polygon, with an empty face range until triangulation
+        let mut submeshes: Vec<Submesh> = Vec::new();
+        let mut polygon_submesh = Vec::new();
+        let mut polygon_line = Vec::new();
+        let mut polygon_smoothing = Vec::new();
+        let mut smoothing = 0;
+        let mut has_smoothing_groups = false;
+
+        let file = File::open(filename).map_err(io_error)?;
         let reader = io::BufReader::new(file);
+        let dir = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
 
-        for line in reader.lines() {
-            let line = line?;
+        for (lineno, line) in reader.lines().enumerate() {
+            let line = line.map_err(io_error)?;
+            let lineno = lineno + 1;
+            // comments may follow the data on the same line
+            let line = line.split('#').next().unwrap_or_default();
             let mut parts = line.split_whitespace();
             if let Some(first) = parts.next() {
                 match first {
-                    "v" => {
-                        let coords: Vec<f32> = parts
-                            .take(3)
-                            .filter_map(|x| x.parse::<f32>().ok())
-                            .collect();
-                        if coords.len() == 3 {
-                            verts.push(Vec3f::new(coords[0], coords[1], coords[2]));
-                        }
-                    }
+                    // x y z, then an optional w or an r g b vertex colour
+                    "v" => match parse_floats(parts, 3, 7) {
+                        Ok(c) => verts.push(Vec3f::new(c[0], c[1], c[2])),
+                        Err(reason) => diag.report(lineno, reason)?,
+                    },
                     "f" => {
                         let mut face = Vec::new();
+                        let mut bad = None;
                         for part in parts {
-                            let indices: Vec<_> = part.split('/').collect();
-                            if indices.len() >= 3 {
-                                let v_idx = indices[0].parse::<i32>().unwrap_or(0) - 1;
-                                let vt_idx = indices[1].parse::<i32>().unwrap_or(0) - 1;
-                                let vn_idx = indices[2].parse::<i32>().unwrap_or(0) - 1;
-                                face.push(Vec3i::new(v_idx, vt_idx, vn_idx));
+                            match parse_corner(part, verts.len(), uv.len(), norms.len()) {
+                                Ok(corner) => face.push(corner),
+                                Err(reason) => {
+                                    bad = Some(reason);
+                                    break;
+                                }
                             }
                         }
-                        faces.push(face);
-                    }
-                    "vn" => {
-                        let coords: Vec<f32> = parts
-                            .take(3)
-                            .filter_map(|x| x.parse::<f32>().ok())
-                            .collect();
-                        if coords.len() == 3 {
-                            norms.push(Vec3f::new(coords[0], coords[1], coords[2]));
+                        if bad.is_none() && face.len() < 3 {
+                            bad = Some(format!("face has {} corners, need 3", face.len()));
                         }
+                        if let Some(reason) = bad {
+                            diag.report(lineno, reason)?;
+                            continue;
+                        }
+                        polygons.push(face);
+                        polygon_line.push(lineno);
+                        polygon_smoothing.push(smoothing);
+                        polygon_material.push(current_material);
+
+                        let name = group.clone().unwrap_or_else(|| object.clone());
+                        let same = submeshes.last().is_some_and(|m| {
+                            m.name == name && m.object == object && m.material == current_material
+                        });
+                        if !same {
+                            submeshes.push(Submesh {
+                                name,
+                                object: object.clone(),
+                                faces: 0..0,
+                                material: current_material,
+                                visible: true,
+                            });
+                        }
+                        polygon_submesh.push(submeshes.len() - 1);
+                    }
+                    "s" => {
+                        has_smoothing_groups = true;
+                        smoothing = match parts.next() {
+                            Some("off") | None => 0,
+                            Some(group) => match group.parse() {
+                                Ok(group) => group,
+                                Err(_) => {
+                                    diag.report(
+                                        lineno,
+                                        format!("invalid smoothing group {:?}", group),
+                                    )?;
+                                    0
+                                }
+                            },
+                        };
+                    }
+                    "o" => {
+                        object = parts.collect::<Vec<_>>().join(" ");
+                        group = None;
+                    }
+                    "g" => {
+                        let name = parts.collect::<Vec<_>>().join(" ");
+                        group = if name.is_empty() { None } else { Some(name) };
                     }
-                    "vt" => {
-                        let coords: Vec<f32> = parts
-                            .take(2)
-                            .filter_map(|x| x.parse::<f32>().ok())
-                            .collect();
-                        if coords.len() == 2 {
-                            uv.push(Vec2f::new(coords[0], coords[1]));
+                    "mtllib" => {
+                        for name in parts {
+                            match load_mtl(&dir.join(name), dir) {
+                                Ok(lib) => materials.extend(lib),
+                                Err(err) => {
+                                    eprintln!("material library {} loading failed: {}", name, err)
+                                }
+                            }
                         }
                     }
+                    "usemtl" => {
+                        let name = parts.collect::<Vec<_>>().join(" ");
+                        // a bare `usemtl` goes back to no material
+                        current_material = if name.is_empty() {
+                            None
+                        } else if let Some(i) = material_names.iter().position(|(n, _)| *n == name)
+                        {
+                            Some(i)
+                        } else {
+                            material_names.push((name, lineno));
+                            Some(material_names.len() - 1)
+                        };
+                    }
+                    "vn" => match parse_floats(parts, 3, 3) {
+                        Ok(c) => norms.push(Vec3f::new(c[0], c[1], c[2])),
+                        Err(reason) => diag.report(lineno, reason)?,
+                    },
+                    "vt" => match parse_floats(parts, 1, 3) {
+                        Ok(c) => uv.push(Vec2f::new(c[0], c.get(1).copied().unwrap_or(0.0))),
+                        Err(reason) => diag.report(lineno, reason)?,
+                    },
                     _ => {}
                 }
             }
         }
-        eprintln!(
-            "# v# {} f# {} n# {} uv# {}",
-            verts.len(),
-            faces.len(),
-            norms.len(),
-            uv.len()
-        );
-        Ok(Model {
+
+        let resolved: Vec<Option<usize>> = material_names
+            .iter()
+            .map(|(name, lineno)| {
+                let found = materials.iter().position(|m| m.name == *name);
+                if found.is_none() {
+                    diag.warn(*lineno, format!("unknown material {}", name));
+                }
+                found
+            })
+            .collect();
+        let resolve = |m: Option<usize>| m.and_then(|m| resolved[m]);
+        for submesh in &mut submeshes {
+            submesh.material = resolve(submesh.material);
+        }
+
+        // check every index up front so bad assets fail here and not mid-render
+        for (polygon, &lineno) in polygons.iter_mut().zip(&polygon_line) {
+            let out_of_range = polygon.iter().find_map(|c| {
+                if c.x as usize >= verts.len() {
+                    Some(format!(
+                        "vertex index {} out of range, {} vertices",
+                        c.x + 1,
+                        verts.len()
+                    ))
+                } else if c.y >= 0 && c.y as usize >= uv.len() {
+                    Some(format!(
+                        "texture index {} out of range, {} texture coordinates",
+                        c.y + 1,
+                        uv.len()
+                    ))
+                } else if c.z >= 0 && c.z as usize >= norms.len() {
+                    Some(format!(
+                        "normal index {} out of range, {} normals",
+                        c.z + 1,
+                        norms.len()
+                    ))
+                } else {
+                    None
+                }
+            });
+            if let Some(reason) = out_of_range {
+                diag.report(lineno, reason)?;
+                // dropped by the corner count check below
+                polygon.clear();
+            }
+        }
+
+        let mut faces = Vec::new();
+        let mut face_info = Vec::new();
+        for (ipoly, polygon) in polygons.iter().enumerate() {
+            if polygon.len() < 3 {
+                continue;
+            }
+            let attributes = FaceAttributes {
+                uv: polygon.iter().all(|c| c.y >= 0),
+                normal: polygon.iter().all(|c| c.z >= 0),
+            };
+            for [a, b, c] in triangulate(&verts, polygon) {
+                faces.push(vec![polygon[a], polygon[b], polygon[c]]);
+                face_info.push(FaceInfo {
+                    source: ipoly,
+                    attributes,
+                    material: resolve(polygon_material[ipoly]),
+                    smoothing: polygon_smoothing[ipoly],
+                });
+
+                let submesh = &mut submeshes[polygon_submesh[ipoly]];
+                if submesh.faces.is_empty() {
+                    submesh.faces = faces.len() - 1..faces.len();
+                } else {
+                    submesh.faces.end = faces.len();
+                }
+            }
+        }
+
+        // polygons with fewer than three corners can leave a submesh empty
+        submeshes.retain(|m| !m.faces.is_empty());
+
+        let mut model = Model::from_triangles(verts, norms, uv, faces);
+        model.face_info = face_info;
+        model.materials = materials;
+        model.submeshes = submeshes;
+        model.has_smoothing_groups = has_smoothing_groups;
+        model.print_summary();
+        Ok(model)
+    }
+
+    /// Builds a model from triangles of (position, uv, normal) indices, -1
+    /// marking a missing attribute. All faces go into one visible submesh
+    /// named "default" without material. Used by the importers.
+    pub(crate) fn from_triangles(
+        verts: Vec<Vec3f>,
+        norms: Vec<Vec3f>,
+        uv: Vec<Vec2f>,
+        faces: Vec<Vec<Vec3i>>,
+    ) -> Model {
+        let face_info = faces
+            .iter()
+            .enumerate()
+            .map(|(i, face)| FaceInfo {
+                source: i,
+                attributes: FaceAttributes {
+                    uv: face.iter().all(|c| c.y >= 0),
+                    normal: face.iter().all(|c| c.z >= 0),
+                },
+                material: None,
+                smoothing: 0,
+            })
+            .collect();
+        let submeshes = if faces.is_empty() {
+            Vec::new()
+        } else {
+            vec![Submesh {
+                name: String::from("default"),
+                object: String::from("default"),
+                faces: 0..faces.len(),
+                material: None,
+                visible: true,
+            }]
+        };
+        Model {
             verts,
             faces,
             norms,
             uv,
+            face_info,
+            materials: Vec::new(),
+            submeshes,
+            has_smoothing_groups: false,
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            vertex_attributes: BTreeMap::new(),
+            polygon_attributes: BTreeMap::new(),
+            vertex_lists: BTreeMap::new(),
+            polygon_lists: BTreeMap::new(),
             diffusemap: None,
-        })
+            normalmap: None,
+            specularmap: None,
+            glowmap: None,
+            roughnessmap: None,
+            metallicmap: None,
+            sampler: Sampler::default(),
+        }
     }
 
-    pub fn load_texture(&mut self, filename: &str) {
-        let texfile = Path::new(filename);
+    /// Drops the faces not marked in `keep` together with their face data,
+    /// shrinking the submesh ranges to match.
+    pub(crate) fn retain_faces(&mut self, keep: &[bool]) {
+        let mut first = Vec::with_capacity(keep.len() + 1);
+        let mut next = 0;
+        for &k in keep {
+            first.push(next);
+            next += k as usize;
+        }
+        first.push(next);
+        self.remap_submeshes(&first);
+        let mut flags = keep.iter();
+        self.faces.retain(|_| *flags.next().unwrap_or(&false));
+        let mut flags = keep.iter();
+        self.face_info.retain(|_| *flags.next().unwrap_or(&false));
+        if self.tangents.len() == keep.len() {
+            let mut flags = keep.iter();
+            self.tangents.retain(|_| *flags.next().unwrap_or(&false));
+        }
+    }
 
-        match TGAImage::from_tga_file(texfile.to_str().unwrap()) {
-            Some(img) => {
-                self.diffusemap = Some(img);
-                eprintln!("texture file {} loading ok", texfile.display());
-            }
-            None => {
-                eprintln!("texture file {} loading failed", texfile.display());
-            }
+    /// Moves the submesh ranges onto a new list of faces, where the faces
+    /// that replace face `f` start at `first[f]`. `first` has one more
+    /// entry for the end of the last face.
+    pub(crate) fn remap_submeshes(&mut self, first: &[usize]) {
+        for submesh in &mut self.submeshes {
+            submesh.faces = first[submesh.faces.start]..first[submesh.faces.end];
         }
     }
 
-    pub fn diffuse(&self, uv: Vec2i) -> TGAColor {
+    pub(crate) fn print_summary(&self) {
+        eprintln!(
+            "# v# {} f# {} n# {} uv# {} submeshes# {}",
+            self.verts.len(),
+            self.faces.len(),
+            self.norms.len(),
+            self.uv.len(),
+            self.submeshes.len()
+        );
+    }
+
+    pub fn load_texture(&mut self, filename: &str) {
+        self.load_map(TextureSlot::Diffuse, filename);
+    }
+
+    pub fn diffuse(&self, uv: Vec2f) -> TGAColor {
         if let Some(ref map) = self.diffusemap {
-            map.get(uv.x, uv.y)
-                .unwrap_or_else(|| TGAColor::from_bpp(map.bpp))
+            self.sampler.sample(map, uv)
         } else {
             TGAColor::from_bpp(3)
         }
     }
 
-    pub fn uv(&self, iface: usize, nthvert: usize) -> Vec2i {
-        let idx = (self.faces[iface][nthvert].y) as usize;
-        let uv_idx = self.uv[idx];
-        if let Some(diffusemap) = &self.diffusemap {
-            Vec2i {
-                x: (uv_idx.x * diffusemap.width() as f32) as i32,
-                y: (uv_idx.y * diffusemap.height() as f32) as i32,
-            }
-        } else {
-            return Vec2i { x: 0, y: 0 };
+    /// Texture coordinates of a corner, (0, 0) when the face has none.
+    /// Values outside 0..1 are kept, `sampler` decides how they wrap.
+    pub fn uv(&self, iface: usize, nthvert: usize) -> Vec2f {
+        if !self.face_info[iface].attributes.uv {
+            return Vec2f::new(0.0, 0.0);
         }
+        self.uv[self.faces[iface][nthvert].y as usize]
     }
 
     pub fn nverts(&self) -> usize {
@@ -142,8 +531,562 @@ impl Model {
         &self.faces[idx]
     }
 
+    /// Index of the OBJ polygon (in file order) that triangle `idx` belongs to.
+    pub fn source_face(&self, idx: usize) -> usize {
+        self.face_info[idx].source
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    /// Index into `materials` of the material assigned with `usemtl`.
+    pub fn face_material(&self, iface: usize) -> Option<usize> {
+        self.face_info[iface].material
+    }
+
+    pub fn material(&self, iface: usize) -> Option<&Material> {
+        self.face_info[iface]
+            .material
+            .and_then(|m| self.materials.get(m))
+    }
+
+    /// Runs of consecutive faces that share a material.
+    pub fn material_ranges(&self) -> Vec<(Range<usize>, Option<usize>)> {
+        let mut ranges: Vec<(Range<usize>, Option<usize>)> = Vec::new();
+        for (iface, info) in self.face_info.iter().enumerate() {
+            let material = info.material;
+            match ranges.last_mut() {
+                Some((range, m)) if *m == material => range.end = iface + 1,
+                _ => ranges.push((iface..iface + 1, material)),
+            }
+        }
+        ranges
+    }
+
+    pub fn submeshes(&self) -> &[Submesh] {
+        &self.submeshes
+    }
+
+    /// First submesh called `name`.
+    pub fn submesh(&self, name: &str) -> Option<&Submesh> {
+        self.submeshes.iter().find(|m| m.name == name)
+    }
+
+    /// Shows or hides every submesh called `name` or belonging to the object
+    /// `name`. Returns false if there is none.
+    pub fn set_visible(&mut self, name: &str, visible: bool) -> bool {
+        let mut found = false;
+        for submesh in &mut self.submeshes {
+            if submesh.name == name || submesh.object == name {
+                submesh.visible = visible;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Hides everything except the submeshes or object called `name`.
+    pub fn show_only(&mut self, name: &str) -> bool {
+        for submesh in &mut self.submeshes {
+            submesh.visible = false;
+        }
+        self.set_visible(name, true)
+    }
+
+    /// Indices of the faces of all visible submeshes.
+    pub fn visible_faces(&self) -> impl Iterator<Item = usize> + '_ {
+        self.submeshes
+            .iter()
+            .filter(|m| m.visible)
+            .flat_map(|m| m.faces.clone())
+    }
+
+    /// Diffuse texture of a face: the `map_Kd` of its material, otherwise
+    /// the texture set with `load_texture`.
+    pub fn texture(&self, iface: usize) -> Option<&TGAImage> {
+        self.material(iface)
+            .and_then(|m| m.diffuse_map.as_ref())
+            .and_then(|map| map.image.as_ref())
+            .or(self.diffusemap.as_ref())
+    }
+
+    pub fn face_attributes(&self, iface: usize) -> FaceAttributes {
+        self.face_info[iface].attributes
+    }
+
+    /// Normal of a corner. Faces without `vn` indices get their geometric normal.
     pub fn norm(&self, iface: usize, nvert: usize) -> Vec3f {
+        if !self.face_info[iface].attributes.normal {
+            return self.face_normal(iface);
+        }
         let idx = self.faces[iface][nvert][2] as usize;
-        return self.norms[idx];
+        self.norms[idx]
+    }
+
+    /// Unit normal of triangle `iface` following its counter-clockwise winding.
+    pub fn face_normal(&self, iface: usize) -> Vec3f {
+        let face = &self.faces[iface];
+        let (a, b, c) = (
+            self.verts[face[0].x as usize],
+            self.verts[face[1].x as usize],
+            self.verts[face[2].x as usize],
+        );
+        Vec3f::triangle_normal(a, b, c).normalized()
+    }
+}
+
+/// Parses the numbers of a `v`, `vt` or `vn` line, between `min` and `max`
+/// of them.
+pub(crate) fn parse_floats<'a>(
+    parts: impl Iterator<Item = &'a str>,
+    min: usize,
+    max: usize,
+) -> Result<Vec<f32>, String> {
+    let mut values = Vec::new();
+    for part in parts {
+        if values.len() == max {
+            return Err(format!(
+                "expected at most {} numbers, found {:?}",
+                max, part
+            ));
+        }
+        match part.parse::<f32>() {
+            Ok(v) if v.is_finite() => values.push(v),
+            _ => return Err(format!("invalid number {:?}", part)),
+        }
+    }
+    if values.len() < min {
+        return Err(format!(
+            "expected {} coordinates, found {}",
+            min,
+            values.len()
+        ));
+    }
+    Ok(values)
+}
+
+/// Parses a face corner in any of the forms v, v/vt, v//vn or v/vt/vn.
+/// Missing texture and normal indices become -1.
+fn parse_corner(part: &str, nverts: usize, nuv: usize, nnorms: usize) -> Result<Vec3i, String> {
+    let fields: Vec<&str> = part.split('/').collect();
+    if fields.len() > 3 {
+        return Err(format!("invalid face corner {:?}", part));
+    }
+    let v = resolve_index(fields[0], nverts)?
+        .ok_or_else(|| format!("face corner {:?} has no vertex", part))?;
+    let vt = match fields.get(1) {
+        Some(s) => resolve_index(s, nuv)?.unwrap_or(-1),
+        None => -1,
+    };
+    let vn = match fields.get(2) {
+        Some(s) => resolve_index(s, nnorms)?.unwrap_or(-1),
+        None => -1,
+    };
+    Ok(Vec3i::new(v, vt, vn))
+}
+
+/// Converts a 1-based OBJ index, or a negative one relative to the `count`
+/// elements read so far, into a 0-based index. Empty fields are `None`.
+fn resolve_index(s: &str, count: usize) -> Result<Option<i32>, String> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    match s.parse::<i32>() {
+        Ok(i) if i > 0 => Ok(Some(i - 1)),
+        Ok(i) if i < 0 && count as i32 + i >= 0 => Ok(Some(count as i32 + i)),
+        Ok(i) => Err(format!("index {} out of range", i)),
+        Err(_) => Err(format!("invalid index {:?}", s)),
+    }
+}
+
+/// Splits a polygon into triangles, returned as corner indices into `polygon`
+/// with the original winding. Convex polygons are fanned, concave ones ear
+/// clipped in the plane of the polygon.
+pub(crate) fn triangulate(verts: &[Vec3f], polygon: &[Vec3i]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect();
+
+    let mut pts3 = Vec::with_capacity(n);
+    for corner in polygon {
+        match verts.get(corner.x as usize) {
+            Some(&v) => pts3.push(v),
+            None => return fan(),
+        }
+    }
+
+    // Newell normal, then drop its dominant axis to get 2D coordinates
+    let mut normal = Vec3f::new(0.0, 0.0, 0.0);
+    for i in 0..n {
+        let (a, b) = (pts3[i], pts3[(i + 1) % n]);
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+    let (ax, ay, az) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let (u, v, sign) = if az >= ax && az >= ay {
+        (0, 1, normal.z)
+    } else if ax >= ay {
+        (1, 2, normal.x)
+    } else {
+        (2, 0, normal.y)
+    };
+    // flip so the polygon is counter-clockwise in 2D
+    let flip = if sign < 0.0 { -1.0 } else { 1.0 };
+    let pts: Vec<Vec2f> = pts3.iter().map(|p| Vec2f::new(p[u], p[v] * flip)).collect();
+
+    let cross =
+        |o: Vec2f, a: Vec2f, b: Vec2f| (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x);
+
+    let convex = (0..n).all(|i| cross(pts[(i + n - 1) % n], pts[i], pts[(i + 1) % n]) >= 0.0);
+    if convex {
+        return fan();
+    }
+
+    let inside = |p: Vec2f, a: Vec2f, b: Vec2f, c: Vec2f| {
+        cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+    };
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = (
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            );
+            cross(pts[a], pts[b], pts[c]) > 0.0
+                && remaining
+                    .iter()
+                    .filter(|&&k| k != a && k != b && k != c)
+                    .all(|&k| !inside(pts[k], pts[a], pts[b], pts[c]))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + m - 1) % m],
+                    remaining[i],
+                    remaining[(i + 1) % m],
+                ]);
+                remaining.remove(i);
+            }
+            None => {
+                // degenerate or self-intersecting, fan what is left
+                for i in 1..m - 1 {
+                    triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+                }
+                return triangles;
+            }
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corners(n: usize) -> Vec<Vec3i> {
+        (0..n as i32).map(|v| Vec3i::new(v, -1, -1)).collect()
+    }
+
+    /// Area-weighted normal of each triangle.
+    fn normals(verts: &[Vec3f], triangles: &[[usize; 3]]) -> Vec<Vec3f> {
+        triangles
+            .iter()
+            .map(|t| Vec3f::triangle_normal(verts[t[0]], verts[t[1]], verts[t[2]]))
+            .collect()
+    }
+
+    #[test]
+    fn fans_convex_polygons() {
+        let verts = [
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(1.5, 1.0, 0.0),
+            Vec3f::new(0.5, 1.5, 0.0),
+            Vec3f::new(-0.5, 1.0, 0.0),
+        ];
+        assert_eq!(triangulate(&verts, &corners(3)), [[0, 1, 2]]);
+        assert_eq!(
+            triangulate(&verts, &corners(5)),
+            [[0, 1, 2], [0, 2, 3], [0, 3, 4]]
+        );
+    }
+
+    /// Checks that the triangles cover `area` and all face along `normal`,
+    /// the way the polygon is wound.
+    fn check_cover(verts: &[Vec3f], polygon: &[Vec3i], normal: Vec3f, area: f32) {
+        let triangles: Vec<[usize; 3]> = triangulate(verts, polygon)
+            .iter()
+            .map(|t| t.map(|c| polygon[c].x as usize))
+            .collect();
+        assert_eq!(triangles.len(), polygon.len() - 2);
+        let mut total = 0.0;
+        for n in normals(verts, &triangles) {
+            assert!(n * normal > 0.0, "{:?} is flipped", n);
+            total += n.norm() / 2.0;
+        }
+        assert!((total - area).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ear_clips_concave_polygons() {
+        // an L with the notch at (1, 1), wound clockwise
+        let verts = [
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 2.0, 0.0),
+            Vec3f::new(1.0, 2.0, 0.0),
+            Vec3f::new(1.0, 1.0, 0.0),
+            Vec3f::new(2.0, 1.0, 0.0),
+            Vec3f::new(2.0, 0.0, 0.0),
+        ];
+        check_cover(&verts, &corners(6), Vec3f::new(0.0, 0.0, -1.0), 3.0);
+        // starting at a corner that can't see the whole L, a fan would overlap
+        let rotated: Vec<Vec3i> = [1, 2, 3, 4, 5, 0]
+            .iter()
+            .map(|&v| Vec3i::new(v, -1, -1))
+            .collect();
+        check_cover(&verts, &rotated, Vec3f::new(0.0, 0.0, -1.0), 3.0);
+
+        // an arrow head in the x = 0 plane, counter-clockwise seen from +x
+        let verts: Vec<Vec3f> = [(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (1.0, 3.0)]
+            .iter()
+            .map(|&(y, z)| Vec3f::new(0.0, y, z))
+            .collect();
+        check_cover(&verts, &corners(4), Vec3f::new(1.0, 0.0, 0.0), 2.0);
+    }
+
+    /// Loads `text` as an OBJ file.
+    fn load_obj(name: &str, text: &str, mode: ParseMode) -> Result<Model, ModelError> {
+        let path = std::env::temp_dir().join(format!("{}_{}.obj", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let model = Model::load(path.to_str().unwrap(), mode);
+        std::fs::remove_file(&path).unwrap();
+        model
+    }
+
+    #[test]
+    fn resolves_indices() {
+        assert_eq!(resolve_index("1", 3), Ok(Some(0)));
+        assert_eq!(resolve_index("7", 3), Ok(Some(6)));
+        assert_eq!(resolve_index("-1", 3), Ok(Some(2)));
+        assert_eq!(resolve_index("-3", 3), Ok(Some(0)));
+        assert_eq!(resolve_index("", 3), Ok(None));
+        assert!(resolve_index("-4", 3).is_err());
+        assert!(resolve_index("0", 3).is_err());
+        assert!(resolve_index("1.5", 3).is_err());
+    }
+
+    #[test]
+    fn parses_every_corner_form() {
+        let corner = |part: &str| parse_corner(part, 4, 3, 2);
+        assert_eq!(corner("2"), Ok(Vec3i::new(1, -1, -1)));
+        assert_eq!(corner("2/3"), Ok(Vec3i::new(1, 2, -1)));
+        assert_eq!(corner("2//1"), Ok(Vec3i::new(1, -1, 0)));
+        assert_eq!(corner("2/3/1"), Ok(Vec3i::new(1, 2, 0)));
+        assert_eq!(corner("2/3/"), Ok(Vec3i::new(1, 2, -1)));
+        assert_eq!(corner("-1/-1/-1"), Ok(Vec3i::new(3, 2, 1)));
+        assert_eq!(corner("-4//-2"), Ok(Vec3i::new(0, -1, 0)));
+        assert!(corner("/1").is_err());
+        assert!(corner("1/2/3/4").is_err());
+        assert!(corner("1//-3").is_err());
+    }
+
+    #[test]
+    fn loads_relative_and_partial_corners() {
+        let text = "v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+vn 0 0 1
+f -3//-1 -2//-1 -1//-1
+v 1 1 0
+f 2/2 4/3 3/1
+f -4 -3 -1
+";
+        let model = load_obj("relative_corners", text, ParseMode::Strict).unwrap();
+        assert_eq!(model.nfaces(), 3);
+        assert_eq!(
+            model.face(0),
+            &[
+                Vec3i::new(0, -1, 0),
+                Vec3i::new(1, -1, 0),
+                Vec3i::new(2, -1, 0)
+            ]
+        );
+        // relative indices count the vertices read so far, four by now
+        assert_eq!(
+            model.face(1),
+            &[
+                Vec3i::new(1, 1, -1),
+                Vec3i::new(3, 2, -1),
+                Vec3i::new(2, 0, -1)
+            ]
+        );
+        assert_eq!(
+            model.face(2),
+            &[
+                Vec3i::new(0, -1, -1),
+                Vec3i::new(1, -1, -1),
+                Vec3i::new(3, -1, -1)
+            ]
+        );
+        let attributes: Vec<(bool, bool)> = model
+            .face_info
+            .iter()
+            .map(|i| (i.attributes.uv, i.attributes.normal))
+            .collect();
+        assert_eq!(attributes, [(false, true), (true, false), (false, false)]);
+        assert_eq!(model.uv(1, 1), Vec2f::new(0.0, 1.0));
+        assert_eq!(model.uv(0, 0), Vec2f::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn vertex_lines_with_comments_and_extras() {
+        let text = "v 1 2 3 # note
+v 0 1 0 1
+v 0 0 1 0.5 0.25 1
+vt 0.5 # half
+vt 0 1 0
+vn 0 0 1 # up
+f 1/1/1 2/2/1 3/1/1 # first
+";
+        let model = load_obj("vertex_extras", text, ParseMode::Strict).unwrap();
+        assert_eq!(model.nverts(), 3);
+        assert_eq!(model.vert(0), Vec3f::new(1.0, 2.0, 3.0));
+        assert_eq!(model.vert(2), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(model.nfaces(), 1);
+        for line in ["v 1 2 3 4 5 6 7 8", "vn 0 0 1 0", "vt 0 0 0 0", "v 1 2 x"] {
+            let text = format!("{}\n", line);
+            assert!(
+                load_obj("vertex_extras_bad", &text, ParseMode::Strict).is_err(),
+                "{}",
+                line
+            );
+        }
+    }
+
+    fn parts() -> Model {
+        let text = "v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+f 1 2 3
+o car
+g wheel
+f 1 2 3
+f 2 4 3
+g body
+f 1 2 4
+o house
+f 1 3 4
+g wheel
+f 2 4 3
+";
+        load_obj("submesh_parts", text, ParseMode::Strict).unwrap()
+    }
+
+    fn visible(model: &Model) -> Vec<usize> {
+        model.visible_faces().collect()
+    }
+
+    #[test]
+    fn splits_submeshes_at_objects_and_groups() {
+        let model = parts();
+        let names: Vec<(&str, &str, Range<usize>)> = model
+            .submeshes()
+            .iter()
+            .map(|m| (m.name.as_str(), m.object.as_str(), m.faces.clone()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("default", "default", 0..1),
+                ("wheel", "car", 1..3),
+                ("body", "car", 3..4),
+                // faces after `o` and before any `g` are named after the object
+                ("house", "house", 4..5),
+                ("wheel", "house", 5..6),
+            ]
+        );
+        assert_eq!(model.submesh("wheel").unwrap().faces, 1..3);
+        assert!(model.submesh("car").is_none());
+        assert_eq!(visible(&model), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn hides_and_shows_submeshes() {
+        let mut model = parts();
+        // a group name hides it in every object
+        assert!(model.set_visible("wheel", false));
+        assert_eq!(visible(&model), [0, 3, 4]);
+        // an object name covers all of its groups
+        assert!(model.set_visible("car", false));
+        assert_eq!(visible(&model), [0, 4]);
+        assert!(model.set_visible("wheel", true));
+        assert_eq!(visible(&model), [0, 1, 2, 4, 5]);
+        assert!(!model.set_visible("boat", false));
+        assert_eq!(visible(&model), [0, 1, 2, 4, 5]);
+
+        assert!(model.show_only("house"));
+        assert_eq!(visible(&model), [4, 5]);
+        assert!(model.show_only("body"));
+        assert_eq!(visible(&model), [3]);
+        assert!(!model.show_only("boat"));
+        assert_eq!(visible(&model), []);
+    }
+
+    #[test]
+    fn reports_out_of_range_indices() {
+        let text = "# triangle and three broken faces
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+
+f 1 2 3
+f 1 2 9
+f 1/1 2/2 3/1
+f -4 -2 -1
+f 3 2 1
+";
+        let line = |text: &str| match load_obj("out_of_range", text, ParseMode::Strict) {
+            Err(ModelError::Parse { line, reason, .. }) => (line, reason),
+            other => panic!("loaded {:?} faces", other.map(|m| m.nfaces())),
+        };
+        // relative indices are checked against the vertices read so far,
+        // while reading, so strict mode stops there first
+        let (lineno, reason) = line(text);
+        assert_eq!(lineno, 10);
+        assert_eq!(reason, "index -4 out of range");
+
+        // the other indices are checked once the whole file is read
+        let checked = text.replace("f -4 -2 -1\n", "");
+        let (lineno, reason) = line(&checked);
+        assert_eq!(lineno, 8);
+        assert_eq!(reason, "vertex index 9 out of range, 3 vertices");
+        let (lineno, reason) = line(&checked.replace("f 1 2 9\n", ""));
+        assert_eq!(lineno, 8);
+        assert_eq!(
+            reason,
+            "texture index 2 out of range, 1 texture coordinates"
+        );
+
+        // lenient mode drops the three faces and keeps the rest; the face
+        // with the bad relative index never became a polygon
+        let model = load_obj("out_of_range", text, ParseMode::Lenient).unwrap();
+        assert_eq!(model.nfaces(), 2);
+        assert_eq!(model.source_face(1), 3);
+        assert_eq!(model.face(1)[0].x, 2);
+        assert_eq!(model.submeshes()[0].faces, 0..2);
     }
 }
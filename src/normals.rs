@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use crate::geometry::Vec3f;
+use crate::model::Model;
+
+/// How the faces around a vertex contribute to its normal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weighting {
+    /// Face normals scaled by triangle area.
+    Area,
+    /// Face normals scaled by the corner angle at the vertex.
+    Angle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalMode {
+    /// One normal per face.
+    Flat,
+    /// Average over all faces sharing a vertex.
+    Smooth(Weighting),
+    /// Like `Smooth`, but faces meeting at more than `angle` degrees keep
+    /// separate normals, so hard edges stay sharp.
+    Crease { weighting: Weighting, angle: f32 },
+}
+
+impl Model {
+    /// True if every face has `vn` indices.
+    pub fn has_normals(&self) -> bool {
+        self.face_info.iter().all(|info| info.attributes.normal)
+    }
+
+    /// Replaces all vertex normals with computed ones. In the smooth modes
+    /// faces only share normals within their OBJ smoothing group; faces with
+    /// `s off` stay flat. Files without `s` statements are one group.
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        let nfaces = self.faces.len();
+
+        // unnormalized cross product, its length is twice the triangle area
+        let mut face_normals = Vec::with_capacity(nfaces);
+        let mut corner_angles = Vec::with_capacity(nfaces);
+        for face in &self.faces {
+            let p: Vec<Vec3f> = face.iter().map(|c| self.verts[c.x as usize]).collect();
+            face_normals.push(Vec3f::triangle_normal(p[0], p[1], p[2]));
+            corner_angles.push([
+                (p[1] - p[0]).angle(p[2] - p[0]),
+                (p[2] - p[1]).angle(p[0] - p[1]),
+                (p[0] - p[2]).angle(p[1] - p[2]),
+            ]);
+        }
+
+        // faces around every position
+        let mut incident: Vec<Vec<(usize, usize)>> = vec![Vec::new(); self.verts.len()];
+        for (iface, face) in self.faces.iter().enumerate() {
+            for (k, c) in face.iter().enumerate() {
+                incident[c.x as usize].push((iface, k));
+            }
+        }
+
+        let group = |iface: usize| {
+            if self.has_smoothing_groups {
+                self.face_info[iface].smoothing
+            } else {
+                1
+            }
+        };
+
+        let mut norms = Vec::new();
+        let mut lookup: HashMap<(i32, [u32; 3]), i32> = HashMap::new();
+        let mut corner_normals = vec![[0i32; 3]; nfaces];
+        for iface in 0..nfaces {
+            for k in 0..3 {
+                let own = face_normals[iface];
+                let n = match mode {
+                    _ if group(iface) == 0 => own,
+                    NormalMode::Flat => own,
+                    NormalMode::Smooth(weighting) | NormalMode::Crease { weighting, .. } => {
+                        let max_angle = match mode {
+                            NormalMode::Crease { angle, .. } => angle.to_radians(),
+                            _ => f32::MAX,
+                        };
+                        let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+                        for &(other, ok) in &incident[self.faces[iface][k].x as usize] {
+                            if group(other) != group(iface)
+                                || own.angle(face_normals[other]) > max_angle
+                            {
+                                continue;
+                            }
+                            let n = face_normals[other];
+                            sum = sum
+                                + match weighting {
+                                    Weighting::Area => n,
+                                    Weighting::Angle => {
+                                        let len = n.norm();
+                                        if len > 0.0 {
+                                            n * (corner_angles[other][ok] / len)
+                                        } else {
+                                            n
+                                        }
+                                    }
+                                };
+                        }
+                        sum
+                    }
+                };
+                let n = n.normalized();
+
+                // corners of one position with the same normal share it
+                let key = (
+                    self.faces[iface][k].x,
+                    [n.x.to_bits(), n.y.to_bits(), n.z.to_bits()],
+                );
+                corner_normals[iface][k] = *lookup.entry(key).or_insert_with(|| {
+                    norms.push(n);
+                    norms.len() as i32 - 1
+                });
+            }
+        }
+
+        for (iface, face) in self.faces.iter_mut().enumerate() {
+            for (k, c) in face.iter_mut().enumerate() {
+                c.z = corner_normals[iface][k];
+            }
+            self.face_info[iface].attributes.normal = true;
+        }
+        self.norms = norms;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec3i;
+
+    fn model(verts: Vec<Vec3f>, triangles: &[[i32; 3]]) -> Model {
+        let faces = triangles
+            .iter()
+            .map(|t| t.iter().map(|&v| Vec3i::new(v, -1, -1)).collect())
+            .collect();
+        Model::from_triangles(verts, Vec::new(), Vec::new(), faces)
+    }
+
+    /// Unit cube, two outward facing triangles per side.
+    fn cube() -> Model {
+        let verts = (0..8)
+            .map(|i| Vec3f::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32))
+            .collect();
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let triangles: Vec<[i32; 3]> = quads
+            .iter()
+            .flat_map(|q| [[q[0], q[1], q[2]], [q[0], q[2], q[3]]])
+            .collect();
+        model(verts, &triangles)
+    }
+
+    /// Two triangles folded 90 degrees along the edge 0-1.
+    fn hinge() -> Model {
+        let verts = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, -1.0),
+        ];
+        model(verts, &[[0, 1, 2], [1, 0, 3]])
+    }
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    #[test]
+    fn flat_normals_are_face_normals() {
+        let mut model = hinge();
+        model.generate_normals(NormalMode::Flat);
+        assert!(model.has_normals());
+        for f in 0..2 {
+            for k in 0..3 {
+                assert!(close(model.norm(f, k), model.face_normal(f)));
+            }
+        }
+        // the shared edge has a normal for each side
+        assert_eq!(model.norms.len(), 6);
+        assert!(close(model.norm(0, 0), Vec3f::new(0.0, 0.0, 1.0)));
+        assert!(close(model.norm(1, 0), Vec3f::new(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn area_and_angle_weighting() {
+        // a large and a small triangle meeting at the origin at right angles
+        let verts = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(2.0, 0.0, 0.0),
+            Vec3f::new(0.0, 2.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        ];
+        let fan = model(verts, &[[0, 1, 2], [0, 3, 4]]);
+
+        let mut area = fan.clone();
+        area.generate_normals(NormalMode::Smooth(Weighting::Area));
+        // areas 2 and 0.5
+        let expected = Vec3f::new(1.0, 0.0, 4.0).normalized();
+        assert!(close(area.norm(0, 0), expected));
+        assert!(close(area.norm(1, 0), expected));
+
+        let mut angle = fan;
+        angle.generate_normals(NormalMode::Smooth(Weighting::Angle));
+        // both corners at the origin are right angles
+        let expected = Vec3f::new(1.0, 0.0, 1.0).normalized();
+        assert!(close(angle.norm(0, 0), expected));
+        // the other corners only see their own face
+        assert!(close(angle.norm(0, 1), Vec3f::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn crease_angle_splits_cube_corners() {
+        let mut smooth = cube();
+        smooth.generate_normals(NormalMode::Smooth(Weighting::Angle));
+        assert_eq!(smooth.norms.len(), 8);
+        let center = Vec3f::new(0.5, 0.5, 0.5);
+        for f in 0..smooth.nfaces() {
+            for k in 0..3 {
+                let outward = (smooth.vert(smooth.face(f)[k].x as usize) - center).normalized();
+                assert!(close(smooth.norm(f, k), outward));
+            }
+        }
+
+        let mut creased = cube();
+        creased.generate_normals(NormalMode::Crease {
+            weighting: Weighting::Angle,
+            angle: 30.0,
+        });
+        // three per corner, one for every side
+        assert_eq!(creased.norms.len(), 24);
+        for f in 0..creased.nfaces() {
+            for k in 0..3 {
+                assert!(close(creased.norm(f, k), creased.face_normal(f)));
+            }
+        }
+
+        // a crease angle past 90 degrees keeps the corners smooth
+        let mut wide = cube();
+        wide.generate_normals(NormalMode::Crease {
+            weighting: Weighting::Angle,
+            angle: 100.0,
+        });
+        assert_eq!(wide.norms.len(), 8);
+    }
+
+    #[test]
+    fn smoothing_groups_keep_separate_normals() {
+        let mut model = hinge();
+        model.has_smoothing_groups = true;
+        model.face_info[0].smoothing = 1;
+        model.face_info[1].smoothing = 2;
+        model.generate_normals(NormalMode::Smooth(Weighting::Area));
+        assert!(close(model.norm(0, 0), Vec3f::new(0.0, 0.0, 1.0)));
+        assert!(close(model.norm(1, 1), Vec3f::new(0.0, -1.0, 0.0)));
+
+        model.face_info[1].smoothing = 1;
+        model.generate_normals(NormalMode::Smooth(Weighting::Area));
+        let shared = Vec3f::new(0.0, -1.0, 1.0).normalized();
+        assert!(close(model.norm(0, 0), shared));
+        assert!(close(model.norm(1, 1), shared));
+
+        // `s off` faces stay flat
+        model.face_info[1].smoothing = 0;
+        model.generate_normals(NormalMode::Smooth(Weighting::Area));
+        assert!(close(model.norm(1, 1), Vec3f::new(0.0, -1.0, 0.0)));
+        assert!(close(model.norm(0, 0), Vec3f::new(0.0, 0.0, 1.0)));
+    }
+}
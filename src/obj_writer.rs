@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::material::write_mtl;
+use crate::model::Model;
+
+impl Model {
+    /// Writes the model as an OBJ file that `Model::new` reads back to the
+    /// same vertices, faces, submeshes, materials and smoothing groups. Faces
+    /// are written as the triangles they were split into. With `with_mtl`
+    /// the materials go into `<name>.mtl` next to it, referenced by `mtllib`.
+    pub fn write_obj(&self, filename: &str, with_mtl: bool) -> io::Result<()> {
+        let path = Path::new(filename);
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# tinyrenderer-rust")?;
+        let with_mtl = with_mtl && !self.materials.is_empty();
+        if with_mtl {
+            let mtl = path.with_extension("mtl");
+            write_mtl(&mtl, &self.materials)?;
+            let name = mtl.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            writeln!(out, "mtllib {}", name)?;
+        }
+
+        for v in &self.verts {
+            writeln!(out, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        for t in &self.uv {
+            writeln!(out, "vt {} {}", t.x, t.y)?;
+        }
+        for n in &self.norms {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+
+        let mut object = None;
+        let mut group = None;
+        let mut material = None;
+        let mut smoothing = None;
+        for submesh in &self.submeshes {
+            if object != Some(&submesh.object) {
+                writeln!(out, "o {}", submesh.object)?;
+                object = Some(&submesh.object);
+                // `o` ends the group
+                group = None;
+            }
+            // the loader names a submesh after its group, or its object without one
+            let name = Some(&submesh.name).filter(|&n| *n != submesh.object);
+            if name != group {
+                match name {
+                    Some(name) => writeln!(out, "g {}", name)?,
+                    // a bare g goes back to no group when read again
+                    None => writeln!(out, "g")?,
+                }
+                group = name;
+            }
+            if with_mtl && submesh.material != material {
+                match submesh.material.and_then(|m| self.materials.get(m)) {
+                    Some(m) => writeln!(out, "usemtl {}", m.name)?,
+                    // a bare usemtl goes back to no material when read again
+                    None => writeln!(out, "usemtl")?,
+                }
+                material = submesh.material;
+            }
+            for iface in submesh.faces.clone() {
+                let info = &self.face_info[iface];
+                if self.has_smoothing_groups && smoothing != Some(info.smoothing) {
+                    match info.smoothing {
+                        0 => writeln!(out, "s off")?,
+                        group => writeln!(out, "s {}", group)?,
+                    }
+                    smoothing = Some(info.smoothing);
+                }
+                write!(out, "f")?;
+                for c in &self.faces[iface] {
+                    // OBJ indices are 1-based
+                    match (info.attributes.uv, info.attributes.normal) {
+                        (true, true) => write!(out, " {}/{}/{}", c.x + 1, c.y + 1, c.z + 1)?,
+                        (true, false) => write!(out, " {}/{}", c.x + 1, c.y + 1)?,
+                        (false, true) => write!(out, " {}//{}", c.x + 1, c.z + 1)?,
+                        (false, false) => write!(out, " {}", c.x + 1)?,
+                    }
+                }
+                writeln!(out)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Vec3f, Vec3i};
+    use crate::material::Material;
+    use crate::model::Submesh;
+
+    #[test]
+    fn round_trips_submeshes() {
+        let verts = (0..7)
+            .map(|i| Vec3f::new(i as f32, (i % 2) as f32, 0.0))
+            .collect();
+        let faces = (0..5)
+            .map(|f| (f..f + 3).map(|v| Vec3i::new(v, -1, -1)).collect())
+            .collect();
+        let mut model = Model::from_triangles(verts, Vec::new(), Vec::new(), faces);
+        model.materials = vec![Material::new("red"), Material::new("blue")];
+        // (group or object name, object, material) of one face each
+        let parts = [
+            ("a", "a", None),
+            ("legs", "a", Some(0)),
+            // back out of the group, same object and material
+            ("a", "a", Some(0)),
+            ("b", "b", Some(1)),
+            ("legs", "b", None),
+        ];
+        model.submeshes = parts
+            .iter()
+            .enumerate()
+            .map(|(f, &(name, object, material))| Submesh {
+                name: name.to_string(),
+                object: object.to_string(),
+                faces: f..f + 1,
+                material,
+                visible: true,
+            })
+            .collect();
+        for (info, submesh) in model.face_info.iter_mut().zip(&model.submeshes) {
+            info.material = submesh.material;
+        }
+
+        let path = std::env::temp_dir().join(format!("obj_writer_{}.obj", std::process::id()));
+        let filename = path.to_str().unwrap();
+        model.write_obj(filename, true).unwrap();
+        let read = Model::new(filename).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("mtl")).unwrap();
+
+        let summary = |m: &Model| -> Vec<_> {
+            m.submeshes()
+                .iter()
+                .map(|s| {
+                    let material = s.material.map(|i| m.materials[i].name.clone());
+                    (s.name.clone(), s.object.clone(), s.faces.clone(), material)
+                })
+                .collect()
+        };
+        assert_eq!(summary(&read), summary(&model));
+    }
+}
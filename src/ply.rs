@@ -0,0 +1,839 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::geometry::{Vec2f, Vec3f, Vec3i, Vec4f};
+use crate::model::{triangulate, Diagnostics, Model, ModelError, ParseMode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// Scale that maps the type's full range onto 0..=1, for colours.
+    fn unit_scale(self) -> f32 {
+        match self {
+            Scalar::U8 => 1.0 / 255.0,
+            Scalar::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+
+    fn decode(self, b: &[u8], big_endian: bool) -> f64 {
+        macro_rules! num {
+            ($t:ty, $n:expr) => {{
+                let bytes: [u8; $n] = b[..$n].try_into().unwrap();
+                if big_endian {
+                    <$t>::from_be_bytes(bytes) as f64
+                } else {
+                    <$t>::from_le_bytes(bytes) as f64
+                }
+            }};
+        }
+        match self {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => num!(i16, 2),
+            Scalar::U16 => num!(u16, 2),
+            Scalar::I32 => num!(i32, 4),
+            Scalar::U32 => num!(u32, 4),
+            Scalar::F32 => num!(f32, 4),
+            Scalar::F64 => num!(f64, 8),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Property {
+    Scalar(String, Scalar),
+    List {
+        name: String,
+        count: Scalar,
+        item: Scalar,
+    },
+}
+
+/// Values of an unrecognised list property, the lists of all rows one
+/// after the other.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListAttribute {
+    values: Vec<f32>,
+    // start of every row in `values`, and the end of the last one
+    offsets: Vec<usize>,
+}
+
+impl ListAttribute {
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// List of row `i`.
+    pub fn get(&self, i: usize) -> Option<&[f32]> {
+        let (start, end) = (*self.offsets.get(i)?, *self.offsets.get(i + 1)?);
+        Some(&self.values[start..end])
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    fn push(&mut self, row: &[f64]) {
+        if self.offsets.is_empty() {
+            self.offsets.push(0);
+        }
+        self.values.extend(row.iter().map(|&v| v as f32));
+        self.offsets.push(self.values.len());
+    }
+
+    /// Replaces the rows with `rows`, in that order, repeating any that
+    /// are listed more than once.
+    pub(crate) fn pick_rows(&mut self, rows: &[usize]) {
+        let mut picked = ListAttribute::default();
+        for &i in rows {
+            picked.offsets.push(picked.values.len());
+            picked
+                .values
+                .extend_from_slice(self.get(i).unwrap_or_default());
+        }
+        picked.offsets.push(picked.values.len());
+        *self = picked;
+    }
+
+    /// Keeps the rows marked in `keep`.
+    pub(crate) fn retain_rows(&mut self, keep: &[bool]) {
+        let mut kept = ListAttribute::default();
+        for (i, &k) in keep.iter().enumerate() {
+            if let (true, Some(row)) = (k, self.get(i)) {
+                kept.offsets.push(kept.values.len());
+                kept.values.extend_from_slice(row);
+            }
+        }
+        kept.offsets.push(kept.values.len());
+        *self = kept;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// Element data after the header. Every row holds one value list per
+/// property, scalar properties have a single value.
+enum Body<'a> {
+    Ascii {
+        lines: std::iter::Enumerate<std::str::Lines<'a>>,
+        // line offset of the body within the file
+        first_line: usize,
+    },
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    /// Reads the next row of `element`. Returns the line number for error
+    /// reports along with either the row or the reason it is malformed.
+    fn row(&mut self, element: &Element) -> (usize, Result<Vec<Vec<f64>>, String>) {
+        match self {
+            Body::Ascii { lines, first_line } => {
+                // blank lines are not rows
+                let Some((lineno, line)) = lines.find(|(_, l)| !l.trim().is_empty()) else {
+                    return (0, Err(format!("missing {} data", element.name)));
+                };
+                let lineno = *first_line + lineno;
+                let mut tokens = line.split_whitespace();
+                let mut next = || -> Result<f64, String> {
+                    let token = tokens
+                        .next()
+                        .ok_or_else(|| format!("{} row ends early", element.name))?;
+                    token
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid number {:?}", token))
+                };
+                let mut row = Vec::with_capacity(element.properties.len());
+                for property in &element.properties {
+                    let values = match property {
+                        Property::Scalar(..) => next().map(|v| vec![v]),
+                        Property::List { .. } => {
+                            next().and_then(|n| (0..n.max(0.0) as usize).map(|_| next()).collect())
+                        }
+                    };
+                    match values {
+                        Ok(values) => row.push(values),
+                        Err(reason) => return (lineno, Err(reason)),
+                    }
+                }
+                (lineno, Ok(row))
+            }
+            Body::Binary {
+                data,
+                pos,
+                big_endian,
+            } => {
+                let mut read = |ty: Scalar| -> Result<f64, String> {
+                    let bytes = data
+                        .get(*pos..*pos + ty.size())
+                        .ok_or_else(|| format!("binary PLY ends inside {} data", element.name))?;
+                    *pos += ty.size();
+                    Ok(ty.decode(bytes, *big_endian))
+                };
+                let mut row = Vec::with_capacity(element.properties.len());
+                for property in &element.properties {
+                    let values = match property {
+                        Property::Scalar(_, ty) => read(*ty).map(|v| vec![v]),
+                        Property::List { count, item, .. } => read(*count)
+                            .and_then(|n| (0..n.max(0.0) as usize).map(|_| read(*item)).collect()),
+                    };
+                    match values {
+                        Ok(values) => row.push(values),
+                        // binary files have no lines, the position is reported as line 0
+                        Err(reason) => return (0, Err(reason)),
+                    }
+                }
+                (0, Ok(row))
+            }
+        }
+    }
+}
+
+/// Parses the header up to `end_header`. Returns the elements, the
+/// encoding and the number of header lines.
+fn parse_header(
+    header: &str,
+    diag: &Diagnostics,
+) -> Result<(Vec<Element>, Encoding, usize), ModelError> {
+    let fail = |line: usize, reason: String| ModelError::Parse {
+        file: diag.file.to_string(),
+        line,
+        reason,
+    };
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(fail(1, String::from("not a PLY file")));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut lineno = 1;
+    for line in lines {
+        lineno += 1;
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("format") => {
+                encoding = match parts.next() {
+                    Some("ascii") => Some(Encoding::Ascii),
+                    Some("binary_little_endian") => Some(Encoding::BinaryLittleEndian),
+                    Some("binary_big_endian") => Some(Encoding::BinaryBigEndian),
+                    other => {
+                        return Err(fail(lineno, format!("unknown PLY format {:?}", other)));
+                    }
+                };
+            }
+            Some("element") => {
+                let name = parts.next().unwrap_or_default().to_string();
+                let count = parts.next().and_then(|c| c.parse().ok());
+                let Some(count) = count else {
+                    return Err(fail(lineno, format!("element {} has no count", name)));
+                };
+                elements.push(Element {
+                    name,
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            Some("property") => {
+                let Some(element) = elements.last_mut() else {
+                    return Err(fail(lineno, String::from("property before any element")));
+                };
+                let tokens: Vec<&str> = parts.collect();
+                let property = match tokens.as_slice() {
+                    ["list", count, item, name] => {
+                        match (Scalar::from_name(count), Scalar::from_name(item)) {
+                            (Some(count), Some(item)) => Some(Property::List {
+                                name: name.to_string(),
+                                count,
+                                item,
+                            }),
+                            _ => None,
+                        }
+                    }
+                    [ty, name] => {
+                        Scalar::from_name(ty).map(|ty| Property::Scalar(name.to_string(), ty))
+                    }
+                    _ => None,
+                };
+                match property {
+                    Some(property) => element.properties.push(property),
+                    None => {
+                        return Err(fail(lineno, format!("invalid property {:?}", line.trim())))
+                    }
+                }
+            }
+            Some("end_header") => {
+                let Some(encoding) = encoding else {
+                    return Err(fail(lineno, String::from("PLY header has no format line")));
+                };
+                return Ok((elements, encoding, lineno));
+            }
+            Some("comment") | Some("obj_info") | None => {}
+            Some(other) => diag.report(lineno, format!("unknown header keyword {:?}", other))?,
+        }
+    }
+    Err(fail(lineno, String::from("PLY header has no end_header")))
+}
+
+/// Column of a scalar vertex property, looked up by any of `names`.
+fn column(element: &Element, names: &[&str]) -> Option<(usize, Scalar)> {
+    element
+        .properties
+        .iter()
+        .enumerate()
+        .find_map(|(i, p)| match p {
+            Property::Scalar(name, ty) if names.contains(&name.as_str()) => Some((i, *ty)),
+            _ => None,
+        })
+}
+
+impl Model {
+    /// Loads an ASCII or binary PLY file, skipping malformed rows with a warning.
+    pub fn from_ply(filename: &str) -> Result<Self, ModelError> {
+        Model::load_ply(filename, ParseMode::Lenient)
+    }
+
+    /// Reads the `vertex` and `face` elements. Positions, normals (`nx`..),
+    /// texture coordinates (`u`/`v` or `s`/`t`) and colours (`red`..) are
+    /// recognised; every other scalar property is kept under its name and can
+    /// be read with `vertex_attribute` and `polygon_attribute`, every other
+    /// list property with `vertex_list_attribute` and `polygon_list_attribute`.
+    pub fn load_ply(filename: &str, mode: ParseMode) -> Result<Self, ModelError> {
+        let data = fs::read(filename).map_err(|source| ModelError::Io {
+            file: filename.to_string(),
+            source,
+        })?;
+        Model::parse_ply(filename, &data, mode)
+    }
+
+    /// `load_ply` on the contents of a file, `filename` is only used in
+    /// messages.
+    fn parse_ply(filename: &str, data: &[u8], mode: ParseMode) -> Result<Self, ModelError> {
+        let diag = Diagnostics {
+            file: filename,
+            mode,
+        };
+        // only the header has to be text for binary files
+        const END_HEADER: &[u8] = b"end_header";
+        let header_len = data
+            .windows(END_HEADER.len())
+            .position(|w| w == END_HEADER)
+            .map_or(data.len(), |i| {
+                let end = i + END_HEADER.len();
+                data[end..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(data.len(), |n| end + n + 1)
+            });
+        let header = String::from_utf8_lossy(&data[..header_len]);
+        let (elements, encoding, header_lines) = parse_header(&header, &diag)?;
+        let text;
+        let mut body = match encoding {
+            Encoding::Ascii => {
+                text = String::from_utf8_lossy(&data[header_len..]);
+                Body::Ascii {
+                    lines: text.lines().enumerate(),
+                    first_line: header_lines + 1,
+                }
+            }
+            Encoding::BinaryLittleEndian | Encoding::BinaryBigEndian => Body::Binary {
+                data: &data[header_len..],
+                pos: 0,
+                big_endian: encoding == Encoding::BinaryBigEndian,
+            },
+        };
+
+        let mut verts = Vec::new();
+        let mut norms = Vec::new();
+        let mut uv = Vec::new();
+        let mut colors = Vec::new();
+        let mut vertex_attributes = BTreeMap::new();
+        let mut polygons: Vec<(Vec<i32>, usize)> = Vec::new();
+        let mut polygon_attributes: BTreeMap<String, Vec<f32>> = BTreeMap::new();
+        let mut vertex_lists = BTreeMap::new();
+        let mut polygon_lists: BTreeMap<String, ListAttribute> = BTreeMap::new();
+
+        for element in &elements {
+            let position = [
+                column(element, &["x"]),
+                column(element, &["y"]),
+                column(element, &["z"]),
+            ];
+            let normal = [
+                column(element, &["nx"]),
+                column(element, &["ny"]),
+                column(element, &["nz"]),
+            ];
+            let texcoord = [
+                column(element, &["u", "s", "texture_u", "texture_s"]),
+                column(element, &["v", "t", "texture_v", "texture_t"]),
+            ];
+            let color = [
+                column(element, &["red", "r", "diffuse_red"]),
+                column(element, &["green", "g", "diffuse_green"]),
+                column(element, &["blue", "b", "diffuse_blue"]),
+                column(element, &["alpha", "a", "diffuse_alpha"]),
+            ];
+            let known: Vec<usize> = position
+                .iter()
+                .chain(&normal)
+                .chain(&texcoord)
+                .chain(&color)
+                .flatten()
+                .map(|&(i, _)| i)
+                .collect();
+            let indices = element.properties.iter().position(|p| {
+                matches!(p, Property::List { name, .. } if name == "vertex_indices" || name == "vertex_index")
+            });
+
+            for _ in 0..element.count {
+                let (lineno, row) = body.row(element);
+                let row = match row {
+                    Ok(row) => row,
+                    // truncated file or a bad binary row, nothing after it can be read
+                    Err(reason) if lineno == 0 => {
+                        return Err(ModelError::Parse {
+                            file: filename.to_string(),
+                            line: 0,
+                            reason,
+                        })
+                    }
+                    Err(reason) => {
+                        diag.report(lineno, reason)?;
+                        // keep the row so later indices stay valid
+                        element
+                            .properties
+                            .iter()
+                            .map(|p| match p {
+                                Property::Scalar(..) => vec![0.0],
+                                Property::List { .. } => Vec::new(),
+                            })
+                            .collect()
+                    }
+                };
+                let get = |c: Option<(usize, Scalar)>, default: f32| {
+                    c.map_or(default, |(i, ty)| row[i][0] as f32 * ty.unit_scale())
+                };
+                let other_attributes =
+                    |scalars: &mut BTreeMap<String, Vec<f32>>,
+                     lists: &mut BTreeMap<String, ListAttribute>| {
+                        for (i, p) in element.properties.iter().enumerate() {
+                            match p {
+                                Property::Scalar(name, _) if !known.contains(&i) => scalars
+                                    .entry(name.clone())
+                                    .or_default()
+                                    .push(row[i][0] as f32),
+                                Property::List { name, .. } if Some(i) != indices => {
+                                    lists.entry(name.clone()).or_default().push(&row[i])
+                                }
+                                _ => {}
+                            }
+                        }
+                    };
+
+                match element.name.as_str() {
+                    "vertex" => {
+                        // positions are never normalised, only colours are
+                        let raw =
+                            |c: Option<(usize, Scalar)>| c.map_or(0.0, |(i, _)| row[i][0] as f32);
+                        verts.push(Vec3f::new(
+                            raw(position[0]),
+                            raw(position[1]),
+                            raw(position[2]),
+                        ));
+                        if normal.iter().all(Option::is_some) {
+                            norms.push(Vec3f::new(raw(normal[0]), raw(normal[1]), raw(normal[2])));
+                        }
+                        if texcoord.iter().all(Option::is_some) {
+                            uv.push(Vec2f::new(raw(texcoord[0]), raw(texcoord[1])));
+                        }
+                        if color[..3].iter().all(Option::is_some) {
+                            colors.push(Vec4f::new(
+                                get(color[0], 0.0),
+                                get(color[1], 0.0),
+                                get(color[2], 0.0),
+                                get(color[3], 1.0),
+                            ));
+                        }
+                        other_attributes(&mut vertex_attributes, &mut vertex_lists);
+                    }
+                    "face" => {
+                        if let Some(i) = indices {
+                            polygons.push((row[i].iter().map(|&v| v as i32).collect(), lineno));
+                            other_attributes(&mut polygon_attributes, &mut polygon_lists);
+                        }
+                    }
+                    // other elements, e.g. edges or materials, are read past
+                    _ => {}
+                }
+            }
+        }
+
+        let has_normals = !norms.is_empty();
+        let has_uv = !uv.is_empty();
+        let mut faces = Vec::new();
+        let mut sources = Vec::new();
+        for (ipoly, (polygon, lineno)) in polygons.iter().enumerate() {
+            if let Some(&bad) = polygon
+                .iter()
+                .find(|&&v| v < 0 || v as usize >= verts.len())
+            {
+                diag.report(
+                    *lineno,
+                    format!(
+                        "vertex index {} out of range, {} vertices",
+                        bad,
+                        verts.len()
+                    ),
+                )?;
+                continue;
+            }
+            if polygon.len() < 3 {
+                diag.report(
+                    *lineno,
+                    format!("face has {} corners, need 3", polygon.len()),
+                )?;
+                continue;
+            }
+            // one index addresses every attribute of a PLY vertex
+            let corners: Vec<Vec3i> = polygon
+                .iter()
+                .map(|&v| {
+                    Vec3i::new(
+                        v,
+                        if has_uv { v } else { -1 },
+                        if has_normals { v } else { -1 },
+                    )
+                })
+                .collect();
+            for [a, b, c] in triangulate(&verts, &corners) {
+                faces.push(vec![corners[a], corners[b], corners[c]]);
+                sources.push(ipoly);
+            }
+        }
+
+        let mut model = Model::from_triangles(verts, norms, uv, faces);
+        for (info, source) in model.face_info.iter_mut().zip(sources) {
+            info.source = source;
+        }
+        model.colors = colors;
+        model.vertex_attributes = vertex_attributes;
+        model.polygon_attributes = polygon_attributes;
+        model.vertex_lists = vertex_lists;
+        model.polygon_lists = polygon_lists;
+        model.print_summary();
+        Ok(model)
+    }
+
+    pub fn has_vertex_colors(&self) -> bool {
+        !self.colors.is_empty()
+    }
+
+    /// Colour of a corner as rgba in 0..=1, `None` without vertex colours.
+    pub fn vertex_color(&self, iface: usize, nthvert: usize) -> Option<Vec4f> {
+        self.colors
+            .get(self.faces[iface][nthvert].x as usize)
+            .copied()
+    }
+
+    /// Unrecognised per-vertex property, indexed like the positions.
+    pub fn vertex_attribute(&self, name: &str) -> Option<&[f32]> {
+        self.vertex_attributes.get(name).map(Vec::as_slice)
+    }
+
+    /// Unrecognised per-face property, indexed by `source_face`.
+    pub fn polygon_attribute(&self, name: &str) -> Option<&[f32]> {
+        self.polygon_attributes.get(name).map(Vec::as_slice)
+    }
+
+    /// Unrecognised per-vertex list property, indexed like the positions.
+    pub fn vertex_list_attribute(&self, name: &str) -> Option<&ListAttribute> {
+        self.vertex_lists.get(name)
+    }
+
+    /// Unrecognised per-face list property, indexed by `source_face`.
+    pub fn polygon_list_attribute(&self, name: &str) -> Option<&ListAttribute> {
+        self.polygon_lists.get(name)
+    }
+
+    pub fn attribute_names(&self) -> impl Iterator<Item = &str> {
+        self.vertex_attributes
+            .keys()
+            .chain(self.polygon_attributes.keys())
+            .chain(self.vertex_lists.keys())
+            .chain(self.polygon_lists.keys())
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 5
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float quality
+property list uchar int neighbours
+element face 2
+property list uchar int vertex_indices
+property list uchar float texcoord
+property uchar flags
+end_header
+";
+
+    const POSITIONS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [2.0, 0.5, 0.0],
+    ];
+    const POLYGONS: [&[i32]; 2] = [&[0, 1, 2, 3], &[1, 4, 2]];
+
+    /// The same five vertices and two faces in any of the three encodings.
+    fn fixture(format: &str) -> Vec<u8> {
+        let mut data = format!("ply\nformat {} 1.0\ncomment test\n{}", format, HEADER).into_bytes();
+        let neighbours = |i: usize| (0..i % 3).map(|n| n as i32).collect::<Vec<_>>();
+        let texcoord = |p: &[i32]| p.iter().map(|&v| v as f32 / 4.0).collect::<Vec<_>>();
+        if format == "ascii" {
+            let mut text = String::new();
+            for (i, p) in POSITIONS.iter().enumerate() {
+                let list = neighbours(i);
+                text += &format!(
+                    "{} {} {} {} 0 255 {} {}",
+                    p[0],
+                    p[1],
+                    p[2],
+                    51 * i,
+                    i as f32 / 2.0,
+                    list.len()
+                );
+                for n in list {
+                    text += &format!(" {}", n);
+                }
+                text += "\n";
+            }
+            for (f, p) in POLYGONS.iter().enumerate() {
+                text += &format!("{}", p.len());
+                for v in p.iter() {
+                    text += &format!(" {}", v);
+                }
+                text += &format!(" {}", 2 * p.len());
+                for t in texcoord(p) {
+                    text += &format!(" {} {}", t, t);
+                }
+                text += &format!(" {}\n", f + 1);
+            }
+            data.extend(text.into_bytes());
+            return data;
+        }
+        // every value is written little endian and reversed for big endian
+        let mut body: Vec<Vec<u8>> = Vec::new();
+        for (i, p) in POSITIONS.iter().enumerate() {
+            body.extend(p.iter().map(|c| c.to_le_bytes().to_vec()));
+            body.extend([vec![51 * i as u8], vec![0], vec![255]]);
+            body.push((i as f32 / 2.0).to_le_bytes().to_vec());
+            let list = neighbours(i);
+            body.push(vec![list.len() as u8]);
+            body.extend(list.iter().map(|n| n.to_le_bytes().to_vec()));
+        }
+        for (f, p) in POLYGONS.iter().enumerate() {
+            body.push(vec![p.len() as u8]);
+            body.extend(p.iter().map(|v| v.to_le_bytes().to_vec()));
+            body.push(vec![2 * p.len() as u8]);
+            for t in texcoord(p) {
+                body.push(t.to_le_bytes().to_vec());
+                body.push(t.to_le_bytes().to_vec());
+            }
+            body.push(vec![f as u8 + 1]);
+        }
+        for mut value in body {
+            if format == "binary_big_endian" {
+                value.reverse();
+            }
+            data.extend(value);
+        }
+        data
+    }
+
+    fn parse(data: &[u8], mode: ParseMode) -> Result<Model, ModelError> {
+        Model::parse_ply("test.ply", data, mode)
+    }
+
+    #[test]
+    fn encodings() {
+        for format in ["ascii", "binary_little_endian", "binary_big_endian"] {
+            let model = parse(&fixture(format), ParseMode::Strict).unwrap();
+            assert_eq!(model.nverts(), 5, "{}", format);
+            for (i, p) in POSITIONS.iter().enumerate() {
+                assert_eq!(model.vert(i), Vec3f::new(p[0], p[1], p[2]), "{}", format);
+            }
+            // the quad is split in two
+            assert_eq!(model.nfaces(), 3, "{}", format);
+            let sources: Vec<usize> = (0..3).map(|f| model.source_face(f)).collect();
+            assert_eq!(sources, [0, 0, 1], "{}", format);
+
+            assert!(model.has_vertex_colors());
+            let color = model.colors[2];
+            assert!((color.x - 0.4).abs() < 1e-6);
+            assert_eq!((color.y, color.z, color.w), (0.0, 1.0, 1.0));
+            assert_eq!(
+                model.vertex_attribute("quality").unwrap(),
+                [0.0, 0.5, 1.0, 1.5, 2.0]
+            );
+            assert_eq!(model.polygon_attribute("flags").unwrap(), [1.0, 2.0]);
+            let names: Vec<&str> = model.attribute_names().collect();
+            assert_eq!(names, ["quality", "flags", "neighbours", "texcoord"]);
+        }
+    }
+
+    #[test]
+    fn list_attributes() {
+        let model = parse(&fixture("binary_little_endian"), ParseMode::Strict).unwrap();
+        let neighbours = model.vertex_list_attribute("neighbours").unwrap();
+        assert_eq!(neighbours.len(), 5);
+        assert_eq!(neighbours.get(0).unwrap(), []);
+        assert_eq!(neighbours.get(2).unwrap(), [0.0, 1.0]);
+        assert_eq!(neighbours.get(4).unwrap(), [0.0]);
+        assert_eq!(neighbours.get(5), None);
+        assert_eq!(neighbours.values(), [0.0, 0.0, 1.0, 0.0]);
+
+        let texcoord = model.polygon_list_attribute("texcoord").unwrap();
+        assert_eq!(texcoord.len(), 2);
+        assert_eq!(texcoord.get(0).unwrap().len(), 8);
+        assert_eq!(texcoord.get(1).unwrap(), [0.25, 0.25, 1.0, 1.0, 0.5, 0.5]);
+        // the indices are the faces, not an attribute
+        assert!(model.polygon_list_attribute("vertex_indices").is_none());
+    }
+
+    #[test]
+    fn triangulates_concave_polygons() {
+        // an L with the notch at (1, 1)
+        let data = "ply
+format ascii 1.0
+element vertex 6
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_index
+end_header
+0 0 0
+2 0 0
+2 1 0
+1 1 0
+1 2 0
+0 2 0
+6 0 1 2 3 4 5
+";
+        let model = parse(data.as_bytes(), ParseMode::Strict).unwrap();
+        assert_eq!(model.nfaces(), 4);
+        let mut area = 0.0;
+        for f in 0..model.nfaces() {
+            let v = |k: usize| model.vert(model.face(f)[k].x as usize);
+            let n = Vec3f::triangle_normal(v(0), v(1), v(2));
+            assert!(n.z > 0.0);
+            area += n.norm() / 2.0;
+        }
+        assert!((area - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn header_errors() {
+        let line = |header: &str| match parse(header.as_bytes(), ParseMode::Lenient) {
+            Err(ModelError::Parse { line, .. }) => line,
+            other => panic!("{:?} for {:?}", other.map(|m| m.nfaces()), header),
+        };
+        assert_eq!(line("solid cube\n"), 1);
+        assert_eq!(line("ply\nelement vertex 0\nend_header\n"), 3);
+        assert_eq!(line("ply\nformat utf8 1.0\nend_header\n"), 2);
+        assert_eq!(
+            line("ply\nformat ascii 1.0\nproperty float x\nend_header\n"),
+            3
+        );
+        assert_eq!(
+            line("ply\nformat ascii 1.0\nelement vertex\nend_header\n"),
+            3
+        );
+        assert_eq!(
+            line("ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n"),
+            4
+        );
+        assert_eq!(
+            line("ply\nformat ascii 1.0\nelement face 1\nproperty list int vertex_indices\nend_header\n"),
+            4
+        );
+        assert_eq!(line("ply\nformat ascii 1.0\nelement vertex 0\n"), 3);
+
+        // unknown keywords only fail strict mode
+        let header = "ply\nformat ascii 1.0\nfoo bar\nend_header\n";
+        assert!(parse(header.as_bytes(), ParseMode::Lenient).is_ok());
+        assert!(parse(header.as_bytes(), ParseMode::Strict).is_err());
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let mut data = fixture("binary_big_endian");
+        data.truncate(data.len() - 3);
+        assert!(parse(&data, ParseMode::Lenient).is_err());
+    }
+}
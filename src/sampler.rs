@@ -0,0 +1,209 @@
+use crate::geometry::Vec2f;
+use crate::tga::{AlphaMode, TGAColor, TGAImage};
+
+/// What happens to texture coordinates outside 0..1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Wrap {
+    /// Tile the texture.
+    #[default]
+    Repeat,
+    /// Stretch the edge texels.
+    Clamp,
+    /// Tile with every other copy flipped, so edges line up.
+    Mirror,
+}
+
+impl Wrap {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "repeat" => Some(Wrap::Repeat),
+            "clamp" => Some(Wrap::Clamp),
+            "mirror" => Some(Wrap::Mirror),
+            _ => None,
+        }
+    }
+
+    /// Maps a texel index onto `0..size`.
+    fn apply(self, i: i32, size: i32) -> i32 {
+        match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Clamp => i.clamp(0, size - 1),
+            Wrap::Mirror => {
+                let m = i.rem_euclid(2 * size);
+                if m < size {
+                    m
+                } else {
+                    2 * size - 1 - m
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Filter {
+    /// The texel under the sample point, as tinyrenderer does.
+    #[default]
+    Nearest,
+    /// Blend of the four nearest texels, weighted by alpha so transparent
+    /// texels do not bleed their colour into the edges of cut-outs.
+    Bilinear,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" => Some(Filter::Bilinear),
+            _ => None,
+        }
+    }
+}
+
+/// Looks up textures by floating point UV. (0, 0) is the first texel of the
+/// image data and (1, 1) the far corner of the last one, the same mapping
+/// the integer texel coordinates used.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sampler {
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+    pub filter: Filter,
+}
+
+impl Sampler {
+    pub fn new(wrap: Wrap, filter: Filter) -> Self {
+        Sampler {
+            wrap_u: wrap,
+            wrap_v: wrap,
+            filter,
+        }
+    }
+
+    fn texel(&self, image: &TGAImage, x: i32, y: i32) -> TGAColor {
+        let x = self.wrap_u.apply(x, image.width());
+        let y = self.wrap_v.apply(y, image.height());
+        image
+            .get(x, y)
+            .unwrap_or_else(|| TGAColor::from_bpp(image.bpp))
+    }
+
+    pub fn sample(&self, image: &TGAImage, uv: Vec2f) -> TGAColor {
+        if image.width() <= 0 || image.height() <= 0 {
+            return TGAColor::from_bpp(image.bpp);
+        }
+        let x = uv.x * image.width() as f32;
+        let y = uv.y * image.height() as f32;
+        match self.filter {
+            Filter::Nearest => self.texel(image, x.floor() as i32, y.floor() as i32),
+            Filter::Bilinear => {
+                // texel centres sit at half integers
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let taps = [
+                    (self.texel(image, x0, y0), (1.0 - fx) * (1.0 - fy)),
+                    (self.texel(image, x0 + 1, y0), fx * (1.0 - fy)),
+                    (self.texel(image, x0, y0 + 1), (1.0 - fx) * fy),
+                    (self.texel(image, x0 + 1, y0 + 1), fx * fy),
+                ];
+                // straight alpha is blended premultiplied and divided back
+                let straight = image.bpp == 4 && image.alpha_mode() == AlphaMode::Straight;
+                let mut sum = [0.0f32; 4];
+                for (c, w) in taps {
+                    let scale = if straight { c[3] as f32 / 255.0 } else { 1.0 };
+                    for (i, acc) in sum.iter_mut().enumerate() {
+                        *acc += c[i] as f32 * w * if i < 3 { scale } else { 1.0 };
+                    }
+                }
+                if straight {
+                    let a = sum[3] / 255.0;
+                    for acc in &mut sum[..3] {
+                        *acc = if a > 0.0 { *acc / a } else { 0.0 };
+                    }
+                }
+                let [b, g, r, a] = sum.map(|v| v.round().clamp(0.0, 255.0) as u8);
+                TGAColor::from_rgba(r, g, b, a)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tga::Format;
+
+    /// One row of four texels, 0, 80, 160 and 240.
+    fn ramp() -> TGAImage {
+        let mut image = TGAImage::new(4, 1, Format::Grayscale);
+        for x in 0..4 {
+            let v = x as u8 * 80;
+            image.set(x, 0, TGAColor::from_rgb(v, v, v)).unwrap();
+        }
+        image
+    }
+
+    fn gray(sampler: Sampler, image: &TGAImage, u: f32) -> u8 {
+        sampler.sample(image, Vec2f::new(u, 0.5))[0]
+    }
+
+    #[test]
+    fn wraps_coordinates_outside_the_texture() {
+        let image = ramp();
+        let cases = [
+            (Wrap::Repeat, [240, 80, 0]),
+            (Wrap::Clamp, [0, 240, 240]),
+            (Wrap::Mirror, [0, 160, 0]),
+        ];
+        for (wrap, expected) in cases {
+            let sampler = Sampler::new(wrap, Filter::Nearest);
+            let got = [-0.25, 1.25, 2.0].map(|u| gray(sampler, &image, u));
+            assert_eq!(got, expected, "{:?}", wrap);
+        }
+    }
+
+    #[test]
+    fn bilinear_weights() {
+        let image = ramp();
+        let sampler = Sampler::new(Wrap::Clamp, Filter::Bilinear);
+        // texel centres return the texel itself
+        for x in 0..4 {
+            assert_eq!(gray(sampler, &image, (x as f32 + 0.5) / 4.0), x as u8 * 80);
+        }
+        // halfway and a quarter of the way between two centres
+        assert_eq!(gray(sampler, &image, 0.25), 40);
+        assert_eq!(gray(sampler, &image, 0.3125), 60);
+
+        // the outer half texel blends with whatever lies past the edge
+        let edges = [
+            (Wrap::Repeat, [120, 120]),
+            (Wrap::Clamp, [0, 240]),
+            (Wrap::Mirror, [0, 240]),
+        ];
+        for (wrap, expected) in edges {
+            let sampler = Sampler::new(wrap, Filter::Bilinear);
+            let got = [0.0, 1.0].map(|u| gray(sampler, &image, u));
+            assert_eq!(got, expected, "{:?}", wrap);
+        }
+    }
+
+    #[test]
+    fn bilinear_ignores_the_colour_of_transparent_texels() {
+        let mut image = TGAImage::new(2, 1, Format::RGBA);
+        image.set(0, 0, TGAColor::from_rgba(255, 0, 0, 0)).unwrap();
+        image
+            .set(1, 0, TGAColor::from_rgba(0, 255, 0, 255))
+            .unwrap();
+        let sampler = Sampler::new(Wrap::Clamp, Filter::Bilinear);
+        let c = sampler.sample(&image, Vec2f::new(0.5, 0.5));
+        assert_eq!([c[2], c[1], c[3]], [0, 255, 128]);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Wrap::from_name("mirror"), Some(Wrap::Mirror));
+        assert_eq!(Filter::from_name("bilinear"), Some(Filter::Bilinear));
+        assert_eq!(Wrap::from_name("border"), None);
+    }
+}
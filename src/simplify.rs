@@ -0,0 +1,684 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::geometry::{Vec3f, Vec3i};
+use crate::model::Model;
+
+// constraint planes along boundaries and seams count this much more than
+// the surface, so collapses do not pull them inwards
+const BOUNDARY_WEIGHT: f64 = 10.0;
+
+/// When `Model::simplify` stops collapsing edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimplifyOptions {
+    /// Stop once no more than this many triangles are left.
+    pub target_triangles: usize,
+    /// Never make a collapse whose error is larger, in model units. The
+    /// error is the root mean square distance of the new vertex to the
+    /// planes of the original faces it stands for.
+    pub max_error: f32,
+    /// Keep every vertex on an open boundary. Otherwise boundary vertices
+    /// may only slide along the boundary.
+    pub lock_boundary: bool,
+}
+
+impl SimplifyOptions {
+    /// Collapse down to `triangles`, however large the error gets.
+    pub fn triangles(triangles: usize) -> Self {
+        SimplifyOptions {
+            target_triangles: triangles,
+            max_error: f32::INFINITY,
+            lock_boundary: false,
+        }
+    }
+
+    /// Collapse as far as possible while staying within `max_error`.
+    pub fn error(max_error: f32) -> Self {
+        SimplifyOptions {
+            target_triangles: 0,
+            max_error,
+            lock_boundary: false,
+        }
+    }
+}
+
+/// One level of detail built by `Model::lod_chain`.
+#[derive(Debug, Clone)]
+pub struct Lod {
+    pub model: Model,
+    /// Largest collapse error relative to the full model, 0 for level 0.
+    pub error: f32,
+}
+
+/// Symmetric 4x4 error quadric of Garland and Heckbert, stored as the upper
+/// triangle of A, the vector b and the constant c, with Q(p) = pAp + 2bp + c.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    a: [f64; 6],
+    b: [f64; 3],
+    c: f64,
+    // area of the faces summed in, to turn Q into a mean squared distance
+    weight: f64,
+}
+
+impl Quadric {
+    /// Squared distance to the plane through `p` with unit normal `n`,
+    /// times `w`.
+    fn plane(n: Vec3f, p: Vec3f, w: f64) -> Quadric {
+        let (x, y, z) = (n.x as f64, n.y as f64, n.z as f64);
+        let d = -(x * p.x as f64 + y * p.y as f64 + z * p.z as f64);
+        Quadric {
+            a: [
+                x * x * w,
+                x * y * w,
+                x * z * w,
+                y * y * w,
+                y * z * w,
+                z * z * w,
+            ],
+            b: [x * d * w, y * d * w, z * d * w],
+            c: d * d * w,
+            weight: 0.0,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for i in 0..6 {
+            self.a[i] += other.a[i];
+        }
+        for i in 0..3 {
+            self.b[i] += other.b[i];
+        }
+        self.c += other.c;
+        self.weight += other.weight;
+    }
+
+    /// Root mean square distance of `p` to the summed planes.
+    fn error(&self, p: Vec3f) -> f64 {
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        let [a00, a01, a02, a11, a12, a22] = self.a;
+        let q = a00 * x * x
+            + a11 * y * y
+            + a22 * z * z
+            + 2.0 * (a01 * x * y + a02 * x * z + a12 * y * z)
+            + 2.0 * (self.b[0] * x + self.b[1] * y + self.b[2] * z)
+            + self.c;
+        let q = q.max(0.0);
+        if self.weight > 0.0 {
+            (q / self.weight).sqrt()
+        } else {
+            q.sqrt()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Interior,
+    /// On an open boundary, may only collapse along it.
+    Border,
+    /// Has two sets of UVs, normals or submeshes, may only collapse along
+    /// the seam between them.
+    Seam,
+    /// Corners of seams and boundaries, vertices of faces on non-manifold
+    /// edges.
+    Locked,
+}
+
+/// A corner's attributes besides the position: uv index, normal index and
+/// submesh. Corners of one vertex with different wedges lie on a seam.
+type Wedge = (i32, i32, usize);
+
+/// Candidate collapse of vertex `u` onto vertex `v`, ordered so the binary
+/// heap pops the cheapest first.
+struct Collapse {
+    error: f64,
+    u: usize,
+    v: usize,
+    stamp: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.total_cmp(&self.error)
+    }
+}
+
+struct Simplifier<'a> {
+    // collapses only ever move a vertex onto another, positions never change
+    verts: &'a [Vec3f],
+    faces: Vec<[Vec3i; 3]>,
+    // submesh of every face
+    groups: Vec<usize>,
+    alive: Vec<bool>,
+    // faces around every vertex, may still list dead faces
+    vertex_faces: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    kinds: Vec<Kind>,
+    removed: Vec<bool>,
+    stamps: Vec<u32>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn wedge(&self, f: usize, k: usize) -> Wedge {
+        let c = self.faces[f][k];
+        (c.y, c.z, self.groups[f])
+    }
+
+    fn corner(&self, f: usize, v: usize) -> Option<usize> {
+        self.faces[f].iter().position(|c| c.x as usize == v)
+    }
+
+    fn faces_of(&self, v: usize) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_faces[v]
+            .iter()
+            .copied()
+            .filter(|&f| self.alive[f])
+    }
+
+    /// Whether only one face is left on the edge between `a` and `b`.
+    fn is_border_edge(&self, a: usize, b: usize) -> bool {
+        self.faces_of(a)
+            .filter(|&f| self.corner(f, b).is_some())
+            .count()
+            == 1
+    }
+
+    fn neighbours(&self, v: usize) -> Vec<usize> {
+        let mut ring: Vec<usize> = self
+            .faces_of(v)
+            .flat_map(|f| self.faces[f].iter().map(|c| c.x as usize))
+            .filter(|&w| w != v)
+            .collect();
+        ring.sort_unstable();
+        ring.dedup();
+        ring
+    }
+
+    /// Adjacency, quadrics and vertex kinds of `model`. Faces with two
+    /// corners on one vertex start out dead.
+    fn new(model: &'a Model, lock_boundary: bool) -> Simplifier<'a> {
+        let mut groups = vec![usize::MAX; model.faces.len()];
+        for (i, submesh) in model.submeshes.iter().enumerate() {
+            for f in submesh.faces.clone() {
+                groups[f] = i;
+            }
+        }
+        let faces: Vec<[Vec3i; 3]> = model
+            .faces
+            .iter()
+            .map(|face| [face[0], face[1], face[2]])
+            .collect();
+        let alive: Vec<bool> = faces
+            .iter()
+            .map(|[a, b, c]| a.x != b.x && b.x != c.x && a.x != c.x)
+            .collect();
+        let n = model.verts.len();
+        let mut s = Simplifier {
+            verts: &model.verts,
+            faces,
+            groups,
+            alive,
+            vertex_faces: vec![Vec::new(); n],
+            quadrics: vec![Quadric::default(); n],
+            kinds: vec![Kind::Interior; n],
+            removed: vec![false; n],
+            stamps: vec![0; n],
+        };
+        for f in 0..s.faces.len() {
+            if s.alive[f] {
+                for c in s.faces[f] {
+                    s.vertex_faces[c.x as usize].push(f);
+                }
+            }
+        }
+
+        // edges with the faces on them
+        let mut edges: Vec<(usize, usize, usize)> = Vec::new();
+        for f in (0..s.faces.len()).filter(|&f| s.alive[f]) {
+            for k in 0..3 {
+                let (a, b) = (s.faces[f][k].x as usize, s.faces[f][(k + 1) % 3].x as usize);
+                edges.push((a.min(b), a.max(b), f));
+            }
+        }
+        edges.sort_unstable();
+
+        for f in (0..s.faces.len()).filter(|&f| s.alive[f]) {
+            let [p0, p1, p2] = s.faces[f].map(|c| s.verts[c.x as usize]);
+            let cross = Vec3f::triangle_normal(p0, p1, p2);
+            let area = cross.norm() as f64 * 0.5;
+            if area <= 0.0 {
+                continue;
+            }
+            let mut q = Quadric::plane(cross.normalized(), p0, area);
+            q.weight = area;
+            for c in s.faces[f] {
+                s.quadrics[c.x as usize].add(&q);
+            }
+        }
+        let mut border_edges = vec![0; n];
+        let mut non_manifold = vec![false; n];
+        let mut i = 0;
+        while i < edges.len() {
+            let (a, b, _) = edges[i];
+            let run = edges[i..]
+                .iter()
+                .take_while(|e| e.0 == a && e.1 == b)
+                .count();
+            let on_edge: Vec<usize> = edges[i..i + run].iter().map(|e| e.2).collect();
+            i += run;
+            let seam = run == 2 && {
+                let wedge_at = |f: usize, v: usize| s.wedge(f, s.corner(f, v).unwrap_or(0));
+                wedge_at(on_edge[0], a) != wedge_at(on_edge[1], a)
+                    || wedge_at(on_edge[0], b) != wedge_at(on_edge[1], b)
+            };
+            match run {
+                1 => {
+                    border_edges[a] += 1;
+                    border_edges[b] += 1;
+                }
+                2 => {}
+                // the wings hanging off the edge are as fragile as the edge
+                _ => {
+                    for &f in &on_edge {
+                        for c in s.faces[f] {
+                            non_manifold[c.x as usize] = true;
+                        }
+                    }
+                }
+            }
+            if run == 1 || seam {
+                // plane through the edge, perpendicular to each face on it
+                let (pa, pb) = (s.verts[a], s.verts[b]);
+                let edge = pb - pa;
+                for &f in &on_edge {
+                    let normal = s.face_normal(f, None);
+                    let mut n = edge ^ normal;
+                    if n.norm() == 0.0 {
+                        continue;
+                    }
+                    n.normalize();
+                    let w = BOUNDARY_WEIGHT * (edge.norm() as f64).powi(2);
+                    let q = Quadric::plane(n, pa, w);
+                    s.quadrics[a].add(&q);
+                    s.quadrics[b].add(&q);
+                }
+            }
+        }
+
+        for v in 0..n {
+            let mut wedges: Vec<Wedge> = s
+                .faces_of(v)
+                .filter_map(|f| s.corner(f, v).map(|k| s.wedge(f, k)))
+                .collect();
+            wedges.sort_unstable();
+            wedges.dedup();
+            let border = border_edges[v] > 0;
+            s.kinds[v] = if non_manifold[v]
+                || wedges.len() > 2
+                || (border && (lock_boundary || border_edges[v] != 2 || wedges.len() > 1))
+            {
+                Kind::Locked
+            } else if border {
+                Kind::Border
+            } else if wedges.len() == 2 {
+                Kind::Seam
+            } else {
+                Kind::Interior
+            };
+        }
+        s
+    }
+
+    /// Unnormalised normal of face `f`, with vertex `moved` standing at a
+    /// new position.
+    fn face_normal(&self, f: usize, moved: Option<(usize, Vec3f)>) -> Vec3f {
+        let [p0, p1, p2] = self.faces[f].map(|c| match moved {
+            Some((v, p)) if c.x as usize == v => p,
+            _ => self.verts[c.x as usize],
+        });
+        Vec3f::triangle_normal(p0, p1, p2)
+    }
+
+    /// Wedge of `v` that every wedge of `u` turns into when `u` collapses
+    /// onto `v`, or `None` if the collapse would tear or merge a seam.
+    fn wedge_map(&self, u: usize, v: usize) -> Option<Vec<(Wedge, Wedge)>> {
+        let mut map: Vec<(Wedge, Wedge)> = Vec::new();
+        for f in self.faces_of(u) {
+            if let (Some(ku), Some(kv)) = (self.corner(f, u), self.corner(f, v)) {
+                let (wu, wv) = (self.wedge(f, ku), self.wedge(f, kv));
+                match map.iter().find(|(from, _)| *from == wu) {
+                    Some((_, to)) if *to != wv => return None,
+                    Some(_) => {}
+                    None => map.push((wu, wv)),
+                }
+            }
+        }
+        // every wedge of u needs a counterpart across the collapsed edge
+        for f in self.faces_of(u) {
+            let wu = self.wedge(f, self.corner(f, u)?);
+            if !map.iter().any(|(from, _)| *from == wu) {
+                return None;
+            }
+        }
+        // two wedges of u landing on one of v would merge the sides of a seam
+        if map.len() > 1 && map.iter().skip(1).any(|(_, to)| *to == map[0].1) {
+            return None;
+        }
+        Some(map)
+    }
+
+    /// Whether `u` may collapse onto `v` without breaking the topology,
+    /// tearing seams, eroding boundaries or flipping faces.
+    fn can_collapse(&self, u: usize, v: usize) -> Option<Vec<(Wedge, Wedge)>> {
+        if self.removed[u] || self.removed[v] || self.kinds[u] == Kind::Locked {
+            return None;
+        }
+        let shared = self
+            .faces_of(u)
+            .filter(|&f| self.corner(f, v).is_some())
+            .count();
+        if shared == 0 || (self.kinds[u] == Kind::Border && shared != 1) {
+            return None;
+        }
+        // collapsing the tip of an ear, a face with two boundary edges, cuts
+        // the whole face off the boundary
+        if self.kinds[u] == Kind::Border {
+            let ear = self.faces_of(u).any(|f| {
+                self.corner(f, v).is_some()
+                    && (0..3)
+                        .filter(|&k| {
+                            let (a, b) = (self.faces[f][k].x, self.faces[f][(k + 1) % 3].x);
+                            self.is_border_edge(a as usize, b as usize)
+                        })
+                        .count()
+                        >= 2
+            });
+            if ear {
+                return None;
+            }
+        }
+        // link condition: u and v may only share the vertices opposite the
+        // edge, otherwise the collapse pinches the surface
+        let ring_v = self.neighbours(v);
+        let common = self
+            .neighbours(u)
+            .iter()
+            .filter(|w| ring_v.binary_search(w).is_ok())
+            .count();
+        if common != shared {
+            return None;
+        }
+        // a lone triangle or the last faces of a component would vanish
+        if self.faces_of(v).count() == shared {
+            return None;
+        }
+        let map = self.wedge_map(u, v)?;
+        let target = self.verts[v];
+        for f in self.faces_of(u) {
+            if self.corner(f, v).is_some() {
+                continue;
+            }
+            // folding a tetrahedron puts this face on top of one around v
+            let others: Vec<usize> = self.faces[f]
+                .iter()
+                .map(|c| c.x as usize)
+                .filter(|&w| w != u)
+                .collect();
+            if self
+                .faces_of(v)
+                .any(|g| others.iter().all(|&w| self.corner(g, w).is_some()))
+            {
+                return None;
+            }
+            // turning a face by more than about 75 degrees is a fold in the making
+            let before = self.face_normal(f, None);
+            let after = self.face_normal(f, Some((u, target)));
+            if after.norm() == 0.0 || before * after < 0.25 * before.norm() * after.norm() {
+                return None;
+            }
+        }
+        Some(map)
+    }
+
+    /// Cheapest allowed collapse of `u`, pushed onto the heap under a new
+    /// stamp so older entries for `u` are ignored.
+    fn update(&mut self, u: usize, heap: &mut BinaryHeap<Collapse>) {
+        self.stamps[u] += 1;
+        if self.removed[u] || self.kinds[u] == Kind::Locked {
+            return;
+        }
+        let mut best: Option<Collapse> = None;
+        for v in self.neighbours(u) {
+            if self.can_collapse(u, v).is_none() {
+                continue;
+            }
+            let mut q = self.quadrics[u];
+            q.add(&self.quadrics[v]);
+            let error = q.error(self.verts[v]);
+            if best.as_ref().is_none_or(|b| error < b.error) {
+                best = Some(Collapse {
+                    error,
+                    u,
+                    v,
+                    stamp: self.stamps[u],
+                });
+            }
+        }
+        if let Some(best) = best {
+            heap.push(best);
+        }
+    }
+
+    fn collapse(&mut self, u: usize, v: usize, map: &[(Wedge, Wedge)]) -> usize {
+        let mut killed = 0;
+        for f in self.vertex_faces[u].clone() {
+            if !self.alive[f] {
+                continue;
+            }
+            let Some(ku) = self.corner(f, u) else {
+                continue;
+            };
+            if self.corner(f, v).is_some() {
+                self.alive[f] = false;
+                killed += 1;
+                continue;
+            }
+            let wu = self.wedge(f, ku);
+            if let Some(&(_, (uv, normal, _))) = map.iter().find(|(from, _)| *from == wu) {
+                self.faces[f][ku] = Vec3i::new(v as i32, uv, normal);
+            }
+            self.vertex_faces[v].push(f);
+        }
+        let q = self.quadrics[u];
+        self.quadrics[v].add(&q);
+        self.removed[u] = true;
+        self.vertex_faces[u].clear();
+        killed
+    }
+}
+
+impl Model {
+    /// Decimates the mesh by quadric error edge collapses after Garland and
+    /// Heckbert until `options` is met. Every collapse moves a vertex onto
+    /// a neighbour, so positions, texture coordinates and normals stay those
+    /// of the original. Vertices on UV, normal or submesh seams only move
+    /// along the seam and boundary vertices along the boundary. Returns the
+    /// largest error of any collapse made.
+    pub fn simplify(&mut self, options: &SimplifyOptions) -> f32 {
+        let mut s = Simplifier::new(self, options.lock_boundary);
+        let mut remaining = s.alive.iter().filter(|&&a| a).count();
+        let mut heap = BinaryHeap::new();
+        for u in 0..s.verts.len() {
+            s.update(u, &mut heap);
+        }
+        let mut max_error = 0.0f64;
+        while remaining > options.target_triangles {
+            let Some(c) = heap.pop() else {
+                break;
+            };
+            if c.stamp != s.stamps[c.u] {
+                continue;
+            }
+            if c.error > options.max_error as f64 {
+                break;
+            }
+            let Some(map) = s.can_collapse(c.u, c.v) else {
+                s.update(c.u, &mut heap);
+                continue;
+            };
+            remaining -= s.collapse(c.u, c.v, &map);
+            max_error = max_error.max(c.error);
+            let mut ring = s.neighbours(c.v);
+            ring.push(c.v);
+            for w in ring {
+                s.update(w, &mut heap);
+            }
+        }
+        let alive = s.alive;
+        self.faces = s.faces.iter().map(|face| face.to_vec()).collect();
+        self.retain_faces(&alive);
+        if !self.tangents.is_empty() {
+            self.compute_tangents();
+        }
+        self.remove_unused();
+        max_error as f32
+    }
+
+    /// Levels of detail down to `levels` more models, each with about
+    /// `ratio` times the triangles of the one before. Every level is
+    /// simplified from the full model, so its error is measured against
+    /// it. Stops early once a level does not get any smaller.
+    pub fn lod_chain(&self, levels: usize, ratio: f32) -> Vec<Lod> {
+        let mut chain = vec![Lod {
+            model: self.clone(),
+            error: 0.0,
+        }];
+        let mut target = self.faces.len() as f32;
+        for _ in 0..levels {
+            target *= ratio;
+            let mut model = self.clone();
+            let error = model.simplify(&SimplifyOptions::triangles(target as usize));
+            if chain
+                .last()
+                .is_some_and(|l| model.nfaces() >= l.model.nfaces())
+            {
+                break;
+            }
+            chain.push(Lod { model, error });
+        }
+        chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec2f;
+
+    /// Flat n x n quad grid over the unit square. With `seam` the left and
+    /// right half get their own texture coordinates, split down x = 0.5.
+    fn grid(n: i32, seam: bool) -> Model {
+        let mut verts = Vec::new();
+        let mut uv = Vec::new();
+        for j in 0..=n {
+            for i in 0..=n {
+                let (x, y) = (i as f32 / n as f32, j as f32 / n as f32);
+                verts.push(Vec3f::new(x, y, 0.0));
+                uv.push(Vec2f::new(x, y));
+            }
+        }
+        // the right island repeats every texture coordinate, shifted right by one
+        let island = uv.len() as i32;
+        if seam {
+            uv.extend(uv.clone().iter().map(|t| Vec2f::new(t.x + 1.0, t.y)));
+        }
+        let mut faces = Vec::new();
+        for j in 0..n {
+            for i in 0..n {
+                let shift = if seam && 2 * i >= n { island } else { 0 };
+                let c = |i: i32, j: i32| {
+                    let v = j * (n + 1) + i;
+                    Vec3i::new(v, v + shift, -1)
+                };
+                faces.push(vec![c(i, j), c(i + 1, j), c(i + 1, j + 1)]);
+                faces.push(vec![c(i, j), c(i + 1, j + 1), c(i, j + 1)]);
+            }
+        }
+        Model::from_triangles(verts, Vec::new(), uv, faces)
+    }
+
+    fn area(model: &Model) -> f32 {
+        (0..model.nfaces())
+            .map(|f| {
+                let v = |k: usize| model.vert(model.face(f)[k].x as usize);
+                Vec3f::triangle_normal(v(0), v(1), v(2)).norm() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn keeps_the_boundary() {
+        let mut model = grid(6, false);
+        model.simplify(&SimplifyOptions::error(1e-4));
+        assert!(model.nfaces() < 72);
+        assert!((area(&model) - 1.0).abs() < 1e-4);
+        for corner in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            assert!((0..model.nverts()).any(|i| {
+                let v = model.vert(i);
+                (v.x, v.y) == corner
+            }));
+        }
+    }
+
+    #[test]
+    fn keeps_uv_seams() {
+        let mut model = grid(6, true);
+        model.simplify(&SimplifyOptions::error(1e-4));
+        assert!(model.nfaces() < 72);
+        assert!((area(&model) - 1.0).abs() < 1e-4);
+        for f in 0..model.nfaces() {
+            // a face takes its texture coordinates from one island, on its side
+            let right = model.uv(f, 0).x > 1.0;
+            for k in 0..3 {
+                assert_eq!(model.uv(f, k).x > 1.0, right);
+                let x = model.vert(model.face(f)[k].x as usize).x;
+                assert!(if right { x >= 0.5 } else { x <= 0.5 });
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_a_fin() {
+        // three faces on the edge 0-1
+        let verts = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, -1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        ];
+        let faces = [[0, 1, 2], [1, 0, 3], [0, 1, 4]]
+            .iter()
+            .map(|f| f.iter().map(|&v| Vec3i::new(v, -1, -1)).collect())
+            .collect();
+        let mut model = Model::from_triangles(verts, Vec::new(), Vec::new(), faces);
+        let error = model.simplify(&SimplifyOptions::triangles(0));
+        assert_eq!(model.nfaces(), 3);
+        assert_eq!(error, 0.0);
+    }
+}
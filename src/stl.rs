@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::geometry::{Vec3f, Vec3i};
+use crate::model::{parse_floats, Diagnostics, Model, ModelError, ParseMode};
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+/// STL repeats every shared corner, so positions are merged when their bits
+/// match. Without this every triangle would be its own island and smooth
+/// normals could not be computed.
+#[derive(Default)]
+struct Welder {
+    verts: Vec<Vec3f>,
+    lookup: HashMap<[u32; 3], i32>,
+}
+
+impl Welder {
+    fn index(&mut self, p: Vec3f) -> i32 {
+        // adding zero turns -0.0 into 0.0 so both weld
+        let key = [
+            (p.x + 0.0).to_bits(),
+            (p.y + 0.0).to_bits(),
+            (p.z + 0.0).to_bits(),
+        ];
+        let verts = &mut self.verts;
+        *self.lookup.entry(key).or_insert_with(|| {
+            verts.push(p);
+            verts.len() as i32 - 1
+        })
+    }
+}
+
+/// Collects facets into welded positions and one normal per facet.
+#[derive(Default)]
+struct Builder {
+    welder: Welder,
+    norms: Vec<Vec3f>,
+    faces: Vec<Vec<Vec3i>>,
+}
+
+impl Builder {
+    fn facet(&mut self, normal: Vec3f, corners: [Vec3f; 3]) {
+        // exporters often leave the normal zero, take the winding instead
+        let n = if normal.norm() > 0.0 {
+            normal
+        } else {
+            Vec3f::triangle_normal(corners[0], corners[1], corners[2])
+        };
+        self.norms.push(n.normalized());
+        let ni = self.norms.len() as i32 - 1;
+        let face = corners
+            .iter()
+            .map(|&p| Vec3i::new(self.welder.index(p), -1, ni))
+            .collect();
+        self.faces.push(face);
+    }
+
+    fn finish(self) -> Model {
+        let model = Model::from_triangles(self.welder.verts, self.norms, Vec::new(), self.faces);
+        model.print_summary();
+        model
+    }
+}
+
+fn read_vec3(data: &[u8]) -> Vec3f {
+    let f = |i: usize| f32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+    Vec3f::new(f(0), f(4), f(8))
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if HEADER_SIZE + 4 + count * TRIANGLE_SIZE == data.len() {
+            return true;
+        }
+    }
+    // binary headers may start with "solid" too, so the size check goes first
+    !data.trim_ascii_start().starts_with(b"solid")
+}
+
+impl Model {
+    /// Loads an ASCII or binary STL file, skipping malformed facets with a warning.
+    pub fn from_stl(filename: &str) -> Result<Self, ModelError> {
+        Model::load_stl(filename, ParseMode::Lenient)
+    }
+
+    pub fn load_stl(filename: &str, mode: ParseMode) -> Result<Self, ModelError> {
+        let data = fs::read(filename).map_err(|source| ModelError::Io {
+            file: filename.to_string(),
+            source,
+        })?;
+        Model::parse_stl(filename, &data, mode)
+    }
+
+    /// `load_stl` on the contents of a file, `filename` is only used in
+    /// messages.
+    fn parse_stl(filename: &str, data: &[u8], mode: ParseMode) -> Result<Self, ModelError> {
+        let diag = Diagnostics {
+            file: filename,
+            mode,
+        };
+        if is_binary(data) {
+            parse_binary(data, &diag)
+        } else {
+            parse_ascii(&String::from_utf8_lossy(data), &diag)
+        }
+    }
+}
+
+fn parse_binary(data: &[u8], diag: &Diagnostics) -> Result<Model, ModelError> {
+    if data.len() < HEADER_SIZE + 4 {
+        return Err(ModelError::Parse {
+            file: diag.file.to_string(),
+            line: 0,
+            reason: format!("binary STL is only {} bytes long", data.len()),
+        });
+    }
+    let declared = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    let body = &data[HEADER_SIZE + 4..];
+    let available = body.len() / TRIANGLE_SIZE;
+    if available != declared {
+        // binary files have no lines, the position is reported as line 0
+        diag.report(
+            0,
+            format!(
+                "binary STL declares {} triangles but holds {}",
+                declared, available
+            ),
+        )?;
+    }
+
+    let mut builder = Builder::default();
+    let finite = |v: Vec3f| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+    for (i, triangle) in body.chunks_exact(TRIANGLE_SIZE).take(declared).enumerate() {
+        // normal, three corners, then a 16-bit attribute that is ignored
+        let mut normal = read_vec3(&triangle[0..]);
+        let corners = [
+            read_vec3(&triangle[12..]),
+            read_vec3(&triangle[24..]),
+            read_vec3(&triangle[36..]),
+        ];
+        // like the ASCII reader, a bad normal is replaced and a bad corner
+        // drops the facet
+        if !finite(normal) {
+            diag.report(0, format!("triangle {} has a non-finite normal", i))?;
+            normal = Vec3f::new(0.0, 0.0, 0.0);
+        }
+        if !corners.iter().all(|&c| finite(c)) {
+            diag.report(0, format!("triangle {} has a non-finite vertex", i))?;
+            continue;
+        }
+        builder.facet(normal, corners);
+    }
+    Ok(builder.finish())
+}
+
+fn parse_ascii(text: &str, diag: &Diagnostics) -> Result<Model, ModelError> {
+    let mut builder = Builder::default();
+    let mut normal = Vec3f::new(0.0, 0.0, 0.0);
+    let mut corners = Vec::new();
+    let mut facet_line = 0;
+
+    for (lineno, line) in text.lines().enumerate() {
+        let lineno = lineno + 1;
+        let mut parts = line.split_whitespace();
+        let Some(first) = parts.next() else {
+            continue;
+        };
+        match first {
+            "facet" => {
+                facet_line = lineno;
+                corners.clear();
+                normal = Vec3f::new(0.0, 0.0, 0.0);
+                if parts.next() == Some("normal") {
+                    match parse_floats(parts, 3, 3) {
+                        Ok(c) => normal = Vec3f::new(c[0], c[1], c[2]),
+                        Err(reason) => diag.report(lineno, reason)?,
+                    }
+                }
+            }
+            "vertex" => match parse_floats(parts, 3, 3) {
+                Ok(c) => corners.push(Vec3f::new(c[0], c[1], c[2])),
+                Err(reason) => diag.report(lineno, reason)?,
+            },
+            "endfacet" => {
+                if corners.len() == 3 {
+                    builder.facet(normal, [corners[0], corners[1], corners[2]]);
+                } else {
+                    diag.report(
+                        facet_line,
+                        format!("facet has {} vertices, need 3", corners.len()),
+                    )?;
+                }
+                corners.clear();
+            }
+            "solid" | "endsolid" | "outer" | "endloop" => {}
+            _ => diag.report(lineno, format!("unknown keyword {:?}", first))?,
+        }
+    }
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles of the unit square in the z = 0 plane, sharing the
+    /// diagonal.
+    const SQUARE: [[[f32; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+    ];
+
+    fn binary(header: &[u8], triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(HEADER_SIZE, b' ');
+        data.extend((triangles.len() as u32).to_le_bytes());
+        for t in triangles {
+            data.extend([0.0f32; 3].iter().flat_map(|v| v.to_le_bytes()));
+            for c in t {
+                data.extend(c.iter().flat_map(|v| v.to_le_bytes()));
+            }
+            data.extend([0, 0]);
+        }
+        data
+    }
+
+    fn ascii(triangles: &[[[f32; 3]; 3]]) -> String {
+        let mut text = String::from("solid square\n");
+        for t in triangles {
+            text += "  facet normal 0 0 1\n    outer loop\n";
+            for c in t {
+                text += &format!("      vertex {} {} {}\n", c[0], c[1], c[2]);
+            }
+            text += "    endloop\n  endfacet\n";
+        }
+        text + "endsolid square\n"
+    }
+
+    fn parse(data: &[u8], mode: ParseMode) -> Result<Model, ModelError> {
+        Model::parse_stl("test.stl", data, mode)
+    }
+
+    #[test]
+    fn detects_the_encoding() {
+        assert!(!is_binary(ascii(&SQUARE).as_bytes()));
+        assert!(!is_binary(b"\n  solid indented\nendsolid\n"));
+        assert!(is_binary(&binary(b"exported", &SQUARE)));
+        // the size matches the triangle count, so "solid" is only a header
+        assert!(is_binary(&binary(b"solid but binary", &SQUARE)));
+        // neither size nor keyword fit, read as binary to report the size
+        assert!(is_binary(&binary(b"exported", &SQUARE)[..100]));
+
+        for data in [ascii(&SQUARE).into_bytes(), binary(b"solid x", &SQUARE)] {
+            let model = parse(&data, ParseMode::Strict).unwrap();
+            assert_eq!(model.nfaces(), 2);
+            assert_eq!(
+                model.vert(model.face(1)[2].x as usize),
+                Vec3f::new(0.0, 1.0, 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn welds_shared_corners() {
+        let model = parse(&binary(b"", &SQUARE), ParseMode::Strict).unwrap();
+        assert_eq!(model.nverts(), 4);
+        assert_eq!(model.face(0)[0].x, model.face(1)[0].x);
+        assert_eq!(model.face(0)[2].x, model.face(1)[1].x);
+
+        // -0.0 and 0.0 are the same corner, a tiny offset is not
+        let mut triangles = SQUARE;
+        triangles[1][0] = [-0.0, 0.0, -0.0];
+        triangles[1][1] = [1.0, 1.0 + 1e-6, 0.0];
+        let model = parse(ascii(&triangles).as_bytes(), ParseMode::Strict).unwrap();
+        assert_eq!(model.nverts(), 5);
+        assert_eq!(model.face(0)[0].x, model.face(1)[0].x);
+    }
+
+    #[test]
+    fn facet_normals() {
+        let mut data = binary(b"", &SQUARE);
+        // the zero normals are taken from the winding
+        let model = parse(&data, ParseMode::Strict).unwrap();
+        assert_eq!(model.norm(0, 0), Vec3f::new(0.0, 0.0, 1.0));
+        // a NaN normal is replaced the same way
+        let at = HEADER_SIZE + 4;
+        data[at..at + 4].copy_from_slice(&f32::NAN.to_le_bytes());
+        let model = parse(&data, ParseMode::Lenient).unwrap();
+        assert_eq!(model.nfaces(), 2);
+        assert_eq!(model.norm(0, 0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(parse(&data, ParseMode::Strict).is_err());
+    }
+
+    #[test]
+    fn rejects_non_finite_corners() {
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let mut triangles = SQUARE;
+            triangles[0][1][2] = bad;
+            let data = binary(b"", &triangles);
+            let model = parse(&data, ParseMode::Lenient).unwrap();
+            assert_eq!(model.nfaces(), 1);
+            assert!(parse(&data, ParseMode::Strict).is_err());
+
+            let text = ascii(&triangles);
+            let model = parse(text.as_bytes(), ParseMode::Lenient).unwrap();
+            assert_eq!(model.nfaces(), 1);
+            assert!(parse(text.as_bytes(), ParseMode::Strict).is_err());
+        }
+    }
+
+    #[test]
+    fn triangle_count_mismatch() {
+        let mut data = binary(b"", &SQUARE);
+        data.truncate(data.len() - TRIANGLE_SIZE);
+        let model = parse(&data, ParseMode::Lenient).unwrap();
+        assert_eq!(model.nfaces(), 1);
+        assert!(parse(&data, ParseMode::Strict).is_err());
+        assert!(parse(&data[..HEADER_SIZE], ParseMode::Lenient).is_err());
+    }
+}
@@ -0,0 +1,586 @@
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Range};
+
+use crate::geometry::{Vec3f, Vec3i};
+use crate::model::{FaceInfo, Model};
+
+// corner normals closer than this (as a cosine) are the same, anything else
+// is a hard edge
+const SMOOTH_COS: f32 = 0.999;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subdivision {
+    /// Loop's scheme for triangle meshes, every triangle splits into four.
+    Loop,
+    /// Catmull-Clark for quad meshes, every polygon of the source file
+    /// splits into one quad per corner.
+    CatmullClark,
+}
+
+impl Subdivision {
+    /// Parses "loop" or "catmull-clark".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "loop" => Some(Subdivision::Loop),
+            "catmull-clark" => Some(Subdivision::CatmullClark),
+            _ => None,
+        }
+    }
+
+    /// Catmull-Clark when any polygon of the file had more than three
+    /// corners, Loop otherwise.
+    pub fn for_model(model: &Model) -> Subdivision {
+        let polygons = model
+            .face_info
+            .windows(2)
+            .any(|w| w[0].source == w[1].source);
+        if polygons {
+            Subdivision::CatmullClark
+        } else {
+            Subdivision::Loop
+        }
+    }
+}
+
+/// A polygon being refined, with the first face of the model it came from.
+#[derive(Debug, Clone)]
+struct Polygon {
+    corners: Vec<Vec3i>,
+    info: FaceInfo,
+    origin: usize,
+}
+
+/// One level of refinement of an index mesh: every new value as a weighted
+/// sum of old ones, and the child polygons of every polygon.
+struct Refinement {
+    stencils: Vec<Vec<(usize, f32)>>,
+    children: Vec<Option<Vec<Vec<usize>>>>,
+}
+
+/// Refines polygons over `count` values. Polygons that are `None` do not
+/// take part and get no children. Boundary edges, edges of more than two
+/// polygons and `creases` stay sharp: their new points are midpoints, a
+/// vertex on two of them follows the crease and a vertex on more, or at a
+/// corner with no other edges, stays put.
+fn refine(
+    scheme: Subdivision,
+    count: usize,
+    polygons: &[Option<Vec<usize>>],
+    creases: &HashSet<(usize, usize)>,
+) -> Refinement {
+    let mut edge_ids: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    let mut edge_polygons: Vec<Vec<usize>> = Vec::new();
+    let mut vertex_edges: Vec<Vec<usize>> = vec![Vec::new(); count];
+    let mut vertex_polygons: Vec<Vec<usize>> = vec![Vec::new(); count];
+    for (p, poly) in polygons.iter().enumerate() {
+        let Some(poly) = poly else {
+            continue;
+        };
+        for (k, &a) in poly.iter().enumerate() {
+            let b = poly[(k + 1) % poly.len()];
+            let key = (a.min(b), a.max(b));
+            let id = *edge_ids.entry(key).or_insert_with(|| {
+                edges.push(key);
+                edge_polygons.push(Vec::new());
+                vertex_edges[a].push(edges.len() - 1);
+                vertex_edges[b].push(edges.len() - 1);
+                edges.len() - 1
+            });
+            edge_polygons[id].push(p);
+            vertex_polygons[a].push(p);
+        }
+    }
+    let sharp: Vec<bool> = edges
+        .iter()
+        .zip(&edge_polygons)
+        .map(|(e, polys)| polys.len() != 2 || creases.contains(e))
+        .collect();
+    let other = |e: usize, v: usize| {
+        let (a, b) = edges[e];
+        if a == v {
+            b
+        } else {
+            a
+        }
+    };
+    let face_point = |p: usize| -> Vec<(usize, f32)> {
+        let poly = polygons[p].as_ref().map_or(&[][..], |c| &c[..]);
+        let w = 1.0 / poly.len() as f32;
+        poly.iter().map(|&c| (c, w)).collect()
+    };
+
+    let mut stencils = Vec::with_capacity(count + edges.len() + polygons.len());
+    for v in 0..count {
+        let around = &vertex_edges[v];
+        let creased: Vec<usize> = around.iter().copied().filter(|&e| sharp[e]).collect();
+        let stencil = match creased.len() {
+            // corners of an open boundary stay put like the tips of a crease
+            _ if around.len() <= 2 && around.len() == creased.len() => vec![(v, 1.0)],
+            0 | 1 => {
+                let n = around.len() as f32;
+                match scheme {
+                    Subdivision::Loop => {
+                        let c = 3.0 / 8.0 + (2.0 * PI / n).cos() / 4.0;
+                        let beta = (5.0 / 8.0 - c * c) / n;
+                        let mut s = vec![(v, 1.0 - n * beta)];
+                        s.extend(around.iter().map(|&e| (other(e, v), beta)));
+                        s
+                    }
+                    Subdivision::CatmullClark => {
+                        // (F + 2R + (n - 3)P) / n, F the mean of the face
+                        // points and R of the edge midpoints
+                        let polys = &vertex_polygons[v];
+                        let mut s = vec![(v, (n - 3.0) / n)];
+                        for &p in polys {
+                            let w = 1.0 / (n * polys.len() as f32);
+                            s.extend(face_point(p).into_iter().map(|(c, fw)| (c, fw * w)));
+                        }
+                        for &e in around {
+                            s.push((edges[e].0, 1.0 / (n * n)));
+                            s.push((edges[e].1, 1.0 / (n * n)));
+                        }
+                        s
+                    }
+                }
+            }
+            2 => vec![
+                (v, 0.75),
+                (other(creased[0], v), 0.125),
+                (other(creased[1], v), 0.125),
+            ],
+            _ => vec![(v, 1.0)],
+        };
+        stencils.push(stencil);
+    }
+    for (e, &(a, b)) in edges.iter().enumerate() {
+        let midpoint = vec![(a, 0.5), (b, 0.5)];
+        if sharp[e] {
+            stencils.push(midpoint);
+            continue;
+        }
+        let stencil = match scheme {
+            Subdivision::Loop => {
+                let mut s = vec![(a, 3.0 / 8.0), (b, 3.0 / 8.0)];
+                for &p in &edge_polygons[e] {
+                    let poly = polygons[p].as_deref().unwrap_or_default();
+                    s.extend(
+                        poly.iter()
+                            .filter(|&&c| c != a && c != b)
+                            .map(|&c| (c, 1.0 / 8.0)),
+                    );
+                }
+                // only triangles have a single opposite corner
+                if s.len() == 4 {
+                    s
+                } else {
+                    midpoint
+                }
+            }
+            Subdivision::CatmullClark => {
+                let mut s = vec![(a, 0.25), (b, 0.25)];
+                for &p in &edge_polygons[e] {
+                    s.extend(face_point(p).into_iter().map(|(c, w)| (c, w * 0.25)));
+                }
+                s
+            }
+        };
+        stencils.push(stencil);
+    }
+
+    let edge_point = |a: usize, b: usize| count + edge_ids[&(a.min(b), a.max(b))];
+    let mut children = Vec::with_capacity(polygons.len());
+    for (p, poly) in polygons.iter().enumerate() {
+        let Some(poly) = poly else {
+            children.push(None);
+            continue;
+        };
+        let n = poly.len();
+        let kids = match scheme {
+            Subdivision::Loop => {
+                let [a, b, c] = [poly[0], poly[1], poly[2]];
+                let (ab, bc, ca) = (edge_point(a, b), edge_point(b, c), edge_point(c, a));
+                vec![
+                    vec![a, ab, ca],
+                    vec![b, bc, ab],
+                    vec![c, ca, bc],
+                    vec![ab, bc, ca],
+                ]
+            }
+            Subdivision::CatmullClark => {
+                let center = stencils.len();
+                stencils.push(face_point(p));
+                (0..n)
+                    .map(|k| {
+                        let (prev, v, next) = (poly[(k + n - 1) % n], poly[k], poly[(k + 1) % n]);
+                        vec![v, edge_point(v, next), center, edge_point(prev, v)]
+                    })
+                    .collect()
+            }
+        };
+        children.push(Some(kids));
+    }
+    Refinement { stencils, children }
+}
+
+fn apply<T>(stencils: &[Vec<(usize, f32)>], values: &[T]) -> Vec<T>
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    stencils
+        .iter()
+        .map(|s| {
+            let (&(first, w), rest) = s.split_first().expect("stencils are never empty");
+            rest.iter()
+                .fold(values[first] * w, |acc, &(i, w)| acc + values[i] * w)
+        })
+        .collect()
+}
+
+impl Model {
+    /// Subdivides the surface `levels` times. Texture coordinates and
+    /// normals are refined on their own index topology, so UV seams and
+    /// hard normals stay where they are. Open boundaries, hard edges (where
+    /// the corner normals of neighbouring faces differ) and edges between
+    /// OBJ smoothing groups are kept as sharp creases. Faces keep their
+    /// material and submesh; every refined polygon becomes a source polygon
+    /// of its own, carrying the per-polygon attributes of its parent.
+    pub fn subdivide(&mut self, scheme: Subdivision, levels: usize) {
+        if levels == 0 || self.faces.is_empty() {
+            return;
+        }
+        let mut polygons = match scheme {
+            Subdivision::Loop => (0..self.faces.len())
+                .map(|f| Polygon {
+                    corners: self.faces[f].clone(),
+                    info: self.face_info[f],
+                    origin: f,
+                })
+                .collect(),
+            Subdivision::CatmullClark => self.polygons(),
+        };
+        for _ in 0..levels {
+            polygons = self.refine_level(scheme, &polygons);
+        }
+
+        // every refined polygon is a source polygon of its own, so the next
+        // call finds them again instead of merging the children of a parent
+        let mut faces = Vec::new();
+        let mut face_info = Vec::new();
+        let mut origins = Vec::new();
+        let mut sources = Vec::with_capacity(polygons.len());
+        for (p, poly) in polygons.iter().enumerate() {
+            let c = &poly.corners;
+            for k in 1..c.len() - 1 {
+                faces.push(vec![c[0], c[k], c[k + 1]]);
+                face_info.push(FaceInfo {
+                    source: p,
+                    ..poly.info
+                });
+                origins.push(poly.origin);
+            }
+            sources.push(poly.info.source);
+        }
+        for values in self.polygon_attributes.values_mut() {
+            *values = sources
+                .iter()
+                .map(|&s| values.get(s).copied().unwrap_or(0.0))
+                .collect();
+        }
+        for lists in self.polygon_lists.values_mut() {
+            lists.pick_rows(&sources);
+        }
+        let first: Vec<usize> = (0..=self.faces.len())
+            .map(|f| origins.partition_point(|&o| o < f))
+            .collect();
+        self.remap_submeshes(&first);
+        self.faces = faces;
+        self.face_info = face_info;
+        if !self.tangents.is_empty() {
+            self.compute_tangents();
+        }
+        self.remove_unused();
+    }
+
+    fn refine_level(&mut self, scheme: Subdivision, polygons: &[Polygon]) -> Vec<Polygon> {
+        let topology = |slot: usize| -> Vec<Option<Vec<usize>>> {
+            polygons
+                .iter()
+                .map(|p| {
+                    p.corners
+                        .iter()
+                        .map(|c| (c[slot] >= 0).then_some(c[slot] as usize))
+                        .collect()
+                })
+                .collect()
+        };
+        let positions = refine(
+            scheme,
+            self.verts.len(),
+            &topology(0),
+            &self.creases(polygons),
+        );
+        let uvs = refine(scheme, self.uv.len(), &topology(1), &HashSet::new());
+        let normals = refine(scheme, self.norms.len(), &topology(2), &HashSet::new());
+
+        let count = self.verts.len();
+        if self.colors.len() == count {
+            self.colors = apply(&positions.stencils, &self.colors);
+        }
+        for values in self.vertex_attributes.values_mut() {
+            if values.len() == count {
+                *values = apply(&positions.stencils, values);
+            }
+        }
+        // lists can't be blended, and would no longer line up with the positions
+        self.vertex_lists.clear();
+        self.verts = apply(&positions.stencils, &self.verts);
+        self.uv = apply(&uvs.stencils, &self.uv);
+        self.norms = apply(&normals.stencils, &self.norms)
+            .into_iter()
+            .map(Vec3f::normalized)
+            .collect();
+
+        let mut refined = Vec::new();
+        for (p, poly) in polygons.iter().enumerate() {
+            let kids = positions.children[p].as_deref().unwrap_or_default();
+            for (j, kid) in kids.iter().enumerate() {
+                let index = |r: &Refinement, k: usize| {
+                    r.children[p].as_ref().map_or(-1, |c| c[j][k] as i32)
+                };
+                let corners = (0..kid.len())
+                    .map(|k| Vec3i::new(kid[k] as i32, index(&uvs, k), index(&normals, k)))
+                    .collect();
+                refined.push(Polygon {
+                    corners,
+                    info: poly.info,
+                    origin: poly.origin,
+                });
+            }
+        }
+        refined
+    }
+
+    /// Position edges that must stay sharp: between OBJ smoothing groups,
+    /// next to `s off` faces, and where the corner normals on either side
+    /// differ.
+    fn creases(&self, polygons: &[Polygon]) -> HashSet<(usize, usize)> {
+        let mut sides: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (p, poly) in polygons.iter().enumerate() {
+            for (k, a) in poly.corners.iter().enumerate() {
+                let b = poly.corners[(k + 1) % poly.corners.len()];
+                let (a, b) = (a.x as usize, b.x as usize);
+                sides.entry((a.min(b), a.max(b))).or_default().push(p);
+            }
+        }
+        let normal_at = |p: usize, v: usize| {
+            polygons[p]
+                .corners
+                .iter()
+                .find(|c| c.x as usize == v && c.z >= 0)
+                .map(|c| self.norms[c.z as usize])
+        };
+        sides
+            .into_iter()
+            .filter(|&((a, b), ref polys)| {
+                let [p, q] = polys[..] else {
+                    return false;
+                };
+                let (ip, iq) = (polygons[p].info, polygons[q].info);
+                let groups = self.has_smoothing_groups
+                    && (ip.smoothing != iq.smoothing || ip.smoothing == 0);
+                let hard = [a, b]
+                    .into_iter()
+                    .any(|v| match (normal_at(p, v), normal_at(q, v)) {
+                        (Some(n), Some(m)) => n * m < SMOOTH_COS * n.norm() * m.norm(),
+                        _ => false,
+                    });
+                groups || hard
+            })
+            .map(|(edge, _)| edge)
+            .collect()
+    }
+
+    /// The polygons the faces were triangulated from, found by chaining
+    /// the outer edges of each run of faces with the same source polygon.
+    /// Runs that do not form a single loop stay triangles.
+    fn polygons(&self) -> Vec<Polygon> {
+        let mut polygons = Vec::new();
+        let mut start = 0;
+        while start < self.faces.len() {
+            let source = self.face_info[start].source;
+            let end = start
+                + self.face_info[start..]
+                    .iter()
+                    .take_while(|info| info.source == source)
+                    .count();
+            match self.outline(start..end) {
+                Some(corners) => polygons.push(Polygon {
+                    corners,
+                    info: self.face_info[start],
+                    origin: start,
+                }),
+                None => polygons.extend((start..end).map(|f| Polygon {
+                    corners: self.faces[f].clone(),
+                    info: self.face_info[f],
+                    origin: f,
+                })),
+            }
+            start = end;
+        }
+        polygons
+    }
+
+    fn outline(&self, faces: Range<usize>) -> Option<Vec<Vec3i>> {
+        if faces.len() == 1 {
+            return Some(self.faces[faces.start].clone());
+        }
+        let mut directed = HashSet::new();
+        for face in &self.faces[faces.clone()] {
+            for k in 0..3 {
+                directed.insert((face[k].x, face[(k + 1) % 3].x));
+            }
+        }
+        // corner and following position of every edge without a twin
+        let mut next: HashMap<i32, (Vec3i, i32)> = HashMap::new();
+        for face in &self.faces[faces.clone()] {
+            for k in 0..3 {
+                let (a, b) = (face[k], face[(k + 1) % 3].x);
+                if !directed.contains(&(b, a.x)) && next.insert(a.x, (a, b)).is_some() {
+                    return None;
+                }
+            }
+        }
+        // start where the first face does, to keep the output deterministic
+        let first = self.faces[faces.start]
+            .iter()
+            .map(|c| c.x)
+            .find(|v| next.contains_key(v))?;
+        let mut corners = Vec::with_capacity(next.len());
+        let mut at = first;
+        loop {
+            let &(corner, to) = next.get(&at)?;
+            corners.push(corner);
+            at = to;
+            if at == first || corners.len() > next.len() {
+                break;
+            }
+        }
+        (at == first && corners.len() == next.len()).then_some(corners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn octahedron() -> Model {
+        let verts = vec![
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(-1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, -1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 0.0, -1.0),
+        ];
+        let mut faces = Vec::new();
+        for (a, b) in [(0, 2), (2, 1), (1, 3), (3, 0)] {
+            faces.push(vec![a, b, 4]);
+            faces.push(vec![b, a, 5]);
+        }
+        let faces = faces
+            .into_iter()
+            .map(|f| f.into_iter().map(|v| Vec3i::new(v, -1, -1)).collect())
+            .collect();
+        Model::from_triangles(verts, Vec::new(), Vec::new(), faces)
+    }
+
+    /// Unit cube of six quads split into two triangles each, with a normal
+    /// per face when `hard`.
+    fn cube(hard: bool) -> Model {
+        let verts = (0..8)
+            .map(|i| Vec3f::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32))
+            .collect();
+        let quads = [
+            ([0, 2, 3, 1], Vec3f::new(0.0, 0.0, -1.0)),
+            ([4, 5, 7, 6], Vec3f::new(0.0, 0.0, 1.0)),
+            ([0, 1, 5, 4], Vec3f::new(0.0, -1.0, 0.0)),
+            ([2, 6, 7, 3], Vec3f::new(0.0, 1.0, 0.0)),
+            ([0, 4, 6, 2], Vec3f::new(-1.0, 0.0, 0.0)),
+            ([1, 3, 7, 5], Vec3f::new(1.0, 0.0, 0.0)),
+        ];
+        let mut norms = Vec::new();
+        let mut faces = Vec::new();
+        for (q, (quad, normal)) in quads.iter().enumerate() {
+            let n = if hard { q as i32 } else { -1 };
+            let c = |k: usize| Vec3i::new(quad[k], -1, n);
+            faces.push(vec![c(0), c(1), c(2)]);
+            faces.push(vec![c(0), c(2), c(3)]);
+            norms.push(*normal);
+        }
+        if !hard {
+            norms.clear();
+        }
+        let mut model = Model::from_triangles(verts, norms, Vec::new(), faces);
+        // both halves of a quad come from the same polygon
+        for (f, info) in model.face_info.iter_mut().enumerate() {
+            info.source = f / 2;
+        }
+        model
+    }
+
+    #[test]
+    fn loop_splits_triangles_in_four() {
+        let mut model = octahedron();
+        assert_eq!(Subdivision::for_model(&model), Subdivision::Loop);
+        model.subdivide(Subdivision::Loop, 1);
+        // one new vertex per edge
+        assert_eq!(model.nverts(), 6 + 12);
+        assert_eq!(model.nfaces(), 8 * 4);
+        // the smooth surface shrinks inside the octahedron
+        assert!((0..model.nverts()).all(|i| model.vert(i).norm() < 1.0));
+    }
+
+    #[test]
+    fn catmull_clark_splits_quads() {
+        let mut model = cube(false);
+        assert_eq!(Subdivision::for_model(&model), Subdivision::CatmullClark);
+        model.subdivide(Subdivision::CatmullClark, 1);
+        // one new vertex per edge and per quad, four quads per quad
+        assert_eq!(model.nverts(), 8 + 12 + 6);
+        assert_eq!(model.nfaces(), 6 * 4 * 2);
+        let corner = model.vert(0);
+        assert!(corner.x > 0.0 && corner.y > 0.0 && corner.z > 0.0);
+        assert_eq!(model.submeshes()[0].faces, 0..48);
+    }
+
+    #[test]
+    fn hard_edges_stay_creased() {
+        let mut model = cube(true);
+        model.subdivide(Subdivision::CatmullClark, 2);
+        // every point stays on the surface of the cube
+        for i in 0..model.nverts() {
+            let v = model.vert(i);
+            let on_side = [v.x, v.y, v.z]
+                .iter()
+                .any(|&c| c.abs() < 1e-5 || (c - 1.0).abs() < 1e-5);
+            assert!(on_side, "{:?} left the cube", v);
+        }
+        assert_eq!(model.vert(0), Vec3f::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn repeated_levels_match_one_call() {
+        let mut twice = cube(false);
+        twice.subdivide(Subdivision::CatmullClark, 1);
+        assert_eq!(Subdivision::for_model(&twice), Subdivision::CatmullClark);
+        twice.subdivide(Subdivision::CatmullClark, 1);
+        let mut once = cube(false);
+        once.subdivide(Subdivision::CatmullClark, 2);
+        assert_eq!(twice.nverts(), once.nverts());
+        assert_eq!(twice.nfaces(), once.nfaces());
+        assert_eq!(twice.source_face(0), 0);
+        assert_eq!(twice.source_face(2), 1);
+    }
+}
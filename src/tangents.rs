@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use crate::geometry::{Vec3f, Vec4f};
+use crate::model::Model;
+
+/// Any unit vector perpendicular to `n`.
+fn perpendicular(n: Vec3f) -> Vec3f {
+    let axis = if n.x.abs() < 0.9 {
+        Vec3f::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3f::new(0.0, 1.0, 0.0)
+    };
+    (axis - n * (axis * n)).normalized()
+}
+
+impl Model {
+    /// Computes a tangent frame for every face corner following the
+    /// MikkTSpace conventions: per face tangents from the UV gradients are
+    /// projected onto the tangent plane of each corner normal, weighted by
+    /// the corner angle and summed over corners sharing position, UV, normal
+    /// and UV winding. The handedness goes in w, the bitangent is
+    /// `w * cross(normal, tangent)`.
+    pub fn compute_tangents(&mut self) {
+        let nfaces = self.faces.len();
+        let mut corner_frames =
+            vec![[(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 0.0)); 3]; nfaces];
+        let mut orientation = vec![true; nfaces];
+
+        for (iface, face) in self.faces.iter().enumerate() {
+            let p: Vec<Vec3f> = face.iter().map(|c| self.verts[c.x as usize]).collect();
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (mut sdir, mut tdir) = (e1, e2);
+            if self.face_info[iface].attributes.uv {
+                let t: Vec<_> = face.iter().map(|c| self.uv[c.y as usize]).collect();
+                let (s1, t1) = (t[1].x - t[0].x, t[1].y - t[0].y);
+                let (s2, t2) = (t[2].x - t[0].x, t[2].y - t[0].y);
+                let det = s1 * t2 - s2 * t1;
+                if det != 0.0 {
+                    sdir = (e1 * t2 - e2 * t1) * (1.0 / det);
+                    tdir = (e2 * s1 - e1 * s2) * (1.0 / det);
+                    orientation[iface] = det > 0.0;
+                }
+            }
+            for k in 0..3 {
+                let n = self.norm(iface, k).normalized();
+                let angle = (p[(k + 1) % 3] - p[k]).angle(p[(k + 2) % 3] - p[k]);
+                let t = (sdir - n * (n * sdir)).normalized() * angle;
+                let b = (tdir - n * (n * tdir)).normalized() * angle;
+                corner_frames[iface][k] = (t, b);
+            }
+        }
+
+        // corners that are the same vertex for a renderer share one frame
+        let mut shared: HashMap<(i32, i32, i32, bool), (Vec3f, Vec3f)> = HashMap::new();
+        for (iface, face) in self.faces.iter().enumerate() {
+            for (k, c) in face.iter().enumerate() {
+                let entry = shared
+                    .entry((c.x, c.y, c.z, orientation[iface]))
+                    .or_insert((Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 0.0)));
+                entry.0 = entry.0 + corner_frames[iface][k].0;
+                entry.1 = entry.1 + corner_frames[iface][k].1;
+            }
+        }
+
+        let mut tangents = Vec::with_capacity(nfaces);
+        for iface in 0..nfaces {
+            let mut frame = [Vec4f::new(0.0, 0.0, 0.0, 1.0); 3];
+            for (k, out) in frame.iter_mut().enumerate() {
+                let c = self.faces[iface][k];
+                let (t, b) = shared[&(c.x, c.y, c.z, orientation[iface])];
+                let n = self.norm(iface, k).normalized();
+                let mut t = (t - n * (n * t)).normalized();
+                if t.norm() == 0.0 {
+                    t = perpendicular(n);
+                }
+                let w = if (n ^ t) * b < 0.0 { -1.0 } else { 1.0 };
+                *out = Vec4f::from_vec3(t, w);
+            }
+            tangents.push(frame);
+        }
+        self.tangents = tangents;
+    }
+
+    pub fn has_tangents(&self) -> bool {
+        !self.tangents.is_empty()
+    }
+
+    /// Tangent of a corner with the handedness in w, `None` until
+    /// `compute_tangents` was called or when the file had none.
+    pub fn tangent(&self, iface: usize, nvert: usize) -> Option<Vec4f> {
+        self.tangents.get(iface).map(|frame| frame[nvert])
+    }
+
+    pub fn bitangent(&self, iface: usize, nvert: usize) -> Option<Vec3f> {
+        let t = self.tangent(iface, nvert)?;
+        Some((self.norm(iface, nvert).normalized() ^ t.xyz()) * t.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Vec2f, Vec3i};
+
+    /// Unit square in the xy plane facing +z, with `uv(x, y)` at its corners.
+    fn square(uv: impl Fn(f32, f32) -> Vec2f) -> Model {
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let verts = corners
+            .iter()
+            .map(|&(x, y)| Vec3f::new(x, y, 0.0))
+            .collect();
+        let uvs = corners.iter().map(|&(x, y)| uv(x, y)).collect();
+        let norms = vec![Vec3f::new(0.0, 0.0, 1.0)];
+        let c = |v: i32| Vec3i::new(v, v, 0);
+        let faces = vec![vec![c(0), c(1), c(2)], vec![c(0), c(2), c(3)]];
+        Model::from_triangles(verts, norms, uvs, faces)
+    }
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    #[test]
+    fn follows_the_uv_axes() {
+        let mut model = square(Vec2f::new);
+        assert!(!model.has_tangents());
+        assert!(model.tangent(0, 0).is_none());
+        assert!(model.bitangent(0, 0).is_none());
+        model.compute_tangents();
+        for f in 0..2 {
+            for k in 0..3 {
+                let t = model.tangent(f, k).unwrap();
+                assert!(close(t.xyz(), Vec3f::new(1.0, 0.0, 0.0)));
+                assert_eq!(t.w, 1.0);
+                let b = model.bitangent(f, k).unwrap();
+                assert!(close(b, Vec3f::new(0.0, 1.0, 0.0)));
+            }
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_handedness() {
+        let mut model = square(|x, y| Vec2f::new(1.0 - x, y));
+        model.compute_tangents();
+        for f in 0..2 {
+            for k in 0..3 {
+                let t = model.tangent(f, k).unwrap();
+                assert!(close(t.xyz(), Vec3f::new(-1.0, 0.0, 0.0)));
+                assert_eq!(t.w, -1.0);
+                // v still runs up the square
+                let b = model.bitangent(f, k).unwrap();
+                assert!(close(b, Vec3f::new(0.0, 1.0, 0.0)));
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_uvs_stay_finite() {
+        for uv in [|_, _| Vec2f::new(0.5, 0.5), |x: f32, _| Vec2f::new(x, 0.0)] {
+            let mut model = square(uv);
+            model.compute_tangents();
+            for f in 0..2 {
+                for k in 0..3 {
+                    let t = model.tangent(f, k).unwrap();
+                    assert!((t.xyz().norm() - 1.0).abs() < 1e-5);
+                    assert!(t.xyz().z.abs() < 1e-5);
+                    assert!(t.w.abs() == 1.0);
+                    let b = model.bitangent(f, k).unwrap();
+                    assert!(b.x.is_finite() && b.y.is_finite() && b.z.is_finite());
+                }
+            }
+        }
+    }
+}
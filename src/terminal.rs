@@ -0,0 +1,295 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    process::Command,
+};
+
+use crate::tga::{AlphaMode, TGAColor, TGAImage};
+
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+
+/// Pixels per character cell assumed when sizing sixel output, the common
+/// 8x16 of VGA-style terminal fonts. Terminals with other fonts want
+/// `--cell-size`.
+pub const CELL_SIZE: (usize, usize) = (8, 16);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewMode {
+    Ansi,
+    Sixel,
+}
+
+impl PreviewMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ansi" => Some(PreviewMode::Ansi),
+            "sixel" => Some(PreviewMode::Sixel),
+            _ => None,
+        }
+    }
+}
+
+/// Terminal size in character cells. The controlling terminal is asked
+/// through `stty size` first; if there is none, `COLUMNS`/`LINES` are used,
+/// falling back to 80x24 when they are not exported either.
+pub fn terminal_size() -> (usize, usize) {
+    if let Some(size) = tty_size() {
+        return size;
+    }
+    let read = |name: &str, default: usize| {
+        env::var(name)
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|&v| v > 0)
+            .unwrap_or(default)
+    };
+    (read("COLUMNS", 80), read("LINES", 24))
+}
+
+/// Columns and rows of `/dev/tty`, which works even with stdout redirected.
+fn tty_size() -> Option<(usize, usize)> {
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty").arg("size").stdin(tty).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // prints "rows cols"
+    let text = String::from_utf8(output.stdout).ok()?;
+    let mut parts = text.split_whitespace().map(|p| p.parse::<usize>().ok());
+    let rows = parts.next()??;
+    let cols = parts.next()??;
+    (rows > 0 && cols > 0).then_some((cols, rows))
+}
+
+/// Largest size with the image aspect ratio that fits into `max_w` x `max_h`.
+fn fit(image: &TGAImage, max_w: usize, max_h: usize) -> (usize, usize) {
+    let w = image.width().max(1) as f32;
+    let h = image.height().max(1) as f32;
+    let scale = (max_w as f32 / w).min(max_h as f32 / h);
+    (((w * scale) as usize).max(1), ((h * scale) as usize).max(1))
+}
+
+/// Box-filters the image down to `w` x `h` RGB pixels, top row first,
+/// compositing any alpha over black. With `vflip` the image rows are stored
+/// bottom-up, as in `write_tga_file`.
+fn downsample(image: &TGAImage, w: usize, h: usize, vflip: bool) -> Vec<[u8; 3]> {
+    let small = image.resized(w as i32, h as i32);
+    let mut out = Vec::with_capacity(w * h);
+    for ty in 0..h {
+        let y = if vflip { h - 1 - ty } else { ty };
+        for x in 0..w {
+            let c = small
+                .get(x as i32, y as i32)
+                .unwrap_or_else(|| TGAColor::from_bpp(small.bpp));
+            out.push(match small.bpp {
+                1 => [c[0], c[0], c[0]],
+                4 => {
+                    let c = match small.alpha_mode() {
+                        AlphaMode::Straight => c.premultiplied(),
+                        AlphaMode::Premultiplied => c,
+                    };
+                    [c[2], c[1], c[0]]
+                }
+                _ => [c[2], c[1], c[0]],
+            });
+        }
+    }
+    out
+}
+
+/// Draws the image with 24-bit ANSI colours, two pixels per cell using the
+/// upper half block, scaled to fit into `cols` x `rows` cells.
+pub fn write_ansi(
+    image: &TGAImage,
+    cols: usize,
+    rows: usize,
+    vflip: bool,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let (w, h) = fit(image, cols, rows * 2);
+    let pixels = downsample(image, w, h, vflip);
+
+    for y in (0..h).step_by(2) {
+        let mut line = String::new();
+        for x in 0..w {
+            let top = pixels[y * w + x];
+            line.push_str(&format!("\x1b[38;2;{};{};{}m", top[0], top[1], top[2]));
+            if y + 1 < h {
+                let bottom = pixels[(y + 1) * w + x];
+                line.push_str(&format!(
+                    "\x1b[48;2;{};{};{}m",
+                    bottom[0], bottom[1], bottom[2]
+                ));
+            } else {
+                line.push_str("\x1b[49m");
+            }
+            line.push(UPPER_HALF_BLOCK);
+        }
+        line.push_str("\x1b[0m");
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+/// Emits the image as sixel graphics, scaled to fit into `max_w` x `max_h`
+/// pixels and quantized to a 6x6x6 colour cube.
+pub fn write_sixel(
+    image: &TGAImage,
+    max_w: usize,
+    max_h: usize,
+    vflip: bool,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let (w, h) = fit(image, max_w, max_h);
+    let pixels = downsample(image, w, h, vflip);
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let indices: Vec<usize> = pixels
+        .iter()
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    write!(out, "\x1bPq\"1;1;{};{}", w, h)?;
+    for i in 0..216 {
+        let (r, g, b) = (i / 36, (i / 6) % 6, i % 6);
+        write!(out, "#{};2;{};{};{}", i, r * 20, g * 20, b * 20)?;
+    }
+
+    let mut band_colors = vec![0u8; w];
+    for band in (0..h).step_by(6) {
+        let band_end = (band + 6).min(h);
+        let mut used = [false; 216];
+        for y in band..band_end {
+            for x in 0..w {
+                used[indices[y * w + x]] = true;
+            }
+        }
+
+        let mut first = true;
+        for (color, _) in used.iter().enumerate().filter(|(_, &u)| u) {
+            for (x, bits) in band_colors.iter_mut().enumerate() {
+                *bits = 0;
+                for y in band..band_end {
+                    if indices[y * w + x] == color {
+                        *bits |= 1 << (y - band);
+                    }
+                }
+            }
+            if !first {
+                write!(out, "$")?;
+            }
+            first = false;
+            write!(out, "#{}", color)?;
+            write_sixel_run(&band_colors, out)?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")?;
+    out.flush()
+}
+
+fn write_sixel_run(bits: &[u8], out: &mut dyn Write) -> io::Result<()> {
+    let mut x = 0;
+    while x < bits.len() {
+        let mut run = 1;
+        while x + run < bits.len() && bits[x + run] == bits[x] {
+            run += 1;
+        }
+        let c = (63 + bits[x]) as char;
+        if run > 3 {
+            write!(out, "!{}{}", run, c)?;
+        } else {
+            for _ in 0..run {
+                write!(out, "{}", c)?;
+            }
+        }
+        x += run;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tga::Format;
+
+    fn image(w: i32, h: i32, format: Format, color: impl Fn(i32, i32) -> TGAColor) -> TGAImage {
+        let mut image = TGAImage::new(w, h, format);
+        for y in 0..h {
+            for x in 0..w {
+                image.set(x as usize, y as usize, color(x, y)).unwrap();
+            }
+        }
+        image
+    }
+
+    fn ansi(image: &TGAImage, cols: usize, rows: usize, vflip: bool) -> String {
+        let mut out = Vec::new();
+        write_ansi(image, cols, rows, vflip, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn half_blocks_pair_rows() {
+        let red_over_blue = image(2, 2, Format::RGB, |_, y| {
+            if y == 0 {
+                TGAColor::from_rgb(255, 0, 0)
+            } else {
+                TGAColor::from_rgb(0, 0, 255)
+            }
+        });
+        let cell = "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}";
+        assert_eq!(
+            ansi(&red_over_blue, 2, 1, false),
+            format!("{}{}\x1b[0m\n", cell, cell)
+        );
+        // stored bottom-up, blue is the top row
+        let cell = "\x1b[38;2;0;0;255m\x1b[48;2;255;0;0m\u{2580}";
+        assert_eq!(
+            ansi(&red_over_blue, 2, 1, true),
+            format!("{}{}\x1b[0m\n", cell, cell)
+        );
+        // an odd last row keeps the terminal background below it
+        let gray = image(1, 1, Format::Grayscale, |_, _| TGAColor::from_bpp(1));
+        assert_eq!(
+            ansi(&gray, 1, 1, false),
+            "\x1b[38;2;0;0;0m\x1b[49m\u{2580}\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn downscales_to_fit() {
+        let halves = image(8, 4, Format::RGB, |x, _| {
+            let v = if x < 4 { 255 } else { 0 };
+            TGAColor::from_rgb(v, v, v)
+        });
+        assert_eq!(fit(&halves, 80, 48), (80, 40));
+        assert_eq!(fit(&halves, 4, 100), (4, 2));
+        assert_eq!(
+            downsample(&halves, 2, 1, false),
+            [[255, 255, 255], [0, 0, 0]]
+        );
+        // 4 cells wide, so 4x2 pixels in a single row of cells
+        let text = ansi(&halves, 4, 10, false);
+        assert_eq!(text.lines().count(), 1);
+        assert_eq!(text.matches(UPPER_HALF_BLOCK).count(), 4);
+
+        // straight alpha is composited over black
+        let faded = image(1, 1, Format::RGBA, |_, _| {
+            TGAColor::from_rgba(255, 255, 255, 128)
+        });
+        assert_eq!(downsample(&faded, 1, 1, false), [[128, 128, 128]]);
+    }
+
+    #[test]
+    fn sixel_raster_fits_the_pixel_area() {
+        let wide = image(64, 16, Format::RGB, |_, _| TGAColor::from_rgb(255, 0, 0));
+        let mut out = Vec::new();
+        write_sixel(&wide, 32, 32, false, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("\x1bPq\"1;1;32;8#0;2;0;0;0"));
+        assert!(text.ends_with("-\x1b\\"));
+        // a full band of six rows and one of two, all pure red (colour 5 * 36)
+        assert!(text.contains("#180!32~-#180!32B-"));
+    }
+}
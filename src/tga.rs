@@ -5,7 +5,8 @@ use std::{
 };
 
 #[derive(Default)]
-#[repr(packed)]
+#[allow(dead_code)]
+#[repr(C, packed)]
 pub struct TGAHeader {
     id_length: u8,
     color_map_type: u8,
@@ -22,6 +23,45 @@ pub struct TGAHeader {
 }
 
 impl TGAHeader {
+    /// Header of an image, which fails if a side does not fit in the 16-bit
+    /// size fields.
+    pub fn for_image(width: i32, height: i32, bpp: u8, vflip: bool, rle: bool) -> io::Result<Self> {
+        let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}x{} does not fit in a TGA file, at most {} pixels per side",
+                    width,
+                    height,
+                    u16::MAX
+                ),
+            ));
+        };
+        Ok(TGAHeader {
+            bits_per_pixel: bpp << 3,
+            width: w,
+            height: h,
+            data_type_code: match (bpp, rle) {
+                (1, true) => 11,
+                (1, false) => 3,
+                (_, true) => 10,
+                (_, false) => 2,
+            },
+            image_descriptor: if vflip { 0x00 } else { 0x20 } | if bpp == 4 { 8 } else { 0 },
+            ..Default::default()
+        })
+    }
+
+    pub fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        let header_bytes = unsafe {
+            std::slice::from_raw_parts(
+                self as *const _ as *const u8,
+                std::mem::size_of::<TGAHeader>(),
+            )
+        };
+        out.write_all(header_bytes)
+    }
+
     pub fn new() -> Self {
         TGAHeader {
             id_length: 0,
@@ -134,6 +174,36 @@ impl TGAColor {
             bytespp: bpp,
         }
     }
+
+    pub fn bytespp(&self) -> u8 {
+        self.bytespp
+    }
+
+    pub fn alpha(&self) -> u8 {
+        self.bgra[3]
+    }
+
+    /// Scales the colour channels by alpha.
+    pub fn premultiplied(&self) -> Self {
+        let a = self.bgra[3] as u32;
+        let mut c = *self;
+        for v in &mut c.bgra[..3] {
+            *v = ((*v as u32 * a + 127) / 255) as u8;
+        }
+        c
+    }
+
+    /// Inverse of `premultiplied`. Fully transparent colours become black.
+    pub fn unpremultiplied(&self) -> Self {
+        let a = self.bgra[3] as u32;
+        let mut c = *self;
+        for v in &mut c.bgra[..3] {
+            *v = (*v as u32 * 255 + a / 2)
+                .checked_div(a)
+                .map_or(0, |v| v.min(255) as u8);
+        }
+        c
+    }
 }
 
 impl Index<usize> for TGAColor {
@@ -144,6 +214,20 @@ impl Index<usize> for TGAColor {
     }
 }
 
+/// How colour channels relate to alpha. Stored in the attributes type
+/// byte of the TGA 2.0 extension area.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlphaMode {
+    #[default]
+    Straight,
+    Premultiplied,
+}
+
+const EXTENSION_AREA_SIZE: usize = 495;
+const ATTRIBUTES_TYPE_OFFSET: usize = 494;
+const FOOTER_SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Format {
     Grayscale = 1,
     RGB = 3,
@@ -161,12 +245,13 @@ impl Format {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TGAImage {
     w: i32,
     h: i32,
     pub bpp: u8,
     data: Vec<u8>,
+    alpha: AlphaMode,
 }
 
 impl TGAImage {
@@ -180,8 +265,9 @@ impl TGAImage {
         TGAImage {
             w: width,
             h: height,
-            bpp: bpp,
+            bpp,
             data: vec![0; data_len],
+            alpha: AlphaMode::Straight,
         }
     }
 
@@ -198,6 +284,10 @@ impl TGAImage {
         let width = header.width;
         let height = header.height;
         let bytespp = header.bits_per_pixel >> 3;
+        if !(1..=4).contains(&bytespp) {
+            eprintln!("unsupported bits per pixel: {}", header.bits_per_pixel);
+            return None;
+        }
         let nbytes = (width as usize) * (height as usize) * (bytespp as usize);
 
         if header.id_length > 0 {
@@ -205,26 +295,56 @@ impl TGAImage {
         }
 
         let data = match header.data_type_code {
-            2 => {
+            2 | 3 => {
                 let mut buf = vec![0u8; nbytes];
                 file.read_exact(&mut buf).ok()?;
                 buf
             }
-            10 => TGAImage::load_rle_data(&mut file, width as usize, height as usize, bytespp)?,
+            10 | 11 => {
+                TGAImage::load_rle_data(&mut file, width as usize, height as usize, bytespp)?
+            }
             other => {
                 eprintln!("unsupported data type code: {}", other);
                 return None;
             }
         };
 
+        let alpha = TGAImage::read_alpha_mode(&mut file);
+
         Some(TGAImage {
             h: height as i32,
             w: width as i32,
             bpp: bytespp,
-            data: data,
+            data,
+            alpha,
         })
     }
 
+    /// Looks for a TGA 2.0 footer and reads the attributes type from the
+    /// extension area. Files without one are assumed to be straight alpha.
+    fn read_alpha_mode(file: &mut File) -> AlphaMode {
+        let mut read = || -> io::Result<AlphaMode> {
+            let mut footer = [0u8; 26];
+            file.seek(SeekFrom::End(-26))?;
+            file.read_exact(&mut footer)?;
+            let extension_offset = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+            if &footer[8..] != FOOTER_SIGNATURE || extension_offset == 0 {
+                return Ok(AlphaMode::Straight);
+            }
+            let mut attributes_type = [0u8; 1];
+            file.seek(SeekFrom::Start(
+                extension_offset as u64 + ATTRIBUTES_TYPE_OFFSET as u64,
+            ))?;
+            file.read_exact(&mut attributes_type)?;
+            Ok(if attributes_type[0] == 4 {
+                AlphaMode::Premultiplied
+            } else {
+                AlphaMode::Straight
+            })
+        };
+        read().unwrap_or_default()
+    }
+
     fn load_rle_data(file: &mut File, width: usize, height: usize, bpp: u8) -> Option<Vec<u8>> {
         let pixel_size = bpp as usize;
         let mut data = Vec::with_capacity(width * height * pixel_size);
@@ -257,14 +377,176 @@ impl TGAImage {
         Some(data)
     }
 
-    fn flip_vertically(&mut self) {}
+    pub fn flip_vertically(&mut self) {
+        let row_len = self.w as usize * self.bpp as usize;
+        let h = self.h as usize;
+        for y in 0..h / 2 {
+            let (top, bottom) = self.data.split_at_mut((h - 1 - y) * row_len);
+            top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+        }
+    }
 
-    fn flip_horizontally(&mut self) {}
+    pub fn flip_horizontally(&mut self) {
+        let bpp = self.bpp as usize;
+        let w = self.w as usize;
+        for row in self.data.chunks_exact_mut(w * bpp) {
+            for x in 0..w / 2 {
+                for b in 0..bpp {
+                    row.swap(x * bpp + b, (w - 1 - x) * bpp + b);
+                }
+            }
+        }
+    }
 
     pub fn width(&self) -> i32 {
         self.w
     }
 
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha
+    }
+
+    /// Converts straight alpha data to premultiplied. No-op without an alpha
+    /// channel or when already premultiplied.
+    pub fn premultiply(&mut self) {
+        if self.bpp == 4 && self.alpha == AlphaMode::Straight {
+            self.map_pixels(|c| c.premultiplied());
+        }
+        self.alpha = AlphaMode::Premultiplied;
+    }
+
+    pub fn unpremultiply(&mut self) {
+        if self.bpp == 4 && self.alpha == AlphaMode::Premultiplied {
+            self.map_pixels(|c| c.unpremultiplied());
+        }
+        self.alpha = AlphaMode::Straight;
+    }
+
+    fn map_pixels(&mut self, f: impl Fn(TGAColor) -> TGAColor) {
+        let bpp = self.bpp as usize;
+        for px in self.data.chunks_exact_mut(bpp) {
+            let mut c = TGAColor::from_bpp(self.bpp);
+            c.bgra[..bpp].copy_from_slice(px);
+            px.copy_from_slice(&f(c).bgra[..bpp]);
+        }
+    }
+
+    /// Composites a straight alpha colour over the pixel at (x, y).
+    pub fn blend(&mut self, x: usize, y: usize, color: TGAColor) -> Result<(), String> {
+        let dst = self
+            .get(x as i32, y as i32)
+            .ok_or_else(|| "Coordinates out of bounds".to_string())?;
+        let src = color.premultiplied();
+        let (dst, dst_alpha) = match (self.bpp, self.alpha) {
+            (4, AlphaMode::Straight) => (dst.premultiplied(), dst.bgra[3]),
+            (4, AlphaMode::Premultiplied) => (dst, dst.bgra[3]),
+            _ => (dst, 255),
+        };
+        let inv = 255 - src.bgra[3] as u32;
+        let mut out = TGAColor::from_bpp(self.bpp);
+        for i in 0..3 {
+            out.bgra[i] = (src.bgra[i] as u32 + (dst.bgra[i] as u32 * inv + 127) / 255) as u8;
+        }
+        out.bgra[3] = (src.bgra[3] as u32 + (dst_alpha as u32 * inv + 127) / 255) as u8;
+        if self.bpp == 4 && self.alpha == AlphaMode::Straight {
+            out = out.unpremultiplied();
+        }
+        self.set(x, y, out)
+    }
+
+    /// Averages the pixels in `x0..x1` x `y0..y1`. Colours are weighted by
+    /// alpha so transparent texels don't darken the edges; the result is in
+    /// the image's alpha mode.
+    pub fn average(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> TGAColor {
+        let mut sum = [0u32; 4];
+        let mut count = 0;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if let Some(c) = self.get(x, y) {
+                    let c = match (self.bpp, self.alpha) {
+                        (4, AlphaMode::Straight) => c.premultiplied(),
+                        (4, _) => c,
+                        _ => TGAColor {
+                            bgra: [c.bgra[0], c.bgra[1], c.bgra[2], 255],
+                            bytespp: self.bpp,
+                        },
+                    };
+                    for (acc, v) in sum.iter_mut().zip(c.bgra) {
+                        *acc += v as u32;
+                    }
+                    count += 1;
+                }
+            }
+        }
+        let mut c = TGAColor::from_bpp(self.bpp);
+        if count == 0 {
+            return c;
+        }
+        for (v, acc) in c.bgra.iter_mut().zip(sum) {
+            *v = ((acc + count / 2) / count) as u8;
+        }
+        if self.bpp == 4 && self.alpha == AlphaMode::Straight {
+            c = c.unpremultiplied();
+        }
+        c
+    }
+
+    /// Copy of a 16-bit (5 bits per channel) image as 24-bit RGB. Other
+    /// images are returned as they are.
+    pub fn to_truecolor(&self) -> TGAImage {
+        if self.bpp != 2 {
+            return self.clone();
+        }
+        let mut out = TGAImage::new(self.w, self.h, Format::RGB);
+        let expand = |v: u16| {
+            let v = (v & 0x1f) as u8;
+            (v << 3) | (v >> 2)
+        };
+        for (dst, src) in out.data.chunks_exact_mut(3).zip(self.data.chunks_exact(2)) {
+            // stored little endian as 1 attribute bit, then r, g and b
+            let v = u16::from_le_bytes([src[0], src[1]]);
+            dst.copy_from_slice(&[expand(v), expand(v >> 5), expand(v >> 10)]);
+        }
+        out
+    }
+
+    /// Box filtered copy of the image at `width` x `height`. 16-bit images
+    /// come out as 24-bit RGB.
+    pub fn resized(&self, width: i32, height: i32) -> TGAImage {
+        if self.bpp == 2 {
+            return self.to_truecolor().resized(width, height);
+        }
+        let mut out = TGAImage::new(width, height, Format::from_bpp(self.bpp).unwrap());
+        out.alpha = self.alpha;
+        for ty in 0..height {
+            let y0 = ty * self.h / height;
+            let y1 = ((ty + 1) * self.h / height).max(y0 + 1);
+            for tx in 0..width {
+                let x0 = tx * self.w / width;
+                let x1 = ((tx + 1) * self.w / width).max(x0 + 1);
+                let _ = out.set(tx as usize, ty as usize, self.average(x0, y0, x1, y1));
+            }
+        }
+        out
+    }
+
+    /// Mip chain starting with a copy of the image, halving down to 1x1.
+    pub fn mipmaps(&self) -> Vec<TGAImage> {
+        let mut levels = vec![self.resized(self.w, self.h)];
+        let (mut w, mut h) = (self.w, self.h);
+        while w > 1 || h > 1 {
+            w = (w / 2).max(1);
+            h = (h / 2).max(1);
+            let next = levels.last().unwrap().resized(w, h);
+            levels.push(next);
+        }
+        levels
+    }
+
     pub fn height(&self) -> i32 {
         self.h
     }
@@ -309,95 +591,252 @@ impl TGAImage {
     }
 
     pub fn unload_rle_data(&self, out: &mut dyn Write) -> io::Result<()> {
-        const MAX_CHUNK_LENGTH: u8 = 128;
-        let npixels = (self.w * self.h) as usize;
-        let mut curpix = 0;
-
-        while curpix < npixels {
-            let chunkstart = curpix * self.bpp as usize;
-            let mut curbyte = curpix * self.bpp as usize;
-            let mut run_length = 1;
-            let mut raw = true;
-
-            while curpix + run_length < npixels && run_length < MAX_CHUNK_LENGTH as usize {
-                let mut succ_eq = true;
-                for t in 0..self.bpp as usize {
-                    if self.data[curbyte + t] != self.data[curbyte + t + self.bpp as usize] {
-                        succ_eq = false;
-                        break;
-                    }
-                }
+        write_rle_data(&self.data, self.bpp as usize, self.w as usize, out)
+    }
 
-                curbyte += self.bpp as usize;
-                if run_length == 1 {
-                    raw = !succ_eq;
-                }
-                if raw && succ_eq {
-                    run_length -= 1;
-                    break;
-                }
-                if !raw && !succ_eq {
+    pub fn write_tga_file(&self, filename: &str, vflip: bool, rle: bool) -> io::Result<()> {
+        let header = TGAHeader::for_image(self.w, self.h, self.bpp, vflip, rle)?;
+        let mut out = File::create(filename).expect("Cant open file");
+        header.write_to(&mut out)?;
+
+        if !rle {
+            out.write_all(&self.data)?;
+        } else {
+            self.unload_rle_data(&mut out)?;
+        }
+
+        let position = out.stream_position()?;
+        write_footer(&mut out, position, self.bpp, self.alpha)
+    }
+}
+
+/// RLE encodes `data` in packets of at most 128 pixels. Runs never cross the
+/// end of `data`, so bands can be encoded independently.
+/// RLE encodes rows of `width` pixels. Packets never cross a row, as the
+/// format asks, so encoding the rows in bands gives the same bytes as
+/// encoding the whole image.
+pub(crate) fn write_rle_data(
+    data: &[u8],
+    bpp: usize,
+    width: usize,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    const MAX_CHUNK_LENGTH: u8 = 128;
+    let npixels = data.len() / bpp;
+    let mut curpix = 0;
+
+    while curpix < npixels {
+        let chunkstart = curpix * bpp;
+        let mut curbyte = curpix * bpp;
+        let mut run_length = 1;
+        let mut raw = true;
+        let row_end = npixels.min((curpix / width + 1) * width);
+
+        while curpix + run_length < row_end && run_length < MAX_CHUNK_LENGTH as usize {
+            let mut succ_eq = true;
+            for t in 0..bpp {
+                if data[curbyte + t] != data[curbyte + t + bpp] {
+                    succ_eq = false;
                     break;
                 }
+            }
 
-                run_length += 1;
+            curbyte += bpp;
+            if run_length == 1 {
+                raw = !succ_eq;
+            }
+            if raw && succ_eq {
+                run_length -= 1;
+                break;
+            }
+            if !raw && !succ_eq {
+                break;
             }
 
-            curpix += run_length;
+            run_length += 1;
+        }
 
-            out.write_all(&[if raw {
-                run_length as u8 - 1
-            } else {
-                run_length as u8 + 127
-            }])?;
+        curpix += run_length;
 
-            if raw {
-                out.write_all(&self.data[chunkstart..chunkstart + run_length * self.bpp as usize])?;
-            } else {
-                out.write_all(&self.data[chunkstart..chunkstart + self.bpp as usize])?;
-            }
-        }
+        out.write_all(&[if raw {
+            run_length as u8 - 1
+        } else {
+            run_length as u8 + 127
+        }])?;
 
-        Ok(())
+        if raw {
+            out.write_all(&data[chunkstart..chunkstart + run_length * bpp])?;
+        } else {
+            out.write_all(&data[chunkstart..chunkstart + bpp])?;
+        }
     }
 
-    pub fn write_tga_file(&self, filename: &str, vflip: bool, rle: bool) -> io::Result<()> {
-        let developer_area_ref: [u8; 4] = [0, 0, 0, 0];
-        let extension_area_ref: [u8; 4] = [0, 0, 0, 0];
-        let footer: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+    Ok(())
+}
 
-        let mut out = File::create(filename).expect("Cant open file");
-        let header = TGAHeader {
-            bits_per_pixel: self.bpp << 3,
-            width: self.width() as u16,
-            height: self.height() as u16,
-            data_type_code: match (self.bpp, rle) {
-                (0, true) => 11,
-                (0, false) => 3,
-                (_, true) => 10,
-                (_, false) => 2,
-            },
-            image_descriptor: if vflip { 0x00 } else { 0x20 },
-            ..Default::default()
+/// Writes the extension area (only for images with alpha) and the TGA 2.0
+/// footer. `position` is the current offset in the file.
+pub(crate) fn write_footer(
+    out: &mut dyn Write,
+    position: u64,
+    bpp: u8,
+    alpha: AlphaMode,
+) -> io::Result<()> {
+    let developer_area_ref: [u8; 4] = [0, 0, 0, 0];
+    let mut extension_area_ref: [u8; 4] = [0, 0, 0, 0];
+
+    if bpp == 4 {
+        extension_area_ref = (position as u32).to_le_bytes();
+        let mut extension = [0u8; EXTENSION_AREA_SIZE];
+        extension[..2].copy_from_slice(&(EXTENSION_AREA_SIZE as u16).to_le_bytes());
+        extension[ATTRIBUTES_TYPE_OFFSET] = match alpha {
+            AlphaMode::Straight => 3,
+            AlphaMode::Premultiplied => 4,
         };
-        let header_bytes = unsafe {
-            std::slice::from_raw_parts(
-                &header as *const _ as *const u8,
-                std::mem::size_of::<TGAHeader>(),
-            )
-        };
-        out.write_all(header_bytes)?;
+        out.write_all(&extension)?;
+    }
 
-        if !rle {
-            out.write_all(&self.data)?;
-        } else {
-            self.unload_rle_data(&mut out)?;
+    out.write_all(&extension_area_ref)?;
+    out.write_all(&developer_area_ref)?;
+    out.write_all(FOOTER_SIGNATURE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(c: TGAColor) -> [u8; 4] {
+        [c[2], c[1], c[0], c[3]]
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}_{}.tga", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn premultiply_round_trip() {
+        for a in [255u8, 200, 128, 17] {
+            for v in [0u8, 1, 64, 127, 200, 255] {
+                let c = TGAColor::from_rgba(v, 255 - v, v / 2, a);
+                let back = c.premultiplied().unpremultiplied();
+                assert_eq!(back.alpha(), a);
+                // a byte of premultiplied colour covers 255 / a straight values
+                let tolerance = (255 / a as i32 + 1) / 2;
+                for i in 0..3 {
+                    let diff = (back[i] as i32 - c[i] as i32).abs();
+                    assert!(
+                        diff <= tolerance,
+                        "{:?} came back as {:?}",
+                        rgba(c),
+                        rgba(back)
+                    );
+                }
+            }
         }
+        // nothing survives at alpha 0, and unpremultiplying does not divide by it
+        let clear = TGAColor::from_rgba(200, 100, 50, 0);
+        assert_eq!(rgba(clear.premultiplied()), [0, 0, 0, 0]);
+        assert_eq!(rgba(clear.unpremultiplied()), [0, 0, 0, 0]);
+
+        let mut image = TGAImage::new(2, 1, Format::RGBA);
+        image
+            .set(0, 0, TGAColor::from_rgba(255, 128, 0, 128))
+            .unwrap();
+        image
+            .set(1, 0, TGAColor::from_rgba(255, 255, 255, 0))
+            .unwrap();
+        image.premultiply();
+        assert_eq!(image.alpha_mode(), AlphaMode::Premultiplied);
+        assert_eq!(rgba(image.get(0, 0).unwrap()), [128, 64, 0, 128]);
+        // a second call leaves the data alone
+        image.premultiply();
+        assert_eq!(rgba(image.get(0, 0).unwrap()), [128, 64, 0, 128]);
+        image.unpremultiply();
+        assert_eq!(image.alpha_mode(), AlphaMode::Straight);
+        assert_eq!(rgba(image.get(0, 0).unwrap()), [255, 128, 0, 128]);
+        assert_eq!(rgba(image.get(1, 0).unwrap()), [0, 0, 0, 0]);
+    }
 
-        out.write_all(&developer_area_ref)?;
-        out.write_all(&extension_area_ref)?;
-        out.write_all(footer)?;
+    #[test]
+    fn blends_over() {
+        let half_red = TGAColor::from_rgba(255, 0, 0, 128);
+
+        let mut opaque = TGAImage::new(1, 1, Format::RGB);
+        opaque.set(0, 0, TGAColor::from_rgb(0, 0, 255)).unwrap();
+        opaque.blend(0, 0, half_red).unwrap();
+        assert_eq!(rgba(opaque.get(0, 0).unwrap())[..3], [128, 0, 127]);
+
+        // over nothing, the colour comes out as it went in
+        let mut clear = TGAImage::new(1, 1, Format::RGBA);
+        clear.blend(0, 0, half_red).unwrap();
+        assert_eq!(rgba(clear.get(0, 0).unwrap()), rgba(half_red));
+
+        // half over half covers three quarters
+        clear
+            .blend(0, 0, TGAColor::from_rgba(0, 0, 255, 128))
+            .unwrap();
+        assert_eq!(rgba(clear.get(0, 0).unwrap()), [85, 0, 170, 192]);
+
+        let mut premultiplied = TGAImage::new(1, 1, Format::RGBA);
+        premultiplied.premultiply();
+        premultiplied.blend(0, 0, half_red).unwrap();
+        assert_eq!(rgba(premultiplied.get(0, 0).unwrap()), [128, 0, 0, 128]);
+        assert!(premultiplied.blend(1, 0, half_red).is_err());
+    }
 
-        Ok(())
+    #[test]
+    fn alpha_mode_round_trips_through_the_extension_area() {
+        for (alpha, attributes_type) in [(AlphaMode::Straight, 3), (AlphaMode::Premultiplied, 4)] {
+            let mut image = TGAImage::new(3, 2, Format::RGBA);
+            image
+                .set(1, 1, TGAColor::from_rgba(10, 20, 30, 40))
+                .unwrap();
+            if alpha == AlphaMode::Premultiplied {
+                image.premultiply();
+            }
+            for rle in [false, true] {
+                let path = temp_path(&format!("tga_alpha_{}_{}", attributes_type, rle));
+                image.write_tga_file(&path, false, rle).unwrap();
+                let bytes = std::fs::read(&path).unwrap();
+                let read = TGAImage::from_tga_file(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+
+                let footer = &bytes[bytes.len() - 26..];
+                assert_eq!(&footer[8..], FOOTER_SIGNATURE);
+                let offset =
+                    u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+                assert_eq!(offset + EXTENSION_AREA_SIZE, bytes.len() - 26);
+                assert_eq!(bytes[offset + ATTRIBUTES_TYPE_OFFSET], attributes_type);
+                assert_eq!(read.alpha_mode(), alpha);
+                assert_eq!(read.data(), image.data());
+            }
+        }
+    }
+
+    #[test]
+    fn mip_chain_ends_at_one_pixel() {
+        let sizes = |w, h| -> Vec<(i32, i32)> {
+            TGAImage::new(w, h, Format::RGB)
+                .mipmaps()
+                .iter()
+                .map(|m| (m.width(), m.height()))
+                .collect()
+        };
+        assert_eq!(sizes(8, 8), [(8, 8), (4, 4), (2, 2), (1, 1)]);
+        assert_eq!(sizes(5, 3), [(5, 3), (2, 1), (1, 1)]);
+        assert_eq!(sizes(1, 4), [(1, 4), (1, 2), (1, 1)]);
+        assert_eq!(sizes(1, 1), [(1, 1)]);
+
+        // each level averages the one before
+        let mut checker = TGAImage::new(2, 2, Format::Grayscale);
+        checker
+            .set(0, 0, TGAColor::from_rgb(200, 200, 200))
+            .unwrap();
+        checker
+            .set(1, 1, TGAColor::from_rgb(200, 200, 200))
+            .unwrap();
+        let levels = checker.mipmaps();
+        assert_eq!(levels[1].get(0, 0).unwrap()[0], 100);
     }
 }
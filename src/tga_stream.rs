@@ -0,0 +1,335 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::tga::{write_footer, write_rle_data, AlphaMode, Format, TGAHeader, TGAImage};
+
+/// Writes a TGA file incrementally, so only one band of rows has to be in
+/// memory at a time. Rows are written in image order: with `vflip` the first
+/// band is the bottom of the picture, as in `TGAImage::write_tga_file`.
+pub struct TGAStreamWriter<W: Write> {
+    out: W,
+    width: i32,
+    height: i32,
+    bpp: u8,
+    rle: bool,
+    alpha: AlphaMode,
+    rows_written: i32,
+    bytes_written: u64,
+    // tiles of the band currently being assembled
+    band: Option<TGAImage>,
+    band_filled: i32,
+    // set when a write failed part way, the file can no longer be finished
+    poisoned: bool,
+}
+
+impl TGAStreamWriter<BufWriter<File>> {
+    pub fn create(
+        filename: &str,
+        width: i32,
+        height: i32,
+        format: Format,
+        vflip: bool,
+        rle: bool,
+    ) -> io::Result<Self> {
+        // check the size before an empty file is left behind
+        TGAHeader::for_image(width, height, format as u8, vflip, rle)?;
+        let file = BufWriter::new(File::create(filename)?);
+        TGAStreamWriter::new(file, width, height, format, vflip, rle)
+    }
+}
+
+impl<W: Write> TGAStreamWriter<W> {
+    pub fn new(
+        mut out: W,
+        width: i32,
+        height: i32,
+        format: Format,
+        vflip: bool,
+        rle: bool,
+    ) -> io::Result<Self> {
+        let bpp = format as u8;
+        TGAHeader::for_image(width, height, bpp, vflip, rle)?.write_to(&mut out)?;
+        Ok(TGAStreamWriter {
+            out,
+            width,
+            height,
+            bpp,
+            rle,
+            alpha: AlphaMode::Straight,
+            rows_written: 0,
+            bytes_written: std::mem::size_of::<TGAHeader>() as u64,
+            band: None,
+            band_filled: 0,
+            poisoned: false,
+        })
+    }
+
+    /// Alpha mode recorded in the extension area of RGBA files.
+    pub fn set_alpha_mode(&mut self, alpha: AlphaMode) {
+        self.alpha = alpha;
+    }
+
+    pub fn rows_written(&self) -> i32 {
+        self.rows_written
+    }
+
+    /// Appends a band of full-width rows. Nothing is written if the band
+    /// does not fit; if writing fails part way, `finish` fails too.
+    pub fn write_band(&mut self, band: &TGAImage) -> io::Result<()> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        if self.band.is_some() {
+            return Err(invalid("previous band of tiles is incomplete"));
+        }
+        if band.width() != self.width || band.bpp != self.bpp {
+            return Err(invalid("band does not match the image width or format"));
+        }
+        if self.rows_written + band.height() > self.height {
+            return Err(invalid("band runs past the last row"));
+        }
+
+        self.write_rows(band).inspect_err(|_| self.poisoned = true)
+    }
+
+    fn write_rows(&mut self, band: &TGAImage) -> io::Result<()> {
+        if self.rle {
+            let mut counter = CountingWriter {
+                inner: &mut self.out,
+                count: 0,
+            };
+            write_rle_data(
+                band.data(),
+                self.bpp as usize,
+                self.width as usize,
+                &mut counter,
+            )?;
+            self.bytes_written += counter.count;
+        } else {
+            self.out.write_all(band.data())?;
+            self.bytes_written += band.data().len() as u64;
+        }
+        self.rows_written += band.height();
+        Ok(())
+    }
+
+    /// Appends a tile at column `x` of the current band. Tiles of a band must
+    /// come left to right and share the height of the first one; the band is
+    /// written out once its last tile arrives. A tile that does not fit is
+    /// rejected before anything of it is stored.
+    pub fn write_tile(&mut self, x: i32, tile: &TGAImage) -> io::Result<()> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        if tile.bpp != self.bpp {
+            return Err(invalid("tile does not match the image format"));
+        }
+        if x != self.band_filled || x + tile.width() > self.width {
+            return Err(invalid("tiles must be written left to right"));
+        }
+        match &self.band {
+            Some(band) if tile.height() != band.height() => {
+                return Err(invalid("tile height differs from the rest of the band"));
+            }
+            None if self.rows_written + tile.height() > self.height => {
+                return Err(invalid("band runs past the last row"));
+            }
+            _ => {}
+        }
+        let band = self.band.get_or_insert_with(|| {
+            TGAImage::new(
+                self.width,
+                tile.height(),
+                Format::from_bpp(self.bpp).unwrap(),
+            )
+        });
+        for y in 0..tile.height() {
+            for tx in 0..tile.width() {
+                if let Some(c) = tile.get(tx, y) {
+                    let _ = band.set((x + tx) as usize, y as usize, c);
+                }
+            }
+        }
+        self.band_filled += tile.width();
+
+        if self.band_filled == self.width {
+            let band = self.band.take().unwrap();
+            self.band_filled = 0;
+            self.write_band(&band)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the footer and returns the underlying writer. Fails if fewer
+    /// rows than the image height were written or an earlier write failed.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        if self.band.is_some() || self.rows_written != self.height {
+            return Err(invalid("not all rows of the image were written"));
+        }
+        write_footer(&mut self.out, self.bytes_written, self.bpp, self.alpha)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.to_string())
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("an earlier write failed, the file is incomplete")
+}
+
+struct CountingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tga::TGAColor;
+
+    #[test]
+    fn rejects_sizes_past_the_header_fields() {
+        let new = |w, h| TGAStreamWriter::new(Vec::new(), w, h, Format::RGB, true, false);
+        assert!(new(u16::MAX as i32, 1).is_ok());
+        for (w, h) in [(70_000, 10), (10, 65_536), (-1, 10)] {
+            let err = new(w, h).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let path = std::env::temp_dir().join(format!("tga_size_{}.tga", std::process::id()));
+        let image = TGAImage::new(70_000, 1, Format::Grayscale);
+        assert!(image
+            .write_tga_file(path.to_str().unwrap(), true, false)
+            .is_err());
+        assert!(!path.exists());
+    }
+
+    /// Rows `y0..y1`, columns `x0..x1` of `image`.
+    fn crop(image: &TGAImage, x0: i32, y0: i32, x1: i32, y1: i32) -> TGAImage {
+        let mut out = TGAImage::new(x1 - x0, y1 - y0, Format::from_bpp(image.bpp).unwrap());
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let c = image.get(x, y).unwrap();
+                out.set((x - x0) as usize, (y - y0) as usize, c).unwrap();
+            }
+        }
+        out
+    }
+
+    /// Runs of one colour that wrap from row to row, then noise.
+    fn pattern(format: Format) -> TGAImage {
+        let mut image = TGAImage::new(11, 7, format);
+        for y in 0..7 {
+            for x in 0..11 {
+                let v = if y < 3 { 40 } else { (x * 37 + y * 11) as u8 };
+                let c = TGAColor::from_rgba(v, v / 2, 255 - v, v | 1);
+                image.set(x as usize, y as usize, c).unwrap();
+            }
+        }
+        image
+    }
+
+    fn file_bytes(image: &TGAImage, vflip: bool, rle: bool) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "tga_stream_{}_{}_{}_{}.tga",
+            image.bpp,
+            vflip,
+            rle,
+            std::process::id()
+        ));
+        image
+            .write_tga_file(path.to_str().unwrap(), vflip, rle)
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn streams_match_whole_image_files() {
+        for format in [Format::Grayscale, Format::RGB, Format::RGBA] {
+            let image = pattern(format);
+            for vflip in [false, true] {
+                for rle in [false, true] {
+                    let expected = file_bytes(&image, vflip, rle);
+                    let new = || TGAStreamWriter::new(Vec::new(), 11, 7, format, vflip, rle);
+
+                    let mut bands = new().unwrap();
+                    for (y0, y1) in [(0, 2), (2, 5), (5, 7)] {
+                        bands.write_band(&crop(&image, 0, y0, 11, y1)).unwrap();
+                    }
+                    assert_eq!(bands.finish().unwrap(), expected, "{:?} bands", format);
+
+                    let mut tiles = new().unwrap();
+                    for (y0, y1) in [(0, 4), (4, 7)] {
+                        for (x0, x1) in [(0, 3), (3, 8), (8, 11)] {
+                            tiles.write_tile(x0, &crop(&image, x0, y0, x1, y1)).unwrap();
+                        }
+                    }
+                    assert_eq!(tiles.finish().unwrap(), expected, "{:?} tiles", format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_writes_out_of_order() {
+        let image = pattern(Format::RGB);
+        let new = || TGAStreamWriter::new(Vec::new(), 11, 7, Format::RGB, false, false).unwrap();
+        fn kind<T>(result: io::Result<T>) -> io::ErrorKind {
+            result.err().unwrap().kind()
+        }
+
+        // a band while the tiles of another are incomplete
+        let mut stream = new();
+        stream.write_tile(0, &crop(&image, 0, 0, 5, 2)).unwrap();
+        assert_eq!(
+            kind(stream.write_band(&crop(&image, 0, 0, 11, 2))),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(kind(stream.finish()), io::ErrorKind::InvalidInput);
+
+        // tiles skipping a column, going back, or of another height
+        let mut stream = new();
+        let tile = crop(&image, 0, 0, 5, 2);
+        assert_eq!(
+            kind(stream.write_tile(5, &tile)),
+            io::ErrorKind::InvalidInput
+        );
+        stream.write_tile(0, &tile).unwrap();
+        assert_eq!(
+            kind(stream.write_tile(0, &tile)),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(stream.write_tile(5, &crop(&image, 5, 0, 11, 3))),
+            io::ErrorKind::InvalidInput
+        );
+
+        // rows missing at the end
+        let mut stream = new();
+        stream.write_band(&crop(&image, 0, 0, 11, 5)).unwrap();
+        assert_eq!(stream.rows_written(), 5);
+        assert_eq!(kind(stream.finish()), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::geometry::{Matrix, Vec3f, Vec4f};
+use crate::model::Model;
+
+impl Model {
+    /// Applies a 4x4 transform to every position. Normals go through the
+    /// inverse-transpose and are renormalised, tangents through the matrix
+    /// itself. A mirroring transform also reverses the winding of every
+    /// face and the tangent handedness, so faces keep pointing outwards.
+    pub fn transform(&mut self, m: &Matrix) {
+        for v in &mut self.verts {
+            *v = m.transform_point(*v);
+        }
+        // a singular matrix flattens the model, the old normals are as good as any
+        if let Some(normal_matrix) = m.inverse().map(|inv| inv.transpose()) {
+            for n in &mut self.norms {
+                *n = normal_matrix.transform_vector(*n).normalized();
+            }
+        }
+        let mirrored = m.determinant3() < 0.0;
+        for frame in &mut self.tangents {
+            for t in frame.iter_mut() {
+                let w = if mirrored { -t.w } else { t.w };
+                *t = Vec4f::from_vec3(m.transform_vector(t.xyz()).normalized(), w);
+            }
+        }
+        if mirrored {
+            for face in &mut self.faces {
+                face.swap(1, 2);
+            }
+            for frame in &mut self.tangents {
+                frame.swap(1, 2);
+            }
+        }
+    }
+
+    pub fn translate(&mut self, offset: Vec3f) {
+        self.transform(&Matrix::translation(offset));
+    }
+
+    /// Rotates by `degrees` around `axis` through the origin, e.g. -90
+    /// around x turns a Z-up asset Y-up.
+    pub fn rotate(&mut self, axis: Vec3f, degrees: f32) {
+        self.transform(&Matrix::rotation(axis, degrees));
+    }
+
+    /// Scales along each axis about the origin. A negative factor mirrors
+    /// the model, e.g. to swap handedness.
+    pub fn scale(&mut self, factors: Vec3f) {
+        self.transform(&Matrix::scaling(factors));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec3i;
+
+    /// Triangle on the plane x + y = 1 with a normal at every corner.
+    fn slanted() -> Model {
+        let verts = vec![
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 1.0, 1.0),
+        ];
+        let norms = vec![Vec3f::new(1.0, 1.0, 0.0).normalized()];
+        let faces = vec![(0..3).map(|v| Vec3i::new(v, -1, 0)).collect()];
+        Model::from_triangles(verts, norms, Vec::new(), faces)
+    }
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    #[test]
+    fn mirroring_swaps_the_winding() {
+        let mut model = slanted();
+        let before = model.face_normal(0);
+        model.scale(Vec3f::new(-1.0, 1.0, 1.0));
+        let face: Vec<i32> = model.face(0).iter().map(|c| c.x).collect();
+        assert_eq!(face, [0, 2, 1]);
+        // still facing the mirrored normal, not away from it
+        let mirrored = Vec3f::new(-before.x, before.y, before.z);
+        assert!(close(model.face_normal(0), mirrored));
+        assert!(close(model.norm(0, 0), mirrored));
+
+        // a rotation is not a mirror
+        let mut model = slanted();
+        model.rotate(Vec3f::new(0.0, 0.0, 1.0), 90.0);
+        let face: Vec<i32> = model.face(0).iter().map(|c| c.x).collect();
+        assert_eq!(face, [0, 1, 2]);
+    }
+
+    #[test]
+    fn normals_follow_non_uniform_scale() {
+        let mut model = slanted();
+        model.scale(Vec3f::new(2.0, 1.0, 1.0));
+        // now on x / 2 + y = 1
+        let expected = Vec3f::new(0.5, 1.0, 0.0).normalized();
+        assert!(close(model.norm(0, 0), expected));
+        assert!(close(model.face_normal(0), expected));
+
+        // translation leaves normals alone
+        model.translate(Vec3f::new(5.0, -1.0, 2.0));
+        assert!(close(model.norm(0, 0), expected));
+        assert!(close(model.vert(0), Vec3f::new(7.0, -1.0, 2.0)));
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use crate::geometry::{Vec3f, Vec3i};
+use crate::model::Model;
+
+/// Problems found by `Model::validate`, as the faces, vertices or edges
+/// affected. Edges are pairs of position indices, smaller first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshReport {
+    /// Faces with a position, texture or normal index out of range.
+    pub invalid_indices: Vec<usize>,
+    /// Positions with a NaN or infinite coordinate.
+    pub non_finite_vertices: Vec<usize>,
+    /// Faces using one position for two corners.
+    pub degenerate_faces: Vec<usize>,
+    /// Faces with three distinct positions but no area.
+    pub zero_area_faces: Vec<usize>,
+    /// Faces over the same three positions as an earlier face.
+    pub duplicate_faces: Vec<usize>,
+    /// Edges shared by more than two faces.
+    pub non_manifold_edges: Vec<(usize, usize)>,
+    /// Vertices where separate fans of faces touch.
+    pub non_manifold_vertices: Vec<usize>,
+    /// Edges whose two faces both run along them in the same direction.
+    pub inconsistent_edges: Vec<(usize, usize)>,
+    /// Faces whose corner normals point against their winding.
+    pub flipped_normals: Vec<usize>,
+    /// Holes in the surface, 0 for a closed mesh. The loops that the extra
+    /// faces of a non-manifold edge leave along it are not holes and are
+    /// not counted.
+    pub boundary_loops: usize,
+}
+
+impl MeshReport {
+    /// True if nothing but holes was found.
+    pub fn is_clean(&self) -> bool {
+        self.problems().iter().all(|(count, _, _)| *count == 0)
+    }
+
+    /// Count, description and the first few affected items of every check.
+    fn problems(&self) -> [(usize, &'static str, String); 9] {
+        fn first<T: fmt::Debug>(items: &[T]) -> String {
+            let shown: Vec<String> = items.iter().take(5).map(|i| format!("{:?}", i)).collect();
+            let more = if items.len() > 5 { ", ..." } else { "" };
+            format!("{}{}", shown.join(", "), more)
+        }
+        [
+            (
+                self.invalid_indices.len(),
+                "faces with out of range indices",
+                first(&self.invalid_indices),
+            ),
+            (
+                self.non_finite_vertices.len(),
+                "vertices with NaN or infinite coordinates",
+                first(&self.non_finite_vertices),
+            ),
+            (
+                self.degenerate_faces.len(),
+                "degenerate faces",
+                first(&self.degenerate_faces),
+            ),
+            (
+                self.zero_area_faces.len(),
+                "zero area faces",
+                first(&self.zero_area_faces),
+            ),
+            (
+                self.duplicate_faces.len(),
+                "duplicate faces",
+                first(&self.duplicate_faces),
+            ),
+            (
+                self.non_manifold_edges.len(),
+                "non-manifold edges",
+                first(&self.non_manifold_edges),
+            ),
+            (
+                self.non_manifold_vertices.len(),
+                "non-manifold vertices",
+                first(&self.non_manifold_vertices),
+            ),
+            (
+                self.inconsistent_edges.len(),
+                "edges with inconsistent winding",
+                first(&self.inconsistent_edges),
+            ),
+            (
+                self.flipped_normals.len(),
+                "faces with flipped normals",
+                first(&self.flipped_normals),
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for MeshReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (count, what, examples) in self.problems() {
+            if count > 0 {
+                writeln!(f, "{} {} ({})", count, what, examples)?;
+            }
+        }
+        if self.is_clean() {
+            writeln!(f, "no problems found")?;
+        }
+        match self.boundary_loops {
+            0 => write!(f, "closed surface"),
+            n => write!(f, "{} boundary loops", n),
+        }
+    }
+}
+
+/// What `Model::repair` changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Repairs {
+    /// Faces with out of range indices or non-finite corners.
+    pub removed_invalid: usize,
+    /// Degenerate and zero area faces.
+    pub removed_degenerate: usize,
+    pub removed_duplicates: usize,
+    /// Faces whose winding was reversed to match their neighbours.
+    pub reoriented: usize,
+}
+
+impl fmt::Display for Repairs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "removed {} invalid, {} degenerate and {} duplicate faces, reoriented {} faces",
+            self.removed_invalid, self.removed_degenerate, self.removed_duplicates, self.reoriented
+        )
+    }
+}
+
+fn edge(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+impl Model {
+    /// Checks the faces for problems that trip up rendering and the mesh
+    /// algorithms. Topology checks skip faces that are invalid, degenerate
+    /// or duplicates, so one bad face is not reported several times.
+    pub fn validate(&self) -> MeshReport {
+        let mut report = MeshReport {
+            non_finite_vertices: (0..self.verts.len())
+                .filter(|&v| {
+                    let p = self.verts[v];
+                    !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+                })
+                .collect(),
+            ..MeshReport::default()
+        };
+        let in_range = |index: i32, len: usize| index < 0 || (index as usize) < len;
+        let mut usable = vec![false; self.faces.len()];
+        let mut seen: HashSet<[i32; 3]> = HashSet::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let valid = face.len() == 3
+                && face.iter().all(|c| {
+                    c.x >= 0
+                        && in_range(c.x, self.verts.len())
+                        && in_range(c.y, self.uv.len())
+                        && in_range(c.z, self.norms.len())
+                });
+            if !valid {
+                report.invalid_indices.push(f);
+                continue;
+            }
+            let [a, b, c] = [face[0].x, face[1].x, face[2].x];
+            if a == b || b == c || a == c {
+                report.degenerate_faces.push(f);
+                continue;
+            }
+            // already reported with the vertices
+            let non_finite = [a, b, c].iter().any(|&i| {
+                report
+                    .non_finite_vertices
+                    .binary_search(&(i as usize))
+                    .is_ok()
+            });
+            if non_finite {
+                continue;
+            }
+            let p = [a, b, c].map(|i| self.verts[i as usize]);
+            let cross = Vec3f::triangle_normal(p[0], p[1], p[2]);
+            let longest = [p[1] - p[0], p[2] - p[1], p[0] - p[2]]
+                .iter()
+                .map(|e| *e * *e)
+                .fold(0.0f32, f32::max);
+            if cross.norm() <= f32::EPSILON * longest {
+                report.zero_area_faces.push(f);
+                continue;
+            }
+            let mut key = [a, b, c];
+            key.sort_unstable();
+            if !seen.insert(key) {
+                report.duplicate_faces.push(f);
+                continue;
+            }
+            usable[f] = true;
+            if self.face_info[f].attributes.normal {
+                let n = face.iter().fold(Vec3f::new(0.0, 0.0, 0.0), |acc, c| {
+                    acc + self.norms[c.z as usize]
+                });
+                if n * cross < 0.0 {
+                    report.flipped_normals.push(f);
+                }
+            }
+        }
+
+        // directions every edge is used in, counted from its smaller vertex
+        let mut uses: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+        for f in (0..self.faces.len()).filter(|&f| usable[f]) {
+            for k in 0..3 {
+                let (a, b) = (
+                    self.faces[f][k].x as usize,
+                    self.faces[f][(k + 1) % 3].x as usize,
+                );
+                let count = uses.entry(edge(a, b)).or_default();
+                if a < b {
+                    count.0 += 1;
+                } else {
+                    count.1 += 1;
+                }
+            }
+        }
+        for (&e, &(forward, backward)) in &uses {
+            if forward + backward > 2 {
+                report.non_manifold_edges.push(e);
+            } else if forward == 2 || backward == 2 {
+                report.inconsistent_edges.push(e);
+            }
+        }
+        report.non_manifold_edges.sort_unstable();
+        report.inconsistent_edges.sort_unstable();
+
+        let mut around: Vec<Vec<usize>> = vec![Vec::new(); self.verts.len()];
+        for f in (0..self.faces.len()).filter(|&f| usable[f]) {
+            for c in &self.faces[f] {
+                around[c.x as usize].push(f);
+            }
+        }
+        report.non_manifold_vertices = (0..self.verts.len())
+            .filter(|&v| self.fan_count(&around[v], v) > 1)
+            .collect();
+        let faces: Vec<Vec<Vec3i>> = (0..self.faces.len())
+            .filter(|&f| usable[f])
+            .map(|f| self.faces[f].clone())
+            .collect();
+        let mesh = Model::from_triangles(self.verts.clone(), Vec::new(), Vec::new(), faces)
+            .to_half_edges();
+        let non_manifold = mesh.non_manifold_edges();
+        report.boundary_loops = mesh
+            .boundary_loops()
+            .iter()
+            .filter(|vertices| {
+                let n = vertices.len();
+                (0..n).all(|k| !non_manifold.contains(&edge(vertices[k], vertices[(k + 1) % n])))
+            })
+            .count();
+        report
+    }
+
+    /// Number of groups the faces around `v` fall into, joining faces that
+    /// share an edge at `v` whichever way they run along it. More than one
+    /// means separate surfaces touch at `v`.
+    fn fan_count(&self, around: &[usize], v: usize) -> usize {
+        let others = |f: usize| {
+            self.faces[f]
+                .iter()
+                .map(|c| c.x as usize)
+                .filter(move |&w| w != v)
+        };
+        let mut seen = vec![false; around.len()];
+        let mut fans = 0;
+        for start in 0..around.len() {
+            if seen[start] {
+                continue;
+            }
+            fans += 1;
+            seen[start] = true;
+            let mut stack = vec![start];
+            while let Some(i) = stack.pop() {
+                for j in 0..around.len() {
+                    if !seen[j] && others(around[i]).any(|w| others(around[j]).any(|x| x == w)) {
+                        seen[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+        fans
+    }
+
+    /// Drops invalid, degenerate, zero area and duplicate faces, then turns
+    /// faces so neighbours agree on the winding. Each connected patch keeps
+    /// the winding most of its faces had. A face that is turned shows its
+    /// other side, so back-face culling draws it where it was culled before
+    /// and the other way round. Non-manifold geometry and flipped normals
+    /// are left for the artist.
+    pub fn repair(&mut self) -> Repairs {
+        let report = self.validate();
+        let mut keep = vec![true; self.faces.len()];
+        let mut repairs = Repairs::default();
+        for &f in &report.invalid_indices {
+            keep[f] = false;
+            repairs.removed_invalid += 1;
+        }
+        for (f, face) in self.faces.iter().enumerate() {
+            let non_finite = keep[f]
+                && face.iter().any(|c| {
+                    report
+                        .non_finite_vertices
+                        .binary_search(&(c.x as usize))
+                        .is_ok()
+                });
+            if non_finite {
+                keep[f] = false;
+                repairs.removed_invalid += 1;
+            }
+        }
+        for &f in report
+            .degenerate_faces
+            .iter()
+            .chain(&report.zero_area_faces)
+        {
+            keep[f] = false;
+            repairs.removed_degenerate += 1;
+        }
+        for &f in &report.duplicate_faces {
+            keep[f] = false;
+            repairs.removed_duplicates += 1;
+        }
+        self.retain_faces(&keep);
+        repairs.reoriented = self.orient_faces();
+        self.remove_unused();
+        repairs
+    }
+
+    /// Flips faces to agree with their neighbours across manifold edges.
+    /// Returns the number of faces flipped.
+    fn orient_faces(&mut self) -> usize {
+        let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (face[k].x as usize, face[(k + 1) % 3].x as usize);
+                edge_faces.entry(edge(a, b)).or_default().push(f);
+            }
+        }
+        // whether the face runs from a to b
+        let runs = |face: &[Vec3i], a: usize, b: usize| {
+            (0..3).any(|k| face[k].x as usize == a && face[(k + 1) % 3].x as usize == b)
+        };
+
+        let mut flip = vec![false; self.faces.len()];
+        let mut visited = vec![false; self.faces.len()];
+        for seed in 0..self.faces.len() {
+            if visited[seed] {
+                continue;
+            }
+            visited[seed] = true;
+            let mut patch = vec![seed];
+            let mut queue = VecDeque::from([seed]);
+            while let Some(f) = queue.pop_front() {
+                for k in 0..3 {
+                    let (a, b) = (
+                        self.faces[f][k].x as usize,
+                        self.faces[f][(k + 1) % 3].x as usize,
+                    );
+                    let sides = &edge_faces[&edge(a, b)];
+                    let [g, h] = sides[..] else {
+                        continue;
+                    };
+                    let other = if g == f { h } else { g };
+                    if visited[other] {
+                        continue;
+                    }
+                    visited[other] = true;
+                    // as oriented so far f runs a to b, the neighbour must run b to a
+                    let f_forward = runs(&self.faces[f], a, b) != flip[f];
+                    let other_forward = runs(&self.faces[other], a, b);
+                    flip[other] = other_forward == f_forward;
+                    patch.push(other);
+                    queue.push_back(other);
+                }
+            }
+            let flipped = patch.iter().filter(|&&f| flip[f]).count();
+            if 2 * flipped > patch.len() {
+                for &f in &patch {
+                    flip[f] = !flip[f];
+                }
+            }
+        }
+
+        let mut count = 0;
+        for f in (0..self.faces.len()).filter(|&f| flip[f]) {
+            self.faces[f].swap(1, 2);
+            if let Some(frame) = self.tangents.get_mut(f) {
+                frame.swap(1, 2);
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(verts: &[[f32; 3]], faces: &[[i32; 3]]) -> Model {
+        let verts = verts.iter().map(|&[x, y, z]| Vec3f::new(x, y, z)).collect();
+        let faces = faces
+            .iter()
+            .map(|f| f.iter().map(|&v| Vec3i::new(v, -1, -1)).collect())
+            .collect();
+        Model::from_triangles(verts, Vec::new(), Vec::new(), faces)
+    }
+
+    /// Octahedron wound outwards, or with its first face turned over.
+    fn octahedron(flipped: bool) -> Model {
+        let verts = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        let mut faces = Vec::new();
+        for (a, b) in [(0, 2), (2, 1), (1, 3), (3, 0)] {
+            faces.push([a, b, 4]);
+            faces.push([b, a, 5]);
+        }
+        if flipped {
+            faces[0].swap(1, 2);
+        }
+        model(&verts, &faces)
+    }
+
+    #[test]
+    fn closed_mesh_is_clean() {
+        let report = octahedron(false).validate();
+        assert!(report.is_clean());
+        assert_eq!(report.boundary_loops, 0);
+    }
+
+    #[test]
+    fn finds_and_turns_a_flipped_face() {
+        let mut model = octahedron(true);
+        let report = model.validate();
+        assert!(!report.is_clean());
+        assert_eq!(report.inconsistent_edges, [(0, 2), (0, 4), (2, 4)]);
+        assert!(report.non_manifold_edges.is_empty());
+
+        let repairs = model.repair();
+        assert_eq!(repairs.reoriented, 1);
+        assert_eq!(repairs.removed_invalid + repairs.removed_degenerate, 0);
+        assert_eq!(model.face(0), octahedron(false).face(0));
+        assert!(model.validate().is_clean());
+    }
+
+    #[test]
+    fn repair_drops_broken_faces() {
+        let verts = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [2.0, 0.0, 0.0],
+        ];
+        let mut model = model(
+            &verts,
+            &[[0, 1, 2], [0, 0, 2], [2, 1, 0], [0, 1, 3], [0, 1, 9]],
+        );
+        let report = model.validate();
+        assert_eq!(report.invalid_indices, [4]);
+        assert_eq!(report.degenerate_faces, [1]);
+        assert_eq!(report.zero_area_faces, [3]);
+        assert_eq!(report.duplicate_faces, [2]);
+
+        let repairs = model.repair();
+        assert_eq!(repairs.removed_invalid, 1);
+        assert_eq!(repairs.removed_degenerate, 2);
+        assert_eq!(repairs.removed_duplicates, 1);
+        assert_eq!(model.nfaces(), 1);
+        assert_eq!(model.nverts(), 3);
+        let report = model.validate();
+        assert!(report.is_clean());
+        assert_eq!(report.boundary_loops, 1);
+    }
+
+    #[test]
+    fn fin_has_one_hole() {
+        let verts = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let report = model(&verts, &[[0, 1, 2], [1, 0, 3], [0, 1, 4]]).validate();
+        assert_eq!(report.non_manifold_edges, [(0, 1)]);
+        assert_eq!(report.boundary_loops, 1);
+    }
+}